@@ -0,0 +1,87 @@
+//! LeNet-style convolutional network classifying synthetic 8x8 images of
+//! horizontal bars, vertical bars & diagonals.
+//!
+//! Run with: `cargo run --release --example lenet`
+use neural_network_from_scratch::conv::Conv2d;
+use neural_network_from_scratch::loss::cross_entropy;
+use neural_network_from_scratch::model::Model;
+use neural_network_from_scratch::pool::MaxPool2d;
+use neural_network_from_scratch::scalar::{RcScalar, Scalar};
+use neural_network_from_scratch::sequential::Sequential;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SIZE: usize = 8;
+
+fn image(class: usize, rng: &mut StdRng) -> Vec<f32> {
+    let mut pixels: Vec<f32> = (0..SIZE * SIZE)
+        .map(|_| rng.gen_range(0f32..0.2f32))
+        .collect();
+    let offset = rng.gen_range(0..SIZE);
+    for i in 0..SIZE {
+        let (y, x) = match class {
+            0 => (offset, i),
+            1 => (i, offset),
+            _ => (i, (i + offset) % SIZE),
+        };
+        pixels[y * SIZE + x] = 1f32;
+    }
+    pixels
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(42);
+    let train: Vec<(Vec<f32>, usize)> = (0..90).map(|i| (image(i % 3, &mut rng), i % 3)).collect();
+    let test: Vec<(Vec<f32>, usize)> = (0..30).map(|i| (image(i % 3, &mut rng), i % 3)).collect();
+
+    // 1x8x8 -> 4x8x8 -> 4x4x4 -> 8x4x4 -> 8x2x2 -> 32 -> 16 -> 3
+    let model_a = Model::from(
+        Sequential::new()
+            .push(Conv2d::new(1, 4, 3, (8, 8)).padding(1))
+            .relu()
+            .push(MaxPool2d::new(4, (8, 8), 2))
+            .push(Conv2d::new(4, 8, 3, (4, 4)).padding(1))
+            .relu()
+            .push(MaxPool2d::new(8, (4, 4), 2))
+            .flatten()
+            .dense(32, 16)
+            .relu()
+            .dense(16, 3),
+    );
+
+    for epoch in 0..15 {
+        let mut epoch_loss = 0f32;
+        for batch in train.chunks(10) {
+            let mut loss: RcScalar = RcScalar::new(Scalar::new(0f32));
+            for (pixels, class) in batch {
+                loss =
+                    loss + cross_entropy(&model_a.feed_foward(RcScalar::vec_from(pixels)), *class);
+            }
+            loss = loss * (1f32 / batch.len() as f32);
+
+            for p in model_a.parameters() {
+                p.0.borrow_mut().grad = 0f32;
+            }
+
+            loss.backwards();
+
+            for p in model_a.parameters() {
+                let mut borrowed = p.0.borrow_mut();
+                borrowed.data -= 0.1 * borrowed.grad;
+            }
+            epoch_loss += loss.0.borrow().data;
+        }
+
+        let correct = test
+            .iter()
+            .filter(|(pixels, class)| model_a.predict_class(RcScalar::vec_from(pixels)) == *class)
+            .count();
+        println!(
+            "Epoch: {}, loss: {}, test accuracy: {}/{}",
+            epoch,
+            epoch_loss / (train.len() / 10) as f32,
+            correct,
+            test.len()
+        );
+    }
+}
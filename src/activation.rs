@@ -0,0 +1,63 @@
+use crate::module::Module;
+use crate::scalar::RcScalar;
+use serde::{Deserialize, Serialize};
+
+/// Element-wise non-linearity. Used inside a `Neuron`, or on its own as a module.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    Linear,
+    Tanh,
+    Relu,
+    Sigmoid,
+}
+
+impl Activation {
+    pub fn apply(&self, x: RcScalar) -> RcScalar {
+        match self {
+            Activation::Linear => x,
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.relu(),
+            Activation::Sigmoid => x.sigmoid(),
+        }
+    }
+}
+
+impl Module for Activation {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        input.into_iter().map(|x| self.apply(x)).collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+
+    #[test]
+    fn test_feed_forward() {
+        let x: Vec<RcScalar> = vec![
+            RcScalar::new(Scalar::new(-2f32)),
+            RcScalar::new(Scalar::new(3f32)),
+        ];
+
+        let output: Vec<RcScalar> = Activation::Relu.feed_foward(x);
+
+        assert_eq!(output[0].0.borrow().data, 0.0);
+        assert_eq!(output[1].0.borrow().data, 3.0);
+        assert_eq!(Activation::Relu.parameters().len(), 0);
+        assert_eq!(Activation::Relu.name(), "Relu");
+    }
+}
@@ -0,0 +1,233 @@
+use crate::scalar::{Ops, Scalar};
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+    // Full names of the modules currently running, innermost last
+    static SCOPES: RefCell<Vec<Rc<str>>> = const { RefCell::new(Vec::new()) };
+    static LAST: RefCell<Option<Anomaly>> = const { RefCell::new(None) };
+}
+
+/// Whether the anomaly was found computing a value or propagating a gradient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Forward,
+    Backward,
+}
+
+/// A non-finite value caught in anomaly mode, see `detect_anomaly`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    pub phase: Phase,
+    pub op: Ops,
+    /// uid of the node whose forward or backward produced the value.
+    pub uid: usize,
+    pub data: f32,
+    /// Gradient flowing into the node, for backward anomalies.
+    pub grad: f32,
+    /// Values of the node's inputs.
+    pub inputs: Vec<f32>,
+    /// The module the node was created in, e.g. `encoder (Sequential(2))/0 (Dense(3, 4, Tanh))`.
+    pub site: Option<String>,
+}
+
+impl Anomaly {
+    fn new(phase: Phase, scalar: &Scalar) -> Self {
+        Anomaly {
+            phase,
+            op: scalar.ops.clone(),
+            uid: scalar.uid,
+            data: scalar.data,
+            grad: scalar.grad,
+            inputs: scalar.prev.iter().map(|p| p.0.borrow().data).collect(),
+            site: scalar.site.as_deref().map(str::to_string),
+        }
+    }
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let site = self.site.as_deref().unwrap_or("outside any module");
+        match self.phase {
+            Phase::Forward => write!(
+                f,
+                "{:?} (node {}, created in {}) returned {} for inputs {:?}",
+                self.op, self.uid, site, self.data, self.inputs
+            ),
+            Phase::Backward => write!(
+                f,
+                "backward of {:?} (node {}, created in {}) produced a non-finite gradient from \
+                 grad {}, value {} & inputs {:?}",
+                self.op, self.uid, site, self.grad, self.data, self.inputs
+            ),
+        }
+    }
+}
+
+impl Error for Anomaly {}
+
+/// Turns anomaly mode on or off for the current thread.
+///
+/// In anomaly mode every operation checks its result, & every `Scalar::backward` the gradients
+/// it propagates, panicking with an `Anomaly` on the first non-finite value. Nodes also record
+/// the module they were created in. This slows everything down, so it is off by default.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|e| e.set(enabled));
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(|e| e.get())
+}
+
+/// Runs `f` in anomaly mode, returning the first anomaly as an error of kind `InvalidData`,
+/// with the `Anomaly` itself as the inner error. Other panics are passed on.
+///
+/// The anomaly is still raised as a panic inside `f`, so the panic hook runs as usual: the
+/// default one prints `anomaly detected: ...` to stderr even though the error is returned.
+///
+/// ```
+/// # use neural_network_from_scratch::anomaly::detect_anomaly;
+/// # use neural_network_from_scratch::scalar::{RcScalar, Scalar};
+/// let x = RcScalar::new(Scalar::new(0f32));
+/// let error = detect_anomaly(|| x.ln()).unwrap_err();
+/// assert!(error.to_string().starts_with("Log"));
+/// ```
+pub fn detect_anomaly<T, F: FnOnce() -> T>(f: F) -> io::Result<T> {
+    let enabled = is_enabled();
+    set_enabled(true);
+    // Left over if an anomaly's panic was caught by something else
+    LAST.with(|last| last.borrow_mut().take());
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    set_enabled(enabled);
+    result.map_err(|payload| match LAST.with(|last| last.borrow_mut().take()) {
+        Some(anomaly) => io::Error::new(io::ErrorKind::InvalidData, anomaly),
+        None => panic::resume_unwind(payload),
+    })
+}
+
+/// Marks the nodes created until it is dropped as coming from module `name`, see `scope`.
+pub struct Scope(());
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        SCOPES.with(|scopes| scopes.borrow_mut().pop());
+    }
+}
+
+/// Enters module `name()` in anomaly mode, nested in the module entered before; `None`, without
+/// calling `name`, otherwise. Containers call it around each of their modules.
+pub fn scope<F: FnOnce() -> String>(name: F) -> Option<Scope> {
+    if !is_enabled() {
+        return None;
+    }
+    SCOPES.with(|scopes| {
+        let mut scopes = scopes.borrow_mut();
+        let site = match scopes.last() {
+            Some(parent) => format!("{}/{}", parent, name()),
+            None => name(),
+        };
+        scopes.push(Rc::from(site));
+    });
+    Some(Scope(()))
+}
+
+/// The module new nodes are created in, if in anomaly mode.
+pub(crate) fn site() -> Option<Rc<str>> {
+    if !is_enabled() {
+        return None;
+    }
+    SCOPES.with(|scopes| scopes.borrow().last().cloned())
+}
+
+/// Panics with the anomaly found at `scalar`, keeping it for `detect_anomaly`.
+pub(crate) fn raise(phase: Phase, scalar: &Scalar) -> ! {
+    let anomaly = Anomaly::new(phase, scalar);
+    let message = anomaly.to_string();
+    LAST.with(|last| *last.borrow_mut() = Some(anomaly));
+    panic!("anomaly detected: {}", message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::scalar::RcScalar;
+    use crate::sequential::Sequential;
+
+    fn anomaly(error: io::Error) -> Anomaly {
+        error
+            .into_inner()
+            .unwrap()
+            .downcast_ref::<Anomaly>()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn test_forward() {
+        let model_a = Model::from(
+            Sequential::new()
+                .dense(1, 1)
+                .push_named("head", Sequential::new().dense(1, 1)),
+        );
+        model_a.parameters()[0].0.borrow_mut().data = f32::INFINITY;
+
+        // Only detected in anomaly mode
+        let x = || vec![RcScalar::new(Scalar::new(-1f32))];
+        assert!(!model_a.feed_foward(x())[0].0.borrow().data.is_finite());
+        let found = anomaly(detect_anomaly(|| model_a.feed_foward(x())).unwrap_err());
+
+        assert!(!is_enabled());
+        assert_eq!(found.phase, Phase::Forward);
+        assert_eq!(found.op, Ops::Mul);
+        assert_eq!(found.inputs, vec![f32::INFINITY, -1f32]);
+        assert_eq!(found.site.as_deref(), Some("0 (Dense(1, 1, Linear))"));
+    }
+
+    #[test]
+    fn test_backward() {
+        let model_a =
+            Model::from(Sequential::new().push_named("head", Sequential::new().dense(1, 1)));
+        model_a.parameters()[0].0.borrow_mut().data = 1e30;
+
+        // Every value is finite, the input's gradient is not
+        let found = anomaly(
+            detect_anomaly(|| {
+                let x = RcScalar::new(Scalar::new(1e-30));
+                let y = model_a.feed_foward(vec![x]);
+                (RcScalar::clone(&y[0]) * 1e30).backwards();
+            })
+            .unwrap_err(),
+        );
+        assert_eq!(found.phase, Phase::Backward);
+        assert_eq!(found.op, Ops::Mul);
+        assert_eq!((found.grad, found.inputs), (1e30, vec![1e30, 1e-30]));
+        assert_eq!(
+            found.site.as_deref(),
+            Some("head (Sequential(1))/0 (Dense(1, 1, Linear))")
+        );
+
+        let found = anomaly(
+            detect_anomaly(|| RcScalar::new(Scalar::new(0f32)).powf(0.5).backwards()).unwrap_err(),
+        );
+        assert_eq!(found.op, Ops::Pow(0.5));
+        assert_eq!(found.site, None);
+    }
+
+    #[test]
+    fn test_other_panic() {
+        // An anomaly caught outside `detect_anomaly` is not blamed for a later panic
+        set_enabled(true);
+        let x = RcScalar::new(Scalar::new(0f32));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| x.ln())).is_err());
+        set_enabled(false);
+
+        let payload = panic::catch_unwind(|| detect_anomaly(|| panic!("unrelated"))).unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"unrelated"));
+    }
+}
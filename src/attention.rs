@@ -0,0 +1,592 @@
+use crate::activation::Activation;
+use crate::layer::{Init, Layer};
+use crate::module::Module;
+use crate::normalization::LayerNorm;
+use crate::scalar::{RcScalar, Scalar};
+use crate::sequential::Sequential;
+use crate::softmax::softmax;
+use rand::Rng;
+
+// Sequences are stored time-major & flattened: `[x_0, x_1, ...]` with every `x_t` of size `d_model`
+fn split_steps(input: &[RcScalar], d_model: usize) -> Vec<Vec<RcScalar>> {
+    assert_eq!(input.len() % d_model, 0);
+    input.chunks(d_model).map(|x| x.to_vec()).collect()
+}
+
+fn dot(xs: &[RcScalar], ys: &[RcScalar]) -> RcScalar {
+    xs.iter()
+        .zip(ys.iter())
+        .fold(RcScalar::new(Scalar::new(0f32)), |acc, (x, y)| {
+            acc + RcScalar::clone(x) * RcScalar::clone(y)
+        })
+}
+
+/// `softmax(q k^T / sqrt(d)) v`, one row per query.
+///
+/// Where `mask[i][j]` is false, query `i` does not attend to key `j` at all: the score is left
+/// out of the softmax (rather than set to `-inf`), so every row needs at least one allowed key.
+pub fn scaled_dot_product_attention(
+    queries: &[Vec<RcScalar>],
+    keys: &[Vec<RcScalar>],
+    values: &[Vec<RcScalar>],
+    mask: Option<&[Vec<bool>]>,
+) -> Vec<Vec<RcScalar>> {
+    assert_eq!(keys.len(), values.len());
+    let scale = 1f32 / (queries[0].len() as f32).sqrt();
+
+    queries
+        .iter()
+        .enumerate()
+        .map(|(i, q)| {
+            let allowed: Vec<usize> = (0..keys.len())
+                .filter(|&j| mask.is_none_or(|mask| mask[i][j]))
+                .collect();
+            assert!(!allowed.is_empty(), "query {} is masked from every key", i);
+            let scores: Vec<RcScalar> = allowed.iter().map(|&j| dot(q, &keys[j]) * scale).collect();
+            let weights = softmax(&scores);
+
+            (0..values[0].len())
+                .map(|d| {
+                    allowed.iter().zip(weights.iter()).fold(
+                        RcScalar::new(Scalar::new(0f32)),
+                        |acc, (&j, weight)| {
+                            acc + RcScalar::clone(weight) * RcScalar::clone(&values[j][d])
+                        },
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Lower-triangular mask, so that step `i` only attends to steps `0..=i`.
+pub fn causal_mask(len: usize) -> Vec<Vec<bool>> {
+    (0..len)
+        .map(|i| (0..len).map(|j| j <= i).collect())
+        .collect()
+}
+
+/// Self-attention split over `num_heads` heads, with learned query, key, value & output projections.
+pub struct MultiHeadAttention {
+    d_model: usize,
+    num_heads: usize,
+    causal: bool,
+    query: Layer,
+    key: Layer,
+    value: Layer,
+    output: Layer,
+}
+
+impl MultiHeadAttention {
+    pub fn new(d_model: usize, num_heads: usize) -> Self {
+        assert!(
+            d_model.is_multiple_of(num_heads),
+            "d_model must be divisible by num_heads"
+        );
+        MultiHeadAttention {
+            d_model,
+            num_heads,
+            causal: false,
+            query: Layer::with_init(d_model, d_model, Activation::Linear, Init::Xavier),
+            key: Layer::with_init(d_model, d_model, Activation::Linear, Init::Xavier),
+            value: Layer::with_init(d_model, d_model, Activation::Linear, Init::Xavier),
+            output: Layer::with_init(d_model, d_model, Activation::Linear, Init::Xavier),
+        }
+    }
+
+    /// Masks future steps when used as a module.
+    pub fn causal(mut self, causal: bool) -> Self {
+        self.causal = causal;
+        self
+    }
+
+    pub fn feed_foward_with_mask(
+        &self,
+        input: Vec<RcScalar>,
+        mask: Option<&[Vec<bool>]>,
+    ) -> Vec<RcScalar> {
+        let steps = split_steps(&input, self.d_model);
+        let project = |layer: &Layer| -> Vec<Vec<RcScalar>> {
+            steps.iter().map(|x| layer.feed_foward(x.clone())).collect()
+        };
+        let (queries, keys, values) = (
+            project(&self.query),
+            project(&self.key),
+            project(&self.value),
+        );
+
+        let head_dim = self.d_model / self.num_heads;
+        let head = |xs: &[Vec<RcScalar>], h: usize| -> Vec<Vec<RcScalar>> {
+            xs.iter()
+                .map(|x| x[h * head_dim..(h + 1) * head_dim].to_vec())
+                .collect()
+        };
+        let heads: Vec<Vec<Vec<RcScalar>>> = (0..self.num_heads)
+            .map(|h| {
+                scaled_dot_product_attention(
+                    &head(&queries, h),
+                    &head(&keys, h),
+                    &head(&values, h),
+                    mask,
+                )
+            })
+            .collect();
+
+        (0..steps.len())
+            .flat_map(|t| {
+                let concat: Vec<RcScalar> = heads.iter().flat_map(|head| head[t].clone()).collect();
+                self.output.feed_foward(concat)
+            })
+            .collect()
+    }
+}
+
+impl Module for MultiHeadAttention {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        if self.causal {
+            let mask = causal_mask(input.len() / self.d_model);
+            self.feed_foward_with_mask(input, Some(&mask))
+        } else {
+            self.feed_foward_with_mask(input, None)
+        }
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        [&self.query, &self.key, &self.value, &self.output]
+            .iter()
+            .flat_map(|layer| layer.parameters())
+            .collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        [&self.query, &self.key, &self.value, &self.output]
+            .iter()
+            .flat_map(|layer| layer.biases())
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "MultiHeadAttention({}, heads={})",
+            self.d_model, self.num_heads
+        )
+    }
+}
+
+/// Applies a module to every step of a sequence on its own.
+pub struct PositionWise<M: Module> {
+    module: M,
+    step_size: usize,
+}
+
+impl<M: Module> PositionWise<M> {
+    pub fn new(module: M, step_size: usize) -> Self {
+        PositionWise { module, step_size }
+    }
+}
+
+impl<M: Module> Module for PositionWise<M> {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        split_steps(&input, self.step_size)
+            .into_iter()
+            .flat_map(|x| self.module.feed_foward(x))
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.module.parameters()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.module.biases()
+    }
+
+    fn name(&self) -> String {
+        format!("PositionWise({})", self.module.name())
+    }
+
+    fn buffers(&self) -> Vec<f32> {
+        self.module.buffers()
+    }
+
+    fn load_buffers(&self, buffers: &[f32]) {
+        self.module.load_buffers(buffers);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.module.set_training(training);
+    }
+
+    fn seed(&self, seed: u64) {
+        self.module.seed(seed);
+    }
+}
+
+/// Adds the fixed `sin`/`cos` encodings of "Attention Is All You Need" to every step.
+pub struct SinusoidalPositionalEncoding {
+    d_model: usize,
+}
+
+impl SinusoidalPositionalEncoding {
+    pub fn new(d_model: usize) -> Self {
+        SinusoidalPositionalEncoding { d_model }
+    }
+
+    pub fn encoding(&self, position: usize, i: usize) -> f32 {
+        let angle = position as f32 / 10000f32.powf((i - i % 2) as f32 / self.d_model as f32);
+        if i.is_multiple_of(2) {
+            angle.sin()
+        } else {
+            angle.cos()
+        }
+    }
+}
+
+impl Module for SinusoidalPositionalEncoding {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        assert_eq!(input.len() % self.d_model, 0);
+        input
+            .into_iter()
+            .enumerate()
+            .map(|(k, x)| x + self.encoding(k / self.d_model, k % self.d_model))
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!("SinusoidalPositionalEncoding({})", self.d_model)
+    }
+}
+
+/// Adds a learned vector per position, for sequences of up to `max_len` steps.
+pub struct LearnedPositionalEncoding {
+    d_model: usize,
+    // Indexed as [position][dimension]
+    weight: Vec<Vec<RcScalar>>,
+}
+
+impl LearnedPositionalEncoding {
+    pub fn new(max_len: usize, d_model: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        LearnedPositionalEncoding {
+            d_model,
+            weight: (0..max_len)
+                .map(|_| {
+                    (0..d_model)
+                        .map(|_| RcScalar::new(Scalar::new(rng.gen_range(-0.1..0.1))))
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Module for LearnedPositionalEncoding {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let steps = split_steps(&input, self.d_model);
+        assert!(
+            steps.len() <= self.weight.len(),
+            "sequence of length {} is longer than max_len {}",
+            steps.len(),
+            self.weight.len()
+        );
+        steps
+            .into_iter()
+            .zip(self.weight.iter())
+            .flat_map(|(x, p)| {
+                x.into_iter()
+                    .zip(p.iter())
+                    .map(|(x, p)| x + RcScalar::clone(p))
+                    .collect::<Vec<RcScalar>>()
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.weight.iter().flatten().cloned().collect()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "LearnedPositionalEncoding({}, {})",
+            self.weight.len(),
+            self.d_model
+        )
+    }
+}
+
+/// Pre-norm Transformer encoder block:
+///
+/// `x = x + attention(norm_1(x))`, then `x = x + mlp(norm_2(x))` with the MLP applied per step.
+pub struct TransformerEncoderBlock {
+    d_model: usize,
+    norm_1: PositionWise<LayerNorm>,
+    attention: MultiHeadAttention,
+    norm_2: PositionWise<LayerNorm>,
+    mlp: PositionWise<Sequential>,
+}
+
+impl TransformerEncoderBlock {
+    pub fn new(d_model: usize, num_heads: usize, d_ff: usize) -> Self {
+        TransformerEncoderBlock {
+            d_model,
+            norm_1: PositionWise::new(LayerNorm::new(d_model), d_model),
+            attention: MultiHeadAttention::new(d_model, num_heads),
+            norm_2: PositionWise::new(LayerNorm::new(d_model), d_model),
+            mlp: PositionWise::new(
+                Sequential::new()
+                    .push(Layer::with_init(d_model, d_ff, Activation::Relu, Init::He))
+                    .push(Layer::with_init(
+                        d_ff,
+                        d_model,
+                        Activation::Linear,
+                        Init::Xavier,
+                    )),
+                d_model,
+            ),
+        }
+    }
+
+    pub fn causal(mut self, causal: bool) -> Self {
+        self.attention = self.attention.causal(causal);
+        self
+    }
+}
+
+fn add(xs: Vec<RcScalar>, ys: Vec<RcScalar>) -> Vec<RcScalar> {
+    xs.into_iter().zip(ys).map(|(x, y)| x + y).collect()
+}
+
+impl Module for TransformerEncoderBlock {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let x = add(
+            input.clone(),
+            self.attention.feed_foward(self.norm_1.feed_foward(input)),
+        );
+        add(x.clone(), self.mlp.feed_foward(self.norm_2.feed_foward(x)))
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        let mut parameters = self.norm_1.parameters();
+        parameters.extend(self.attention.parameters());
+        parameters.extend(self.norm_2.parameters());
+        parameters.extend(self.mlp.parameters());
+        parameters
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        let mut biases = self.norm_1.biases();
+        biases.extend(self.attention.biases());
+        biases.extend(self.norm_2.biases());
+        biases.extend(self.mlp.biases());
+        biases
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "TransformerEncoderBlock({}, heads={})",
+            self.d_model, self.attention.num_heads
+        )
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.mlp.set_training(training);
+    }
+
+    fn seed(&self, seed: u64) {
+        self.mlp.seed(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::Embedding;
+    use crate::gradcheck::max_gradient_error;
+    use crate::loss::cross_entropy;
+    use crate::model::Model;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn data(ys: &[RcScalar]) -> Vec<f32> {
+        ys.iter().map(|y| y.0.borrow().data).collect()
+    }
+
+    // A block like `TransformerEncoderBlock::new`, with every weight drawn from `rng`
+    fn seeded_block<R: Rng>(
+        d_model: usize,
+        num_heads: usize,
+        d_ff: usize,
+        rng: &mut R,
+    ) -> TransformerEncoderBlock {
+        let block = TransformerEncoderBlock::new(d_model, num_heads, d_ff);
+        let attention = &block.attention;
+        for layer in [
+            &attention.query,
+            &attention.key,
+            &attention.value,
+            &attention.output,
+        ] {
+            layer.reset_parameters(Init::Xavier, rng);
+        }
+        let ff_1 = Layer::with_activation(d_model, d_ff, Activation::Relu);
+        ff_1.reset_parameters(Init::He, rng);
+        let ff_2 = Layer::with_activation(d_ff, d_model, Activation::Linear);
+        ff_2.reset_parameters(Init::Xavier, rng);
+        TransformerEncoderBlock {
+            mlp: PositionWise::new(Sequential::new().push(ff_1).push(ff_2), d_model),
+            ..block
+        }
+    }
+
+    #[test]
+    fn test_scaled_dot_product_attention() {
+        let rows = |xs: &[[f32; 2]]| -> Vec<Vec<RcScalar>> {
+            xs.iter().map(|x| RcScalar::vec_from(x)).collect()
+        };
+        let queries = rows(&[[1.0, 0.0], [0.0, 0.0]]);
+        let keys = rows(&[[1.0, 0.0], [-1.0, 0.0]]);
+        let values = rows(&[[1.0, 2.0], [3.0, 4.0]]);
+
+        let output = scaled_dot_product_attention(&queries, &keys, &values, None);
+        // Second query scores both keys 0, so it averages the values
+        assert_eq!(data(&output[1]), vec![2.0, 3.0]);
+        let w = 1.0 / (1.0 + (-2f32 / 2f32.sqrt()).exp());
+        assert!((output[0][0].0.borrow().data - (w + 3.0 * (1.0 - w))).abs() < 1e-5);
+
+        let masked = scaled_dot_product_attention(&queries, &keys, &values, Some(&causal_mask(2)));
+        assert_eq!(data(&masked[0]), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_multi_head_attention() {
+        let attention = MultiHeadAttention::new(4, 2).causal(true);
+        let xs: Vec<f32> = (0..12).map(|i| (i as f32 * 0.9).sin()).collect();
+
+        let output = attention.feed_foward(RcScalar::vec_from(&xs));
+        assert_eq!(output.len(), 12);
+        assert_eq!(attention.parameters().len(), 4 * (4 + 1) * 4);
+
+        // With the causal mask, the first step cannot depend on later ones
+        let mut changed = xs.clone();
+        changed[11] += 1.0;
+        let output_changed = attention.feed_foward(RcScalar::vec_from(&changed));
+        assert_eq!(data(&output[..4]), data(&output_changed[..4]));
+
+        let f = |x: Vec<RcScalar>| {
+            attention
+                .feed_foward(x)
+                .into_iter()
+                .enumerate()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, (i, y)| {
+                    acc + y * (1f32 + i as f32)
+                })
+        };
+        assert!(max_gradient_error(f, &xs, 1e-2) < 2e-2);
+    }
+
+    #[test]
+    fn test_positional_encoding() {
+        let sinusoidal = SinusoidalPositionalEncoding::new(4);
+        let output = sinusoidal.feed_foward(RcScalar::vec_from(&[0.0; 8]));
+
+        assert_eq!(data(&output[..4]), vec![0.0, 1.0, 0.0, 1.0]);
+        assert!((output[4].0.borrow().data - 1f32.sin()).abs() < 1e-6);
+        assert!((output[7].0.borrow().data - 0.01f32.cos()).abs() < 1e-6);
+
+        let learned = LearnedPositionalEncoding::new(3, 4);
+        assert_eq!(learned.feed_foward(RcScalar::vec_from(&[0.0; 8])).len(), 8);
+        assert_eq!(learned.parameters().len(), 12);
+    }
+
+    #[test]
+    fn test_learn_reverse() {
+        let (vocab, len, d_model): (usize, usize, usize) = (3, 3, 8);
+        let sequences: Vec<Vec<usize>> = (0..vocab.pow(len as u32))
+            .map(|n| (0..len).map(|t| n / vocab.pow(t as u32) % vocab).collect())
+            .collect();
+        let mut rng = StdRng::seed_from_u64(3);
+        let embedding = Embedding::new(vocab, d_model);
+        for p in embedding.parameters() {
+            p.0.borrow_mut().data = rng.gen_range(-1.0..1.0);
+        }
+        let block = seeded_block(d_model, 2, 16, &mut rng);
+        let head = Layer::with_activation(d_model, vocab, Activation::Linear);
+        head.reset_parameters(Init::Xavier, &mut rng);
+        let model_a = Model::from(
+            Sequential::new()
+                .push(embedding)
+                .push(SinusoidalPositionalEncoding::new(d_model))
+                .push(block)
+                .push(PositionWise::new(Sequential::new().push(head), d_model)),
+        );
+        let to_tokens = |sequence: &[usize]| -> Vec<RcScalar> {
+            sequence
+                .iter()
+                .map(|&token| RcScalar::new(Scalar::new(token as f32)))
+                .collect()
+        };
+
+        // Adam, as plain momentum is too sensitive to the step size for attention
+        let parameters = model_a.parameters();
+        let mut m = vec![0f32; parameters.len()];
+        let mut v = vec![0f32; parameters.len()];
+        let (beta_1, beta_2, lr) = (0.9f32, 0.999f32, 0.01f32);
+        let mut step = 0;
+        for _ in 0..25 {
+            for batch in sequences.chunks(3) {
+                let mut loss = RcScalar::new(Scalar::new(0f32));
+                for sequence in batch {
+                    let logits = model_a.feed_foward(to_tokens(sequence));
+                    for (t, step_logits) in logits.chunks(vocab).enumerate() {
+                        loss = loss + cross_entropy(step_logits, sequence[len - 1 - t]);
+                    }
+                }
+                loss = loss * (1f32 / (batch.len() * len) as f32);
+
+                for p in parameters.iter() {
+                    p.0.borrow_mut().grad = 0f32;
+                }
+                loss.backwards();
+                step += 1;
+                for (i, p) in parameters.iter().enumerate() {
+                    let mut borrowed = p.0.borrow_mut();
+                    m[i] = beta_1 * m[i] + (1f32 - beta_1) * borrowed.grad;
+                    v[i] = beta_2 * v[i] + (1f32 - beta_2) * borrowed.grad.powi(2);
+                    let m_hat = m[i] / (1f32 - beta_1.powi(step));
+                    let v_hat = v[i] / (1f32 - beta_2.powi(step));
+                    borrowed.data -= lr * m_hat / (v_hat.sqrt() + 1e-8);
+                }
+            }
+        }
+
+        let mut correct = 0;
+        for sequence in sequences.iter() {
+            let logits = model_a.feed_foward(to_tokens(sequence));
+            for (t, step_logits) in logits.chunks(vocab).enumerate() {
+                let predicted = (0..vocab)
+                    .max_by(|&a, &b| {
+                        step_logits[a]
+                            .0
+                            .borrow()
+                            .data
+                            .total_cmp(&step_logits[b].0.borrow().data)
+                    })
+                    .unwrap();
+                if predicted == sequence[len - 1 - t] {
+                    correct += 1;
+                }
+            }
+        }
+        let total = sequences.len() * len;
+        assert!(
+            correct as f32 / total as f32 > 0.95,
+            "reversed {}/{} tokens",
+            correct,
+            total
+        );
+    }
+}
@@ -0,0 +1,354 @@
+use crate::scalar::{topological_order, Ops, RcScalar, Scalar};
+use std::collections::{HashMap, HashSet};
+use std::io;
+
+/// Gradients of the sum of `outputs` w.r.t. each of `inputs`, as scalars.
+///
+/// Unlike `backwards()`, this leaves every `grad` field alone. With `create_graph` the
+/// gradients are built from operations on the original graph, so they can be differentiated
+/// again, e.g. for Hessian-vector products or gradient penalties:
+///
+/// ```
+/// # use neural_network_from_scratch::autograd::grad;
+/// # use neural_network_from_scratch::scalar::{RcScalar, Scalar};
+/// let x = RcScalar::new(Scalar::new(3f32));
+/// let y = x.powf(3f32);
+/// let dy = grad(&[y], &[x.clone()], true)?;
+/// let d2y = grad(&dy, &[x], false)?;
+/// assert_eq!(d2y[0].0.borrow().data, 18f32);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Without it they are new leaves. Inputs the outputs do not depend on, or only through
+/// frozen scalars, get a gradient of zero. Gradient hooks are not called. Fails if the graph
+/// was freed by `gradient`.
+pub fn grad(
+    outputs: &[RcScalar],
+    inputs: &[RcScalar],
+    create_graph: bool,
+) -> io::Result<Vec<RcScalar>> {
+    let order = topological_order(outputs);
+    check_not_freed(&order)?;
+    let mut grads: HashMap<usize, RcScalar> = HashMap::new();
+    for output in outputs.iter() {
+        let one = RcScalar::new(Scalar::new(1f32));
+        add_grad(&mut grads, output, one);
+    }
+    for node in order.iter().rev() {
+        let borrowed = node.0.borrow();
+        if borrowed.prev.is_empty() || !borrowed.requires_grad {
+            continue;
+        }
+        let g = match grads.get(&borrowed.uid) {
+            Some(g) => RcScalar::clone(g),
+            None => continue,
+        };
+        let prev = &borrowed.prev;
+        // The same rules as `Scalar::backward`, as operations
+        match borrowed.ops {
+            Ops::Add => {
+                for p in prev.iter() {
+                    add_grad(&mut grads, p, RcScalar::clone(&g));
+                }
+            }
+            Ops::Mul => {
+                add_grad(&mut grads, &prev[0], g.clone() * prev[1].clone());
+                add_grad(&mut grads, &prev[1], g * prev[0].clone());
+            }
+            Ops::Pow2 => add_grad(&mut grads, &prev[0], g * prev[0].clone() * 2f32),
+            Ops::Pow(exponent) => add_grad(
+                &mut grads,
+                &prev[0],
+                g * prev[0].powf(exponent - 1f32) * exponent,
+            ),
+            Ops::Tanh => add_grad(&mut grads, &prev[0], g * (-node.square() + 1f32)),
+            Ops::Exp => add_grad(&mut grads, &prev[0], g * node.clone()),
+            Ops::Log => add_grad(&mut grads, &prev[0], g * prev[0].powf(-1f32)),
+            Ops::Relu => {
+                if borrowed.data > 0f32 {
+                    add_grad(&mut grads, &prev[0], g);
+                }
+            }
+            Ops::Abs => {
+                let data = prev[0].0.borrow().data;
+                if data != 0f32 {
+                    add_grad(&mut grads, &prev[0], g * data.signum());
+                }
+            }
+            Ops::Identity => add_grad(&mut grads, &prev[0], g),
+            Ops::Barrier | Ops::Null => (),
+        }
+    }
+
+    Ok(inputs
+        .iter()
+        .map(|input| match grads.get(&input.0.borrow().uid) {
+            Some(g) if create_graph => RcScalar::clone(g),
+            Some(g) => g.detach(),
+            None => RcScalar::new(Scalar::new(0f32)),
+        })
+        .collect())
+}
+
+/// Gradient of `output` w.r.t. each of `inputs`, leaving every `grad` field alone.
+///
+/// Only the part of the graph between `inputs` & `output` is visited. Inputs that `output` does
+/// not depend on, or only through frozen scalars, get a gradient of zero. Gradient hooks are not
+/// called.
+///
+/// Unless `retain_graph`, the graph behind `output` is freed afterwards: every scalar it was
+/// computed from drops its inputs, releasing their memory even while `output` is kept around.
+/// Differentiating through the freed graph again, with this, `grad` or `try_backwards()`, fails
+/// and `backwards()` panics.
+///
+/// ```
+/// # use neural_network_from_scratch::autograd::gradient;
+/// # use neural_network_from_scratch::scalar::{RcScalar, Scalar};
+/// let x = RcScalar::new(Scalar::new(3f32));
+/// let y = x.square() + 1f32;
+/// assert_eq!(gradient(&y, &[x.clone()], true)?, vec![6f32]);
+/// assert_eq!(gradient(&y, &[x.clone()], false)?, vec![6f32]);
+/// assert!(gradient(&y, &[x], false).is_err());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn gradient(
+    output: &RcScalar,
+    inputs: &[RcScalar],
+    retain_graph: bool,
+) -> io::Result<Vec<f32>> {
+    let order = topological_order(std::slice::from_ref(output));
+    check_not_freed(&order)?;
+
+    // Scalars through which `output` depends on one of `inputs`
+    let mut needed: HashSet<usize> = inputs.iter().map(|x| x.0.borrow().uid).collect();
+    for node in order.iter() {
+        let borrowed = node.0.borrow();
+        if borrowed
+            .prev
+            .iter()
+            .any(|p| needed.contains(&p.0.borrow().uid))
+        {
+            needed.insert(borrowed.uid);
+        }
+    }
+
+    let mut grads: HashMap<usize, f32> = HashMap::new();
+    if output.requires_grad() {
+        grads.insert(output.0.borrow().uid, 1f32);
+    }
+    for node in order.iter().rev() {
+        let borrowed = node.0.borrow();
+        if !needed.contains(&borrowed.uid) || !borrowed.requires_grad {
+            continue;
+        }
+        let g = match grads.get(&borrowed.uid) {
+            Some(&g) => g,
+            None => continue,
+        };
+        for (p, input_grad) in borrowed.prev.iter().zip(borrowed.input_grads(g)) {
+            let p = p.0.borrow();
+            if p.requires_grad && needed.contains(&p.uid) {
+                *grads.entry(p.uid).or_insert(0f32) += input_grad;
+            }
+        }
+    }
+
+    if !retain_graph {
+        for node in order.iter() {
+            node.0.borrow_mut().prev.clear();
+        }
+    }
+    Ok(inputs
+        .iter()
+        .map(|x| grads.get(&x.0.borrow().uid).copied().unwrap_or(0f32))
+        .collect())
+}
+
+// Fails on the first node of `order` whose inputs were dropped by `gradient`
+pub(crate) fn check_not_freed(order: &[RcScalar]) -> io::Result<()> {
+    match order.iter().find(|node| {
+        let borrowed = node.0.borrow();
+        borrowed.prev.is_empty() && borrowed.ops != Ops::Null
+    }) {
+        Some(freed) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "the graph behind node {} was freed, pass retain_graph to differentiate it again",
+                freed.0.borrow().uid
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+// Adds `g` to the gradient of `scalar`, unless it is frozen
+fn add_grad(grads: &mut HashMap<usize, RcScalar>, scalar: &RcScalar, g: RcScalar) {
+    let borrowed = scalar.0.borrow();
+    if !borrowed.requires_grad {
+        return;
+    }
+    let sum = match grads.remove(&borrowed.uid) {
+        Some(acc) => acc + g,
+        None => g,
+    };
+    grads.insert(borrowed.uid, sum);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::Activation;
+    use crate::gradcheck::max_gradient_error;
+    use crate::layer::{Init, Layer};
+    use crate::model::Model;
+    use crate::sequential::Sequential;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn data(xs: &[RcScalar]) -> Vec<f32> {
+        xs.iter().map(|x| x.0.borrow().data).collect()
+    }
+
+    // Exercises every op
+    fn f(x: &[RcScalar]) -> RcScalar {
+        let (a, b) = (x[0].clone(), x[1].clone());
+        (a.clone() * b.clone()).tanh() + a.square() * b.exp() + (b.clone() * 2f32).abs().ln()
+            - a.relu().powf(1.5)
+            + b.sigmoid()
+    }
+
+    #[test]
+    fn test_first_order() {
+        let x = RcScalar::vec_from(&[0.7, -0.4]);
+        let y = f(&x);
+        let grads = grad(std::slice::from_ref(&y), &x, false).unwrap();
+
+        y.backwards();
+        for (g, x) in grads.iter().zip(x.iter()) {
+            assert!((g.0.borrow().data - x.0.borrow().grad).abs() < 1e-6);
+        }
+        // The leaves are new, nothing else was touched
+        assert!(grads[0].0.borrow().prev.is_empty());
+        let z = f(&x);
+        grad(std::slice::from_ref(&z), &x, true).unwrap();
+        assert_eq!(z.0.borrow().grad, 0f32);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let model_a = Model::from(Sequential::new().dense(2, 3).tanh().dense(3, 1));
+        let x = RcScalar::vec_from(&[0.7, -0.4]);
+        let y = model_a.feed_foward(x.clone());
+        let loss = f(&[y[0].clone(), x[1].clone()]);
+        let parameters = model_a.parameters();
+        parameters[1].set_requires_grad(false);
+        let requested = vec![
+            x[0].clone(),
+            parameters[1].clone(),
+            parameters[12].clone(),
+            RcScalar::new(Scalar::new(1f32)),
+        ];
+
+        let grads = gradient(&loss, &requested, true).unwrap();
+        assert_eq!(grads[1], 0f32);
+        assert_eq!(grads[3], 0f32);
+        assert!(parameters
+            .iter()
+            .chain(x.iter())
+            .all(|p| p.0.borrow().grad == 0f32));
+
+        loss.backwards();
+        assert!((grads[0] - x[0].0.borrow().grad).abs() < 1e-6);
+        assert!((grads[2] - parameters[12].0.borrow().grad).abs() < 1e-6);
+
+        // Freeing the graph keeps the values but not the inputs they were computed from
+        let data = loss.0.borrow().data;
+        assert_eq!(gradient(&loss, &requested, false).unwrap(), grads);
+        assert_eq!(loss.0.borrow().data, data);
+        assert!(loss.0.borrow().prev.is_empty() && y[0].0.borrow().prev.is_empty());
+        assert!(gradient(&loss, &requested, true).is_err());
+        assert!(gradient(&y[0], &requested, true).is_err());
+        let grads_of = |xs: &[RcScalar]| xs.iter().map(|x| x.0.borrow().grad).collect::<Vec<_>>();
+        let before = grads_of(&parameters);
+        assert!(loss.try_backwards().is_err());
+        assert_eq!(grads_of(&parameters), before);
+    }
+
+    #[test]
+    fn test_second_order() {
+        // Each partial derivative of f, differentiated again by backwards()
+        for i in 0..2 {
+            let df = |x: Vec<RcScalar>| grad(&[f(&x)], &x, true).unwrap().remove(i);
+            let error = max_gradient_error(df, &[0.7, -0.4], 1e-2);
+            assert!(error < 1e-2, "error {} for input {}", error, i);
+        }
+
+        // Hessian-vector product of x^3 y: H = [[6xy, 3x^2], [3x^2, 0]]
+        let x = RcScalar::vec_from(&[2.0, 3.0]);
+        let y = x[0].powf(3f32) * x[1].clone();
+        let g = grad(&[y], &x, true).unwrap();
+        let v = [1f32, -1f32];
+        let gv = g[0].clone() * v[0] + g[1].clone() * v[1];
+        let hv = grad(&[gv], &x, false).unwrap();
+        assert_eq!(data(&hv), vec![36f32 - 12f32, 12f32]);
+    }
+
+    #[test]
+    fn test_gradient_penalty() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let hidden = Layer::with_activation(2, 3, Activation::Tanh);
+        hidden.reset_parameters(Init::Xavier, &mut rng);
+        let head = Layer::with_activation(3, 1, Activation::Linear);
+        head.reset_parameters(Init::Xavier, &mut rng);
+        let model_a = Model::from(Sequential::new().push(hidden).push(head));
+        let x = RcScalar::vec_from(&[0.5, -1.0]);
+        // Squared norm of the input gradient, differentiable w.r.t. the weights
+        let penalty = || {
+            let y = model_a.feed_foward(x.clone());
+            grad(&y, &x, true)
+                .unwrap()
+                .into_iter()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, g| acc + g.square())
+        };
+        penalty().backwards();
+
+        let w = &model_a.parameters()[0];
+        let dw = w.0.borrow().grad;
+        assert_ne!(dw, 0f32);
+        let h = 1e-3;
+        w.0.borrow_mut().data += h;
+        let plus = penalty().0.borrow().data;
+        w.0.borrow_mut().data -= 2f32 * h;
+        let minus = penalty().0.borrow().data;
+        let numeric = (plus - minus) / (2f32 * h);
+        assert!(
+            (numeric - dw).abs() < 1e-3 * dw.abs().max(1f32),
+            "{} vs {}",
+            numeric,
+            dw
+        );
+    }
+
+    #[test]
+    fn test_backward_hook() {
+        let model_a = Model::from(Sequential::new().dense(2, 3).tanh().dense(3, 1));
+        let x = RcScalar::vec_from(&[0.5, -1.0]);
+        let _handle = model_a.register_backward_hook("0", |_| None).unwrap();
+        let y = model_a.feed_foward(x.clone());
+
+        // A hook that changes nothing leaves the gradients as they were
+        let grads = grad(&y, &x, true).unwrap();
+        y[0].backwards();
+        for (g, x) in grads.iter().zip(x.iter()) {
+            assert!((g.0.borrow().data - x.0.borrow().grad).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_freed_graph() {
+        let x = RcScalar::vec_from(&[2.0]);
+        let y = x[0].square() * 3f32;
+        gradient(&y, &x, false).unwrap();
+        assert!(grad(std::slice::from_ref(&y), &x, false).is_err());
+    }
+}
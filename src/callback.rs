@@ -0,0 +1,312 @@
+use crate::model::Model;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+/// Metrics at the end of an epoch, by name: `loss` for the training loss and `val_loss`
+/// when the trainer was given validation data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochLogs {
+    /// Number of epochs completed, counting this one.
+    pub epoch: usize,
+    pub metrics: BTreeMap<String, f32>,
+}
+
+impl EpochLogs {
+    /// Value of `metric`, failing with the names that are available if it was not recorded.
+    pub fn get(&self, metric: &str) -> io::Result<f32> {
+        self.metrics.get(metric).copied().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "metric {} is not recorded, available: {:?}",
+                    metric,
+                    self.metrics.keys().collect::<Vec<_>>()
+                ),
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Control {
+    Continue,
+    Stop,
+}
+
+/// Whether lower or higher values of a monitored metric are better.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Min,
+    Max,
+}
+
+impl Mode {
+    fn improves(&self, value: f32, best: Option<f32>, min_delta: f32) -> bool {
+        match (self, best) {
+            (_, None) => true,
+            (Mode::Min, Some(best)) => value < best - min_delta,
+            (Mode::Max, Some(best)) => value > best + min_delta,
+        }
+    }
+}
+
+/// Hooks the trainer calls while fitting.
+pub trait Callback {
+    /// After the gradients of a batch have been computed, before clipping & the optimizer step,
+    /// e.g. to inspect `model.layer_stats()`.
+    fn on_backward(&mut self, _model: &Model) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn on_epoch_end(&mut self, _model: &Model, _logs: &EpochLogs) -> io::Result<Control> {
+        Ok(Control::Continue)
+    }
+
+    fn on_train_end(&mut self, _model: &Model) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Progress to carry over when training resumes from a checkpoint, e.g. epochs waited.
+    fn state(&self) -> Value {
+        Value::Null
+    }
+
+    fn load_state(&mut self, _state: &Value) -> serde_json::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EarlyStoppingState {
+    best: Option<f32>,
+    wait: usize,
+    stopped_epoch: Option<usize>,
+}
+
+/// Stops training once `monitor` has not improved by more than `min_delta` for `patience` epochs.
+pub struct EarlyStopping {
+    monitor: String,
+    patience: usize,
+    min_delta: f32,
+    mode: Mode,
+    best: Option<f32>,
+    wait: usize,
+    stopped_epoch: Option<usize>,
+}
+
+impl EarlyStopping {
+    pub fn new(monitor: &str, patience: usize) -> Self {
+        EarlyStopping {
+            monitor: monitor.to_string(),
+            patience,
+            min_delta: 0f32,
+            mode: Mode::Min,
+            best: None,
+            wait: 0,
+            stopped_epoch: None,
+        }
+    }
+
+    pub fn min_delta(mut self, min_delta: f32) -> Self {
+        self.min_delta = min_delta;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn best(&self) -> Option<f32> {
+        self.best
+    }
+
+    /// The epoch training was stopped after, if it was.
+    pub fn stopped_epoch(&self) -> Option<usize> {
+        self.stopped_epoch
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_epoch_end(&mut self, _model: &Model, logs: &EpochLogs) -> io::Result<Control> {
+        let value = logs.get(&self.monitor)?;
+        if self.mode.improves(value, self.best, self.min_delta) {
+            self.best = Some(value);
+            self.wait = 0;
+            return Ok(Control::Continue);
+        }
+        self.wait += 1;
+        if self.wait >= self.patience {
+            self.stopped_epoch = Some(logs.epoch);
+            return Ok(Control::Stop);
+        }
+        Ok(Control::Continue)
+    }
+
+    fn state(&self) -> Value {
+        serde_json::json!(EarlyStoppingState {
+            best: self.best,
+            wait: self.wait,
+            stopped_epoch: self.stopped_epoch,
+        })
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        let state: EarlyStoppingState = serde_json::from_value(state.clone())?;
+        self.best = state.best;
+        self.wait = state.wait;
+        self.stopped_epoch = state.stopped_epoch;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ModelCheckpointState {
+    best: Option<f32>,
+    best_epoch: Option<usize>,
+}
+
+/// Saves the model to `path` whenever `monitor` reaches a new best, and loads those weights
+/// back into the model when training ends.
+pub struct ModelCheckpoint {
+    path: PathBuf,
+    monitor: String,
+    mode: Mode,
+    restore_best: bool,
+    best: Option<f32>,
+    best_epoch: Option<usize>,
+}
+
+impl ModelCheckpoint {
+    pub fn new<P: Into<PathBuf>>(path: P, monitor: &str) -> Self {
+        ModelCheckpoint {
+            path: path.into(),
+            monitor: monitor.to_string(),
+            mode: Mode::Min,
+            restore_best: true,
+            best: None,
+            best_epoch: None,
+        }
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Whether the best weights are loaded back at the end of training. On by default.
+    pub fn restore_best(mut self, restore_best: bool) -> Self {
+        self.restore_best = restore_best;
+        self
+    }
+
+    pub fn best(&self) -> Option<f32> {
+        self.best
+    }
+
+    pub fn best_epoch(&self) -> Option<usize> {
+        self.best_epoch
+    }
+}
+
+impl Callback for ModelCheckpoint {
+    fn on_epoch_end(&mut self, model: &Model, logs: &EpochLogs) -> io::Result<Control> {
+        let value = logs.get(&self.monitor)?;
+        if self.mode.improves(value, self.best, 0f32) {
+            self.best = Some(value);
+            self.best_epoch = Some(logs.epoch);
+            model.save(&self.path)?;
+        }
+        Ok(Control::Continue)
+    }
+
+    fn on_train_end(&mut self, model: &Model) -> io::Result<()> {
+        if self.restore_best && self.best.is_some() {
+            model.load(&self.path)?;
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> Value {
+        serde_json::json!(ModelCheckpointState {
+            best: self.best,
+            best_epoch: self.best_epoch,
+        })
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        let state: ModelCheckpointState = serde_json::from_value(state.clone())?;
+        self.best = state.best;
+        self.best_epoch = state.best_epoch;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logs(epoch: usize, val_loss: f32) -> EpochLogs {
+        EpochLogs {
+            epoch,
+            metrics: BTreeMap::from([("val_loss".to_string(), val_loss)]),
+        }
+    }
+
+    #[test]
+    fn test_early_stopping() {
+        let model = Model::new(vec![1, 1]);
+        let mut early_stopping = EarlyStopping::new("val_loss", 2).min_delta(0.05);
+
+        let controls: Vec<Control> = [1.0, 0.8, 0.78, 0.9, 0.5]
+            .iter()
+            .enumerate()
+            .map(|(i, &loss)| {
+                early_stopping
+                    .on_epoch_end(&model, &logs(i + 1, loss))
+                    .unwrap()
+            })
+            .collect();
+
+        // 0.78 is within min_delta of 0.8, so it does not count as an improvement
+        assert_eq!(
+            controls,
+            vec![
+                Control::Continue,
+                Control::Continue,
+                Control::Continue,
+                Control::Stop,
+                Control::Continue
+            ]
+        );
+        assert_eq!(early_stopping.stopped_epoch(), Some(4));
+        assert!(EarlyStopping::new("accuracy", 2)
+            .on_epoch_end(&model, &logs(1, 1.0))
+            .is_err());
+    }
+
+    #[test]
+    fn test_model_checkpoint() {
+        let path = std::env::temp_dir().join("nn_from_scratch_test_model_checkpoint.json");
+        let model = Model::new(vec![1, 1]);
+        let mut checkpoint = ModelCheckpoint::new(&path, "val_loss");
+
+        checkpoint.on_epoch_end(&model, &logs(1, 0.5)).unwrap();
+        let best = model.state();
+        model.parameters()[0].0.borrow_mut().data += 1f32;
+        checkpoint.on_epoch_end(&model, &logs(2, 0.7)).unwrap();
+        checkpoint.on_train_end(&model).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(checkpoint.best_epoch(), Some(1));
+        assert_eq!(model.state(), best);
+
+        let mut resumed = ModelCheckpoint::new(&path, "val_loss");
+        resumed.load_state(&checkpoint.state()).unwrap();
+        assert_eq!((resumed.best(), resumed.best_epoch()), (Some(0.5), Some(1)));
+        assert!(resumed.load_state(&Value::Null).is_err());
+    }
+}
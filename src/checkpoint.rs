@@ -0,0 +1,129 @@
+use crate::lr_scheduler::LrScheduler;
+use crate::model::{Model, ModelState};
+use crate::optim::Optimizer;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A snapshot of a training run: the model's weights, the state of its optimizer and
+/// learning-rate schedule, and how far training got.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub model: ModelState,
+    #[serde(default)]
+    pub optimizer: Option<Value>,
+    pub scheduler: Option<Value>,
+    /// Number of epochs completed.
+    #[serde(default)]
+    pub epoch: usize,
+    /// Number of optimizer steps taken.
+    #[serde(default)]
+    pub step: usize,
+    /// Seed of the data shuffling & dropout. Each epoch's order is drawn from a generator keyed
+    /// by it & the epoch number, each step's dropout masks from a separate one keyed by it & the
+    /// step number, so together with `epoch` & `step` it is the whole state of both.
+    #[serde(default)]
+    pub seed: u64,
+    /// State of the trainer's callbacks, in the order they were added.
+    #[serde(default)]
+    pub callbacks: Vec<Value>,
+}
+
+fn missing(part: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("checkpoint has no {} state", part),
+    )
+}
+
+impl Checkpoint {
+    pub fn new(
+        model: &Model,
+        optimizer: Option<&dyn Optimizer>,
+        scheduler: Option<&dyn LrScheduler>,
+    ) -> Self {
+        Checkpoint {
+            model: model.state(),
+            optimizer: optimizer.map(|optimizer| optimizer.state()),
+            scheduler: scheduler.map(|scheduler| scheduler.state()),
+            epoch: 0,
+            step: 0,
+            seed: 0,
+            callbacks: Vec::new(),
+        }
+    }
+
+    /// Restores the model, and the optimizer & scheduler if given, failing if the checkpoint
+    /// lacks their state or any part does not match.
+    pub fn restore(
+        &self,
+        model: &Model,
+        optimizer: Option<&mut dyn Optimizer>,
+        scheduler: Option<&mut dyn LrScheduler>,
+    ) -> io::Result<()> {
+        model.load_state(&self.model)?;
+        if let Some(optimizer) = optimizer {
+            optimizer.load_state(
+                self.optimizer
+                    .as_ref()
+                    .ok_or_else(|| missing("optimizer"))?,
+            )?;
+        }
+        if let Some(scheduler) = scheduler {
+            scheduler.load_state(
+                self.scheduler
+                    .as_ref()
+                    .ok_or_else(|| missing("scheduler"))?,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes to a temporary file first, so an interruption never leaves a truncated checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let json = serde_json::to_string(self)?;
+        let mut tmp = path.as_ref().as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lr_scheduler::StepLr;
+
+    #[test]
+    fn test_save_load() {
+        let model = Model::new(vec![2, 3, 1]);
+        let mut scheduler = StepLr::new(0.1, 2, 0.5);
+        scheduler.advance();
+        scheduler.advance();
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+
+        Checkpoint::new(&model, None, Some(&scheduler))
+            .save(&path)
+            .unwrap();
+
+        let restored_model = Model::new(vec![2, 3, 1]);
+        let mut restored_scheduler = StepLr::new(0.1, 2, 0.5);
+        Checkpoint::load(&path)
+            .unwrap()
+            .restore(&restored_model, None, Some(&mut restored_scheduler))
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored_model.state(), model.state());
+        assert_eq!(restored_scheduler, scheduler);
+        assert!(Checkpoint::new(&model, None, None)
+            .restore(&restored_model, None, Some(&mut restored_scheduler))
+            .is_err());
+    }
+}
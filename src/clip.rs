@@ -0,0 +1,99 @@
+use crate::scalar::RcScalar;
+use serde::{Deserialize, Serialize};
+
+/// How gradients are clipped before the optimizer step.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GradClip {
+    /// Clamp every gradient to `[-clip, clip]`.
+    Value(f32),
+    /// Rescale all gradients together so their global L2 norm is at most `max_norm`.
+    Norm(f32),
+}
+
+impl GradClip {
+    /// Clips the gradients of `parameters`, returning their global L2 norm before clipping.
+    pub fn apply(&self, parameters: &[RcScalar]) -> f32 {
+        match *self {
+            GradClip::Value(clip) => clip_grad_value(parameters, clip),
+            GradClip::Norm(max_norm) => clip_grad_norm(parameters, max_norm),
+        }
+    }
+}
+
+/// Global L2 norm of the gradients of `parameters`, as if they were one flat vector.
+pub fn grad_norm(parameters: &[RcScalar]) -> f32 {
+    parameters
+        .iter()
+        .map(|p| p.0.borrow().grad.powi(2))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Clamps every gradient to `[-clip, clip]`, returning the global L2 norm before clipping.
+pub fn clip_grad_value(parameters: &[RcScalar], clip: f32) -> f32 {
+    assert!(clip > 0f32);
+    let norm = grad_norm(parameters);
+    for p in parameters {
+        let mut borrowed = p.0.borrow_mut();
+        borrowed.grad = borrowed.grad.clamp(-clip, clip);
+    }
+    norm
+}
+
+/// Scales all gradients by the same factor so their global L2 norm is at most `max_norm`,
+/// keeping the update's direction. Returns the norm before clipping.
+pub fn clip_grad_norm(parameters: &[RcScalar], max_norm: f32) -> f32 {
+    assert!(max_norm > 0f32);
+    let norm = grad_norm(parameters);
+    if norm > max_norm {
+        let scale = max_norm / norm;
+        for p in parameters {
+            p.0.borrow_mut().grad *= scale;
+        }
+    }
+    norm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+
+    fn with_grads(grads: &[f32]) -> Vec<RcScalar> {
+        grads
+            .iter()
+            .map(|&grad| {
+                let p = RcScalar::new(Scalar::new(0f32));
+                p.0.borrow_mut().grad = grad;
+                p
+            })
+            .collect()
+    }
+
+    fn grads(parameters: &[RcScalar]) -> Vec<f32> {
+        parameters.iter().map(|p| p.0.borrow().grad).collect()
+    }
+
+    #[test]
+    fn test_clip_grad_value() {
+        let parameters = with_grads(&[3.0, -4.0, 0.5]);
+
+        let norm = clip_grad_value(&parameters, 1.0);
+
+        assert!((norm - 25.25f32.sqrt()).abs() < 1e-6);
+        assert_eq!(grads(&parameters), vec![1.0, -1.0, 0.5]);
+    }
+
+    #[test]
+    fn test_clip_grad_norm() {
+        let parameters = with_grads(&[3.0, -4.0]);
+
+        assert_eq!(clip_grad_norm(&parameters, 10.0), 5.0);
+        assert_eq!(grads(&parameters), vec![3.0, -4.0]);
+
+        assert_eq!(GradClip::Norm(1.0).apply(&parameters), 5.0);
+        let clipped = grads(&parameters);
+        assert!((clipped[0] - 0.6).abs() < 1e-6 && (clipped[1] + 0.8).abs() < 1e-6);
+        assert!((grad_norm(&parameters) - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,801 @@
+use crate::activation::Activation;
+use crate::clip::GradClip;
+use crate::data::{check_samples, read_csv};
+use crate::dropout::Dropout;
+use crate::layer::{Init, Layer};
+use crate::logger::{LogFormat, LogInterval, MetricsLogger};
+use crate::loss::Loss;
+use crate::lr_scheduler::{
+    CosineAnnealingLr, ExponentialLr, LinearWarmup, LrScheduler, OneCycleLr, ReduceLrOnPlateau,
+    StepLr,
+};
+use crate::model::Model;
+use crate::normalization::{BatchNorm1d, LayerNorm};
+use crate::optim::{Adam, Optimizer, Sgd};
+use crate::regularization::{Penalty, Regularizer};
+use crate::sequential::Sequential;
+use crate::tensorboard::SummaryWriter;
+use crate::trainer::{Sample, Trainer, TrainerConfig};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Everything needed to build a model & its trainer, read from a TOML or JSON file.
+///
+/// ```toml
+/// loss = "cross_entropy"
+///
+/// [model]
+/// input = 2
+/// layers = [
+///     { type = "dense", size = 16, activation = "relu" },
+///     { type = "dropout", p = 0.1 },
+///     { type = "dense", size = 3 },
+/// ]
+///
+/// [optimizer]
+/// type = "adam"
+/// lr = 0.01
+///
+/// [scheduler]
+/// type = "cosine"
+/// t_max = 50
+///
+/// [data]
+/// train = "train.csv"
+/// validation = "validation.csv"
+///
+/// [training]
+/// epochs = 50
+/// batch_size = 16
+///
+/// [[logs]]
+/// path = "epochs.csv"
+/// format = "csv"
+/// ```
+///
+/// `tensorboard = "runs/baseline"` at the top level writes TensorBoard summaries too.
+///
+/// Unknown fields are rejected, and errors name the field they come from, e.g.
+/// `model.layers[1].p: must be in [0, 1)`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub model: ModelConfig,
+    pub loss: Loss,
+    pub optimizer: OptimizerConfig,
+    #[serde(default)]
+    pub scheduler: Option<SchedulerConfig>,
+    /// Added to the training loss, see `Trainer::penalty`.
+    #[serde(default)]
+    pub penalty: Option<Penalty>,
+    pub data: DataConfig,
+    #[serde(default)]
+    pub training: TrainerConfig,
+    /// Metrics files written while training.
+    #[serde(default)]
+    pub logs: Vec<LogConfig>,
+    /// Directory TensorBoard event files are written to, see `Trainer::tensorboard`.
+    #[serde(default)]
+    pub tensorboard: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    /// Number of inputs.
+    pub input: usize,
+    pub layers: Vec<LayerConfig>,
+}
+
+/// One layer of the model, taking the output of the previous one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LayerConfig {
+    /// Fully-connected layer, linear unless given an activation. `init` defaults to He for
+    /// ReLU and to Xavier otherwise.
+    Dense {
+        size: usize,
+        #[serde(default = "linear")]
+        activation: Activation,
+        init: Option<Init>,
+    },
+    Dropout {
+        p: f32,
+    },
+    BatchNorm,
+    LayerNorm,
+}
+
+fn linear() -> Activation {
+    Activation::Linear
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum OptimizerConfig {
+    Sgd {
+        lr: f32,
+        #[serde(default)]
+        momentum: f32,
+        /// Decoupled decay of the weights, biases left alone.
+        weight_decay: Option<Penalty>,
+    },
+    Adam {
+        lr: f32,
+        betas: Option<(f32, f32)>,
+        weight_decay: Option<Penalty>,
+    },
+}
+
+/// Learning-rate schedules, all starting from the optimizer's `lr`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SchedulerConfig {
+    Step {
+        step_size: usize,
+        gamma: f32,
+    },
+    Exponential {
+        gamma: f32,
+    },
+    Cosine {
+        t_max: usize,
+        #[serde(default)]
+        eta_min: f32,
+        /// Restarts the schedule, `t_mult` times longer each cycle.
+        t_mult: Option<usize>,
+    },
+    LinearWarmup {
+        warmup_steps: usize,
+        /// What follows the warmup, constant if left out.
+        then: Option<Box<SchedulerConfig>>,
+    },
+    OneCycle {
+        total_steps: usize,
+        pct_start: Option<f32>,
+        /// `div_factor` & `final_div_factor`.
+        div_factors: Option<(f32, f32)>,
+    },
+    ReduceOnPlateau {
+        factor: Option<f32>,
+        patience: Option<usize>,
+        threshold: Option<f32>,
+        min_lr: Option<f32>,
+    },
+}
+
+/// CSV files of samples whose last `targets` columns are the targets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataConfig {
+    pub train: PathBuf,
+    pub validation: Option<PathBuf>,
+    /// Number of target columns, always 1 (the class index) for `cross_entropy`.
+    #[serde(default = "one")]
+    pub targets: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+/// A `MetricsLogger`, per epoch unless `interval = "step"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    pub path: PathBuf,
+    pub format: LogFormat,
+    #[serde(default = "epoch")]
+    pub interval: LogInterval,
+    #[serde(default)]
+    pub append: bool,
+}
+
+fn epoch() -> LogInterval {
+    LogInterval::Epoch
+}
+
+impl LogConfig {
+    pub fn build(&self) -> MetricsLogger {
+        MetricsLogger::new(&self.path, self.format)
+            .interval(self.interval)
+            .append(self.append)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse<'de, D>(deserializer: D) -> io::Result<Config>
+where
+    D: serde::Deserializer<'de>,
+    D::Error: Display,
+{
+    let config: Config = serde_path_to_error::deserialize(deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        if path == "." {
+            invalid(e.into_inner().to_string())
+        } else {
+            invalid(format!("{}: {}", path, e.into_inner()))
+        }
+    })?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Validation errors, one `path: message` per line.
+#[derive(Default)]
+struct Errors(Vec<String>);
+
+impl Errors {
+    fn check(&mut self, ok: bool, path: &str, message: &str) {
+        if !ok {
+            self.0.push(format!("{}: {}", path, message));
+        }
+    }
+
+    fn check_penalty(&mut self, penalty: &Option<Penalty>, path: &str) {
+        let ok = match *penalty {
+            None => true,
+            Some(Penalty::L1(c)) | Some(Penalty::L2(c)) => c >= 0f32,
+            Some(Penalty::ElasticNet { l1, l2 }) => l1 >= 0f32 && l2 >= 0f32,
+        };
+        self.check(ok, path, "coefficients must not be negative");
+    }
+}
+
+impl Config {
+    pub fn from_toml(toml: &str) -> io::Result<Self> {
+        parse(toml::Deserializer::new(toml))
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let config = parse(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(config)
+    }
+
+    /// Reads a `.toml` or `.json` file. Relative data paths are taken from the file's directory.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let with_path =
+            |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+        let text = fs::read_to_string(path).map_err(with_path)?;
+        let mut config = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Config::from_toml(&text),
+            Some("json") => Config::from_json(&text),
+            _ => Err(invalid("expected a .toml or .json file".to_string())),
+        }
+        .map_err(with_path)?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        config.data.train = dir.join(&config.data.train);
+        config.data.validation = config.data.validation.map(|v| dir.join(v));
+        for log in config.logs.iter_mut() {
+            log.path = dir.join(&log.path);
+        }
+        config.tensorboard = config.tensorboard.map(|t| dir.join(t));
+        Ok(config)
+    }
+
+    /// Checks the values deserialization cannot, reporting every problem found.
+    pub fn validate(&self) -> io::Result<()> {
+        let mut errors = Errors::default();
+        self.model.validate(&mut errors);
+
+        let outputs = self.model.output_size();
+        match self.loss {
+            // Without layers there are no outputs to speak of, already reported above
+            _ if self.model.layers.is_empty() => {}
+            Loss::Mse => errors.check(
+                self.data.targets == outputs,
+                "data.targets",
+                &format!(
+                    "{} targets for {} model outputs",
+                    self.data.targets, outputs
+                ),
+            ),
+            Loss::CrossEntropy => {
+                errors.check(
+                    self.data.targets == 1,
+                    "data.targets",
+                    "cross_entropy takes a single column of class indices",
+                );
+                errors.check(
+                    outputs >= 2,
+                    "model.layers",
+                    &format!("cross_entropy needs at least 2 outputs, found {}", outputs),
+                );
+            }
+        }
+
+        self.optimizer.validate(&mut errors);
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.validate("scheduler", &mut errors);
+        }
+        errors.check_penalty(&self.penalty, "penalty");
+        errors.check(
+            self.training.epochs > 0,
+            "training.epochs",
+            "must be positive",
+        );
+        errors.check(
+            self.training.batch_size > 0,
+            "training.batch_size",
+            "must be positive",
+        );
+        let clip = match self.training.grad_clip {
+            Some(GradClip::Value(clip)) | Some(GradClip::Norm(clip)) => clip,
+            None => 1f32,
+        };
+        errors.check(clip > 0f32, "training.grad_clip", "must be positive");
+
+        if errors.0.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid(errors.0.join("\n")))
+        }
+    }
+
+    /// The model, initialized from `training.seed`.
+    pub fn build_model(&self) -> Model {
+        self.model
+            .build(&mut StdRng::seed_from_u64(self.training.seed))
+    }
+
+    /// A trainer for a freshly built model, with the optimizer, scheduler, penalty & logging
+    /// configured. Fails only if the TensorBoard event file cannot be created.
+    pub fn build(&self) -> io::Result<Trainer> {
+        let model = self.build_model();
+        let optimizer = self.optimizer.build(&model);
+        let penalty = self
+            .penalty
+            .map(|penalty| Regularizer::new(penalty, model.layers()));
+
+        let mut trainer = Trainer::new(model, optimizer, self.loss, self.training.clone());
+        if let Some(scheduler) = &self.scheduler {
+            trainer = trainer.scheduler(scheduler.build(self.optimizer.lr()));
+        }
+        if let Some(penalty) = penalty {
+            trainer = trainer.penalty(penalty);
+        }
+        for log in self.logs.iter() {
+            trainer = trainer.logger(log.build());
+        }
+        if let Some(log_dir) = &self.tensorboard {
+            trainer = trainer.tensorboard(SummaryWriter::new(log_dir)?);
+        }
+        Ok(trainer)
+    }
+
+    /// The training & validation samples, checked against the model's shape.
+    pub fn read_data(&self) -> io::Result<(Vec<Sample>, Option<Vec<Sample>>)> {
+        let read = |path: &Path| -> io::Result<Vec<Sample>> {
+            let samples = read_csv(path, self.data.targets)?;
+            if samples.is_empty() {
+                return Err(invalid(format!("{}: no rows", path.display())));
+            }
+            check_samples(
+                &samples,
+                self.model.input,
+                self.model.output_size(),
+                self.loss,
+            )
+            .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?;
+            Ok(samples)
+        };
+        let train = read(&self.data.train)?;
+        let validation = self.data.validation.as_deref().map(read).transpose()?;
+        Ok((train, validation))
+    }
+}
+
+impl ModelConfig {
+    /// Dense layers of the given sizes, from input to output, with `activation` on the hidden
+    /// ones and a linear output layer.
+    pub fn mlp(sizes: &[usize], activation: Activation) -> Self {
+        let layers = sizes
+            .iter()
+            .skip(1)
+            .enumerate()
+            .map(|(i, &size)| LayerConfig::Dense {
+                size,
+                activation: if i + 2 == sizes.len() {
+                    Activation::Linear
+                } else {
+                    activation
+                },
+                init: None,
+            })
+            .collect();
+        ModelConfig {
+            input: sizes.first().copied().unwrap_or(0),
+            layers,
+        }
+    }
+
+    /// Number of outputs of the last layer.
+    pub fn output_size(&self) -> usize {
+        self.layers
+            .iter()
+            .fold(self.input, |width, layer| match layer {
+                LayerConfig::Dense { size, .. } => *size,
+                _ => width,
+            })
+    }
+
+    fn validate(&self, errors: &mut Errors) {
+        errors.check(self.input > 0, "model.input", "must be positive");
+        errors.check(
+            !self.layers.is_empty(),
+            "model.layers",
+            "needs at least one layer",
+        );
+        for (i, layer) in self.layers.iter().enumerate() {
+            match layer {
+                LayerConfig::Dense { size, .. } => errors.check(
+                    *size > 0,
+                    &format!("model.layers[{}].size", i),
+                    "must be positive",
+                ),
+                LayerConfig::Dropout { p } => errors.check(
+                    (0f32..1f32).contains(p),
+                    &format!("model.layers[{}].p", i),
+                    "must be in [0, 1)",
+                ),
+                LayerConfig::BatchNorm | LayerConfig::LayerNorm => {}
+            }
+        }
+    }
+
+    pub fn build<R: Rng>(&self, rng: &mut R) -> Model {
+        let mut width = self.input;
+        let mut sequential = Sequential::new();
+        for layer in self.layers.iter() {
+            sequential = match *layer {
+                LayerConfig::Dense {
+                    size,
+                    activation,
+                    init,
+                } => {
+                    let init = init.unwrap_or(if activation == Activation::Relu {
+                        Init::He
+                    } else {
+                        Init::Xavier
+                    });
+                    let dense = Layer::with_activation(width, size, activation);
+                    dense.reset_parameters(init, rng);
+                    width = size;
+                    sequential.push(dense)
+                }
+                LayerConfig::Dropout { p } => sequential.push(Dropout::new(p)),
+                LayerConfig::BatchNorm => sequential.push(BatchNorm1d::new(width)),
+                LayerConfig::LayerNorm => sequential.push(LayerNorm::new(width)),
+            };
+        }
+        Model::from(sequential)
+    }
+}
+
+impl OptimizerConfig {
+    pub fn lr(&self) -> f32 {
+        match *self {
+            OptimizerConfig::Sgd { lr, .. } | OptimizerConfig::Adam { lr, .. } => lr,
+        }
+    }
+
+    fn validate(&self, errors: &mut Errors) {
+        let lr = self.lr();
+        errors.check(
+            lr > 0f32 && lr.is_finite(),
+            "optimizer.lr",
+            "must be positive",
+        );
+        match self {
+            OptimizerConfig::Sgd {
+                momentum,
+                weight_decay,
+                ..
+            } => {
+                errors.check(
+                    (0f32..1f32).contains(momentum),
+                    "optimizer.momentum",
+                    "must be in [0, 1)",
+                );
+                errors.check_penalty(weight_decay, "optimizer.weight_decay");
+            }
+            OptimizerConfig::Adam {
+                betas,
+                weight_decay,
+                ..
+            } => {
+                if let Some((beta_1, beta_2)) = betas {
+                    errors.check(
+                        (0f32..1f32).contains(beta_1) && (0f32..1f32).contains(beta_2),
+                        "optimizer.betas",
+                        "must be in [0, 1)",
+                    );
+                }
+                errors.check_penalty(weight_decay, "optimizer.weight_decay");
+            }
+        }
+    }
+
+    pub fn build(&self, model: &Model) -> Box<dyn Optimizer> {
+        let decay = |penalty: &Option<Penalty>| {
+            penalty.map(|penalty| Regularizer::new(penalty, model.layers()))
+        };
+        match self {
+            OptimizerConfig::Sgd {
+                lr,
+                momentum,
+                weight_decay,
+            } => {
+                let mut sgd = Sgd::new(model.parameters(), *lr).momentum(*momentum);
+                if let Some(regularizer) = decay(weight_decay) {
+                    sgd = sgd.weight_decay(regularizer);
+                }
+                Box::new(sgd)
+            }
+            OptimizerConfig::Adam {
+                lr,
+                betas,
+                weight_decay,
+            } => {
+                let mut adam = Adam::new(model.parameters(), *lr);
+                if let Some((beta_1, beta_2)) = *betas {
+                    adam = adam.betas(beta_1, beta_2);
+                }
+                if let Some(regularizer) = decay(weight_decay) {
+                    adam = adam.weight_decay(regularizer);
+                }
+                Box::new(adam)
+            }
+        }
+    }
+}
+
+impl SchedulerConfig {
+    fn validate(&self, path: &str, errors: &mut Errors) {
+        let field = |name: &str| format!("{}.{}", path, name);
+        match self {
+            SchedulerConfig::Step { step_size, gamma } => {
+                errors.check(*step_size > 0, &field("step_size"), "must be positive");
+                errors.check(*gamma > 0f32, &field("gamma"), "must be positive");
+            }
+            SchedulerConfig::Exponential { gamma } => {
+                errors.check(*gamma > 0f32, &field("gamma"), "must be positive");
+            }
+            SchedulerConfig::Cosine {
+                t_max,
+                eta_min,
+                t_mult,
+            } => {
+                errors.check(*t_max > 0, &field("t_max"), "must be positive");
+                errors.check(*eta_min >= 0f32, &field("eta_min"), "must not be negative");
+                errors.check(t_mult != &Some(0), &field("t_mult"), "must be positive");
+            }
+            SchedulerConfig::LinearWarmup { warmup_steps, then } => {
+                errors.check(
+                    *warmup_steps > 0,
+                    &field("warmup_steps"),
+                    "must be positive",
+                );
+                if let Some(then) = then {
+                    then.validate(&field("then"), errors);
+                }
+            }
+            SchedulerConfig::OneCycle {
+                total_steps,
+                pct_start,
+                div_factors,
+            } => {
+                errors.check(
+                    *total_steps > 1,
+                    &field("total_steps"),
+                    "must be at least 2",
+                );
+                if let Some(pct_start) = pct_start {
+                    errors.check(
+                        *pct_start > 0f32 && *pct_start < 1f32,
+                        &field("pct_start"),
+                        "must be in (0, 1)",
+                    );
+                }
+                if let Some((div_factor, final_div_factor)) = div_factors {
+                    errors.check(
+                        *div_factor > 0f32 && *final_div_factor > 0f32,
+                        &field("div_factors"),
+                        "must be positive",
+                    );
+                }
+            }
+            SchedulerConfig::ReduceOnPlateau { factor, min_lr, .. } => {
+                if let Some(factor) = factor {
+                    errors.check(
+                        *factor > 0f32 && *factor < 1f32,
+                        &field("factor"),
+                        "must be in (0, 1)",
+                    );
+                }
+                if let Some(min_lr) = min_lr {
+                    errors.check(*min_lr >= 0f32, &field("min_lr"), "must not be negative");
+                }
+            }
+        }
+    }
+
+    pub fn build(&self, base_lr: f32) -> Box<dyn LrScheduler> {
+        match self {
+            SchedulerConfig::Step { step_size, gamma } => {
+                Box::new(StepLr::new(base_lr, *step_size, *gamma))
+            }
+            SchedulerConfig::Exponential { gamma } => Box::new(ExponentialLr::new(base_lr, *gamma)),
+            SchedulerConfig::Cosine {
+                t_max,
+                eta_min,
+                t_mult,
+            } => {
+                let cosine = CosineAnnealingLr::new(base_lr, *t_max, *eta_min);
+                match t_mult {
+                    Some(t_mult) => Box::new(cosine.warm_restarts(*t_mult)),
+                    None => Box::new(cosine),
+                }
+            }
+            SchedulerConfig::LinearWarmup { warmup_steps, then } => {
+                let warmup = LinearWarmup::new(base_lr, *warmup_steps);
+                match then {
+                    Some(then) => Box::new(warmup.then(then.build(base_lr))),
+                    None => Box::new(warmup),
+                }
+            }
+            SchedulerConfig::OneCycle {
+                total_steps,
+                pct_start,
+                div_factors,
+            } => {
+                let mut one_cycle = OneCycleLr::new(base_lr, *total_steps);
+                if let Some(pct_start) = pct_start {
+                    one_cycle = one_cycle.pct_start(*pct_start);
+                }
+                if let Some((div_factor, final_div_factor)) = div_factors {
+                    one_cycle = one_cycle.div_factors(*div_factor, *final_div_factor);
+                }
+                Box::new(one_cycle)
+            }
+            SchedulerConfig::ReduceOnPlateau {
+                factor,
+                patience,
+                threshold,
+                min_lr,
+            } => {
+                let mut plateau = ReduceLrOnPlateau::new(base_lr);
+                if let Some(factor) = factor {
+                    plateau = plateau.factor(*factor);
+                }
+                if let Some(patience) = patience {
+                    plateau = plateau.patience(*patience);
+                }
+                if let Some(threshold) = threshold {
+                    plateau = plateau.threshold(*threshold);
+                }
+                if let Some(min_lr) = min_lr {
+                    plateau = plateau.min_lr(*min_lr);
+                }
+                Box::new(plateau)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+loss = "cross_entropy"
+
+[model]
+input = 2
+layers = [
+    { type = "dense", size = 8, activation = "relu" },
+    { type = "dropout", p = 0.1 },
+    { type = "batch_norm" },
+    { type = "dense", size = 3, init = "uniform" },
+]
+
+[optimizer]
+type = "sgd"
+lr = 0.1
+momentum = 0.9
+weight_decay = { L2 = 0.01 }
+
+[scheduler]
+type = "linear_warmup"
+warmup_steps = 5
+then = { type = "step", step_size = 10, gamma = 0.5 }
+
+[data]
+train = "train.csv"
+
+[training]
+epochs = 3
+seed = 7
+
+[[logs]]
+path = "steps.jsonl"
+format = "jsonl"
+interval = "step"
+"#;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(TOML).unwrap();
+
+        assert_eq!(config.model.output_size(), 3);
+        assert_eq!(config.training.epochs, 3);
+        assert_eq!(config.training.batch_size, 32);
+        assert_eq!(config.data.targets, 1);
+        assert_eq!(config.logs[0].interval, LogInterval::Step);
+
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(Config::from_json(&json).unwrap(), config);
+
+        let trainer = config.build().unwrap();
+        // 2 * 8 + 8 dense, 2 * 8 batch norm, 8 * 3 + 3 dense
+        assert_eq!(trainer.model().parameters().len(), 67);
+        // warming up from 0.1 / 5
+        assert!((trainer.learning_rate() - 0.02).abs() < 1e-6);
+        // the seed makes the initialization reproducible
+        assert_eq!(config.build_model().state(), trainer.model().state());
+    }
+
+    #[test]
+    fn test_field_paths() {
+        let error = Config::from_toml(&TOML.replace("p = 0.1", "p = 1.5")).unwrap_err();
+        assert_eq!(error.to_string(), "model.layers[1].p: must be in [0, 1)");
+
+        let error = Config::from_toml(&TOML.replace("gamma = 0.5", "gamma = -0.5")).unwrap_err();
+        assert_eq!(error.to_string(), "scheduler.then.gamma: must be positive");
+
+        let error = Config::from_toml(&TOML.replace("epochs = 3", "epochs = 0\nbatch_size = 0"))
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "training.epochs: must be positive\ntraining.batch_size: must be positive"
+        );
+
+        let error = Config::from_toml(&TOML.replace("seed = 7", "sede = 7")).unwrap_err();
+        assert!(
+            error.to_string().starts_with("training.sede: "),
+            "{}",
+            error
+        );
+
+        let error = Config::from_json(r#"{"model": {"input": "two"}}"#).unwrap_err();
+        assert!(error.to_string().starts_with("model.input: "), "{}", error);
+    }
+
+    #[test]
+    fn test_mlp() {
+        let model = ModelConfig::mlp(&[3, 4, 1], Activation::Tanh);
+
+        assert_eq!(model.input, 3);
+        assert_eq!(model.output_size(), 1);
+        assert_eq!(
+            model.layers[1],
+            LayerConfig::Dense {
+                size: 1,
+                activation: Activation::Linear,
+                init: None
+            }
+        );
+    }
+}
@@ -0,0 +1,375 @@
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+use rand::Rng;
+
+/// 1D convolution over channel-major input, i.e. `[c0 t0, c0 t1, ..., c1 t0, ...]`.
+///
+/// The sequence length is inferred from the input length and `in_channels`,
+/// the output is laid out the same way with `out_channels` channels.
+pub struct Conv1d {
+    in_channels: usize,
+    out_channels: usize,
+    kernel_size: usize,
+    stride: usize,
+    padding: usize,
+    dilation: usize,
+    // Indexed as [out_channel][in_channel][kernel position]
+    w: Vec<RcScalar>,
+    b: Vec<RcScalar>,
+}
+
+impl Conv1d {
+    pub fn new(in_channels: usize, out_channels: usize, kernel_size: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        let bound = 1f32 / ((in_channels * kernel_size) as f32).sqrt();
+        Conv1d {
+            in_channels,
+            out_channels,
+            kernel_size,
+            stride: 1,
+            padding: 0,
+            dilation: 1,
+            w: (0..out_channels * in_channels * kernel_size)
+                .map(|_| RcScalar::new(Scalar::new(rng.gen_range(-bound..bound))))
+                .collect(),
+            b: (0..out_channels)
+                .map(|_| RcScalar::new(Scalar::new(0f32)))
+                .collect(),
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn dilation(mut self, dilation: usize) -> Self {
+        assert!(dilation > 0);
+        self.dilation = dilation;
+        self
+    }
+
+    pub fn output_len(&self, input_len: usize) -> usize {
+        let span = self.dilation * (self.kernel_size - 1) + 1;
+        assert!(
+            input_len + 2 * self.padding >= span,
+            "input of length {} is shorter than the kernel span {}",
+            input_len,
+            span
+        );
+        (input_len + 2 * self.padding - span) / self.stride + 1
+    }
+}
+
+impl Module for Conv1d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        assert_eq!(input.len() % self.in_channels, 0);
+        let len = input.len() / self.in_channels;
+        let out_len = self.output_len(len);
+
+        let mut output = Vec::with_capacity(self.out_channels * out_len);
+        for oc in 0..self.out_channels {
+            for t in 0..out_len {
+                let mut acc = RcScalar::clone(&self.b[oc]);
+                for ic in 0..self.in_channels {
+                    for k in 0..self.kernel_size {
+                        // Position in the unpadded input, skipped when it falls in the zero padding
+                        let pos =
+                            (t * self.stride + k * self.dilation) as isize - self.padding as isize;
+                        if pos < 0 || pos >= len as isize {
+                            continue;
+                        }
+                        let w = &self.w[(oc * self.in_channels + ic) * self.kernel_size + k];
+                        acc = acc
+                            + RcScalar::clone(w) * RcScalar::clone(&input[ic * len + pos as usize]);
+                    }
+                }
+                output.push(acc);
+            }
+        }
+        output
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.w.iter().chain(self.b.iter()).cloned().collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.b.clone()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "Conv1d({}, {}, kernel_size={}, stride={}, padding={}, dilation={})",
+            self.in_channels,
+            self.out_channels,
+            self.kernel_size,
+            self.stride,
+            self.padding,
+            self.dilation
+        )
+    }
+}
+
+/// 2D convolution over channel-major (CHW) input of a fixed spatial size.
+///
+/// With `groups > 1` the channels are split into that many groups, and each output channel
+/// only sees the input channels of its own group.
+pub struct Conv2d {
+    in_channels: usize,
+    out_channels: usize,
+    kernel_size: usize,
+    input_size: (usize, usize),
+    stride: usize,
+    padding: usize,
+    groups: usize,
+    // Indexed as [out_channel][in_channel within the group][kernel row][kernel column]
+    w: Vec<RcScalar>,
+    b: Vec<RcScalar>,
+}
+
+impl Conv2d {
+    pub fn new(
+        in_channels: usize,
+        out_channels: usize,
+        kernel_size: usize,
+        input_size: (usize, usize),
+    ) -> Self {
+        let mut conv = Conv2d {
+            in_channels,
+            out_channels,
+            kernel_size,
+            input_size,
+            stride: 1,
+            padding: 0,
+            groups: 1,
+            w: Vec::new(),
+            b: Vec::new(),
+        };
+        conv.init_parameters();
+        conv
+    }
+
+    fn init_parameters(&mut self) {
+        let mut rng = rand::thread_rng();
+        let fan_in = self.in_channels / self.groups * self.kernel_size * self.kernel_size;
+        let bound = 1f32 / (fan_in as f32).sqrt();
+        self.w = (0..self.out_channels * fan_in)
+            .map(|_| RcScalar::new(Scalar::new(rng.gen_range(-bound..bound))))
+            .collect();
+        self.b = (0..self.out_channels)
+            .map(|_| RcScalar::new(Scalar::new(0f32)))
+            .collect();
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.stride = stride;
+        self
+    }
+
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn groups(mut self, groups: usize) -> Self {
+        assert!(
+            groups > 0
+                && self.in_channels.is_multiple_of(groups)
+                && self.out_channels.is_multiple_of(groups),
+            "channels must be divisible by groups"
+        );
+        self.groups = groups;
+        self.init_parameters();
+        self
+    }
+
+    /// Spatial size (height, width) of every output channel.
+    pub fn output_size(&self) -> (usize, usize) {
+        let (height, width) = self.input_size;
+        assert!(
+            height + 2 * self.padding >= self.kernel_size
+                && width + 2 * self.padding >= self.kernel_size,
+            "input is smaller than the kernel"
+        );
+        (
+            (height + 2 * self.padding - self.kernel_size) / self.stride + 1,
+            (width + 2 * self.padding - self.kernel_size) / self.stride + 1,
+        )
+    }
+}
+
+impl Module for Conv2d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let (height, width) = self.input_size;
+        assert_eq!(input.len(), self.in_channels * height * width);
+        let (out_height, out_width) = self.output_size();
+        let in_per_group = self.in_channels / self.groups;
+        let out_per_group = self.out_channels / self.groups;
+        let k = self.kernel_size;
+
+        let mut output = Vec::with_capacity(self.out_channels * out_height * out_width);
+        for oc in 0..self.out_channels {
+            let group = oc / out_per_group;
+            for oy in 0..out_height {
+                for ox in 0..out_width {
+                    let mut acc = RcScalar::clone(&self.b[oc]);
+                    for icg in 0..in_per_group {
+                        let ic = group * in_per_group + icg;
+                        for ky in 0..k {
+                            // Positions in the unpadded input, skipped when they fall in the zero padding
+                            let y = (oy * self.stride + ky) as isize - self.padding as isize;
+                            if y < 0 || y >= height as isize {
+                                continue;
+                            }
+                            for kx in 0..k {
+                                let x = (ox * self.stride + kx) as isize - self.padding as isize;
+                                if x < 0 || x >= width as isize {
+                                    continue;
+                                }
+                                let w = &self.w[((oc * in_per_group + icg) * k + ky) * k + kx];
+                                let pixel = &input[(ic * height + y as usize) * width + x as usize];
+                                acc = acc + RcScalar::clone(w) * RcScalar::clone(pixel);
+                            }
+                        }
+                    }
+                    output.push(acc);
+                }
+            }
+        }
+        output
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.w.iter().chain(self.b.iter()).cloned().collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.b.clone()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "Conv2d({}, {}, kernel_size={}, stride={}, padding={}, groups={})",
+            self.in_channels,
+            self.out_channels,
+            self.kernel_size,
+            self.stride,
+            self.padding,
+            self.groups
+        )
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        let (height, width) = self.input_size;
+        Some(self.in_channels * height * width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::{max_gradient_error, max_parameter_gradient_error};
+
+    #[test]
+    fn test_feed_forward() {
+        let conv = Conv1d::new(1, 1, 2);
+        conv.w[0].0.borrow_mut().data = 1.0;
+        conv.w[1].0.borrow_mut().data = -1.0;
+        conv.b[0].0.borrow_mut().data = 0.5;
+
+        let output: Vec<f32> = conv
+            .feed_foward(RcScalar::vec_from(&[1.0, 3.0, 2.0, 5.0]))
+            .iter()
+            .map(|y| y.0.borrow().data)
+            .collect();
+
+        assert_eq!(output, vec![-1.5, 1.5, -2.5]);
+        assert_eq!(conv.parameters().len(), 3);
+    }
+
+    #[test]
+    fn test_output_len() {
+        let conv = Conv1d::new(2, 3, 3).stride(2).padding(1).dilation(2);
+        let output = conv.feed_foward(RcScalar::vec_from(&[0.5; 2 * 9]));
+
+        // (9 + 2*1 - 2*(3-1) - 1) / 2 + 1 = 4
+        assert_eq!(conv.output_len(9), 4);
+        assert_eq!(output.len(), 3 * 4);
+    }
+
+    #[test]
+    fn test_backward() {
+        let conv = Conv1d::new(2, 2, 3).stride(2).padding(1).dilation(2);
+        let xs: Vec<f32> = (0..14).map(|i| (i as f32 * 0.37).sin()).collect();
+        let f = |input: Vec<RcScalar>| {
+            conv.feed_foward(input)
+                .into_iter()
+                .enumerate()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, (i, y)| {
+                    acc + y.tanh() * (1f32 + i as f32)
+                })
+        };
+
+        assert!(max_gradient_error(f, &xs, 1e-2) < 1e-2);
+    }
+
+    #[test]
+    fn test_conv2d_feed_forward() {
+        let conv = Conv2d::new(1, 1, 2, (3, 3));
+        for (w, value) in conv.w.iter().zip([1.0, 0.0, 0.0, -1.0]) {
+            w.0.borrow_mut().data = value;
+        }
+
+        let output: Vec<f32> = conv
+            .feed_foward(RcScalar::vec_from(&[
+                1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0,
+            ]))
+            .iter()
+            .map(|y| y.0.borrow().data)
+            .collect();
+
+        assert_eq!(output, vec![-4.0, -4.0, -4.0, -4.0]);
+    }
+
+    #[test]
+    fn test_conv2d_output_size() {
+        let conv = Conv2d::new(4, 6, 3, (7, 5)).stride(2).padding(1).groups(2);
+        let output = conv.feed_foward(RcScalar::vec_from(&[0.5; 4 * 7 * 5]));
+
+        assert_eq!(conv.output_size(), (4, 3));
+        assert_eq!(output.len(), 6 * 4 * 3);
+        // 6 output channels * 2 input channels per group * 3*3 + 6 biases
+        assert_eq!(conv.parameters().len(), 114);
+    }
+
+    #[test]
+    fn test_conv2d_backward() {
+        let conv = Conv2d::new(4, 2, 3, (4, 5)).stride(2).padding(1).groups(2);
+        let xs: Vec<f32> = (0..4 * 4 * 5).map(|i| (i as f32 * 0.37).sin()).collect();
+        let loss = |input: Vec<RcScalar>| {
+            conv.feed_foward(input)
+                .into_iter()
+                .enumerate()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, (i, y)| {
+                    acc + y.tanh() * (1f32 + i as f32)
+                })
+        };
+
+        assert!(max_gradient_error(loss, &xs, 1e-2) < 1e-2);
+        assert!(
+            max_parameter_gradient_error(
+                || loss(RcScalar::vec_from(&xs)),
+                &conv.parameters(),
+                1e-2
+            ) < 1e-2
+        );
+    }
+}
@@ -0,0 +1,131 @@
+use crate::loss::Loss;
+use crate::trainer::Sample;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+fn invalid(line: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+/// Reads comma-separated rows of numbers, skipping blank lines and a header row if the first
+/// line is not numeric. Every row must have the same number of columns.
+pub fn read_rows<R: BufRead>(reader: R) -> io::Result<Vec<Vec<f32>>> {
+    let mut rows: Vec<Vec<f32>> = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let values: Result<Vec<f32>, _> = line.split(',').map(|v| v.trim().parse()).collect();
+        let values = match values {
+            Ok(values) => values,
+            Err(_) if i == 0 => continue,
+            Err(e) => return Err(invalid(i + 1, format!("{} in {:?}", e, line))),
+        };
+        if let Some(first) = rows.first() {
+            if first.len() != values.len() {
+                return Err(invalid(
+                    i + 1,
+                    format!("expected {} columns, found {}", first.len(), values.len()),
+                ));
+            }
+        }
+        rows.push(values);
+    }
+    Ok(rows)
+}
+
+/// Splits each row into inputs & its last `num_targets` columns as targets.
+pub fn to_samples(rows: Vec<Vec<f32>>, num_targets: usize) -> io::Result<Vec<Sample>> {
+    rows.into_iter()
+        .enumerate()
+        .map(|(i, mut input)| {
+            if input.len() <= num_targets {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "row {} has {} columns, need more than {} targets",
+                        i + 1,
+                        input.len(),
+                        num_targets
+                    ),
+                ));
+            }
+            let target = input.split_off(input.len() - num_targets);
+            Ok(Sample { input, target })
+        })
+        .collect()
+}
+
+/// Reads a CSV file of samples, the targets being the last `num_targets` columns.
+pub fn read_csv<P: AsRef<Path>>(path: P, num_targets: usize) -> io::Result<Vec<Sample>> {
+    let file = File::open(path.as_ref())
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.as_ref().display(), e)))?;
+    to_samples(read_rows(BufReader::new(file))?, num_targets)
+}
+
+/// Checks that every sample fits a model with `nin` inputs & `nout` outputs trained with `loss`:
+/// `nout` targets for `Mse`, a single class index below `nout` for `CrossEntropy`.
+pub fn check_samples(data: &[Sample], nin: usize, nout: usize, loss: Loss) -> io::Result<()> {
+    for (i, sample) in data.iter().enumerate() {
+        if sample.input.len() != nin {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "row {} has {} inputs, the model takes {}",
+                    i + 1,
+                    sample.input.len(),
+                    nin
+                ),
+            ));
+        }
+        if !loss.fits(&sample.target, nout) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "row {} has target {:?}, which does not fit {} outputs with {:?} loss",
+                    i + 1,
+                    sample.target,
+                    nout,
+                    loss
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_rows() {
+        let csv = "x1,x2,y\n1, 2,0\n\n-1.5,0,1\n";
+        let samples = to_samples(read_rows(csv.as_bytes()).unwrap(), 1).unwrap();
+
+        assert_eq!(
+            samples,
+            vec![
+                Sample {
+                    input: vec![1.0, 2.0],
+                    target: vec![0.0]
+                },
+                Sample {
+                    input: vec![-1.5, 0.0],
+                    target: vec![1.0]
+                },
+            ]
+        );
+
+        let error = read_rows("1,2\n3,x\n".as_bytes()).unwrap_err();
+        assert!(error.to_string().starts_with("line 2:"), "{}", error);
+        let error = read_rows("1,2\n3\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected 2 columns, found 1");
+        assert!(to_samples(vec![vec![1.0]], 1).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use crate::module::Module;
+use crate::scalar::RcScalar;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+
+/// Zeroes each input with probability `p` while training, scaling the survivors by `1 / (1 - p)`
+/// so that evaluation (where dropout is the identity) sees the same expected activations.
+///
+/// Masks are drawn from the module's own generator: seed it with `with_seed` or `Module::seed`
+/// for reproducible masks. `Trainer` reseeds it before every step.
+pub struct Dropout {
+    p: f32,
+    training: bool,
+    rng: RefCell<StdRng>,
+}
+
+impl Dropout {
+    /// Seeded from the system's entropy.
+    pub fn new(p: f32) -> Self {
+        Dropout::with_rng(p, StdRng::from_entropy())
+    }
+
+    pub fn with_seed(p: f32, seed: u64) -> Self {
+        Dropout::with_rng(p, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(p: f32, rng: StdRng) -> Self {
+        assert!(
+            (0f32..1f32).contains(&p),
+            "dropout probability must be in [0, 1)"
+        );
+        Dropout {
+            p,
+            training: true,
+            rng: RefCell::new(rng),
+        }
+    }
+}
+
+impl Module for Dropout {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        if !self.training || self.p == 0f32 {
+            return input;
+        }
+        let mut rng = self.rng.borrow_mut();
+        let scale = 1f32 / (1f32 - self.p);
+        input
+            .into_iter()
+            .map(|x| {
+                if rng.gen::<f32>() < self.p {
+                    x * 0f32
+                } else {
+                    x * scale
+                }
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!("Dropout(p={})", self.p)
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn seed(&self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+
+    #[test]
+    fn test_feed_forward() {
+        let mut dropout = Dropout::new(0.5);
+        let x: Vec<RcScalar> = (0..1000)
+            .map(|_| RcScalar::new(Scalar::new(1f32)))
+            .collect();
+
+        let output: Vec<RcScalar> = dropout.feed_foward(x.clone());
+        let zeros = output.iter().filter(|y| y.0.borrow().data == 0.0).count();
+        assert!(zeros > 400 && zeros < 600);
+        assert!(output
+            .iter()
+            .all(|y| y.0.borrow().data == 0.0 || y.0.borrow().data == 2.0));
+
+        dropout.set_training(false);
+        let output: Vec<RcScalar> = dropout.feed_foward(x.clone());
+        assert_eq!(output, x);
+    }
+
+    #[test]
+    fn test_seed() {
+        let mask = |dropout: &Dropout| -> Vec<f32> {
+            let x = RcScalar::vec_from(&[1f32; 20]);
+            dropout
+                .feed_foward(x)
+                .iter()
+                .map(|y| y.0.borrow().data)
+                .collect()
+        };
+        let dropout = Dropout::with_seed(0.5, 7);
+        let first = mask(&dropout);
+        assert_ne!(mask(&dropout), first);
+        assert_eq!(mask(&Dropout::with_seed(0.5, 7)), first);
+        dropout.seed(7);
+        assert_eq!(mask(&dropout), first);
+    }
+}
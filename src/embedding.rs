@@ -0,0 +1,205 @@
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+use rand::Rng;
+
+/// Lookup table of `num_embeddings` learnable vectors of size `embedding_dim`.
+///
+/// Only the looked-up rows become part of the graph, so `backwards()` only writes gradients into
+/// those; `parameters_for` returns just their scalars for a sparse optimizer step.
+pub struct Embedding {
+    embedding_dim: usize,
+    // Indexed as [row][dimension]
+    weight: Vec<Vec<RcScalar>>,
+    padding_idx: Option<usize>,
+    max_norm: Option<f32>,
+}
+
+impl Embedding {
+    pub fn new(num_embeddings: usize, embedding_dim: usize) -> Self {
+        let mut rng = rand::thread_rng();
+        Embedding {
+            embedding_dim,
+            weight: (0..num_embeddings)
+                .map(|_| {
+                    (0..embedding_dim)
+                        .map(|_| RcScalar::new(Scalar::new(rng.gen_range(-1.0..1.0))))
+                        .collect()
+                })
+                .collect(),
+            padding_idx: None,
+            max_norm: None,
+        }
+    }
+
+    /// The row at `padding_idx` is all zeros and never receives a gradient.
+    pub fn padding_idx(mut self, padding_idx: usize) -> Self {
+        assert!(padding_idx < self.weight.len());
+        for w in self.weight[padding_idx].iter() {
+            w.0.borrow_mut().data = 0f32;
+        }
+        self.padding_idx = Some(padding_idx);
+        self
+    }
+
+    /// Rows looked up with an L2 norm above `max_norm` are rescaled in place to that norm.
+    pub fn max_norm(mut self, max_norm: f32) -> Self {
+        assert!(max_norm > 0f32);
+        self.max_norm = Some(max_norm);
+        self
+    }
+
+    pub fn num_embeddings(&self) -> usize {
+        self.weight.len()
+    }
+
+    pub fn embedding_dim(&self) -> usize {
+        self.embedding_dim
+    }
+
+    fn renorm(&self, index: usize) {
+        if let Some(max_norm) = self.max_norm {
+            let row = &self.weight[index];
+            let norm = row
+                .iter()
+                .map(|w| w.0.borrow().data.powi(2))
+                .sum::<f32>()
+                .sqrt();
+            if norm > max_norm {
+                for w in row.iter() {
+                    w.0.borrow_mut().data *= max_norm / norm;
+                }
+            }
+        }
+    }
+
+    /// Concatenated vectors of the given rows.
+    pub fn lookup(&self, indices: &[usize]) -> Vec<RcScalar> {
+        indices
+            .iter()
+            .flat_map(|&index| {
+                assert!(
+                    index < self.weight.len(),
+                    "index {} out of range for {} embeddings",
+                    index,
+                    self.weight.len()
+                );
+                if self.padding_idx == Some(index) {
+                    return (0..self.embedding_dim)
+                        .map(|_| RcScalar::new(Scalar::new(0f32)))
+                        .collect();
+                }
+                self.renorm(index);
+                self.weight[index].clone()
+            })
+            .collect()
+    }
+
+    /// Scalars of the given rows only (deduplicated, padding row excluded).
+    pub fn parameters_for(&self, indices: &[usize]) -> Vec<RcScalar> {
+        let mut rows: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&index| self.padding_idx != Some(index))
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        rows.into_iter()
+            .flat_map(|index| self.weight[index].clone())
+            .collect()
+    }
+}
+
+impl Module for Embedding {
+    /// Every input value is taken as a row index, e.g. a label-encoded categorical column.
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let indices: Vec<usize> = input
+            .iter()
+            .map(|x| {
+                let value = x.0.borrow().data;
+                assert!(
+                    value >= 0f32 && value.fract() == 0f32,
+                    "embedding index must be a non-negative integer, got {}",
+                    value
+                );
+                value as usize
+            })
+            .collect();
+        self.lookup(&indices)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.weight.iter().flatten().cloned().collect()
+    }
+
+    fn name(&self) -> String {
+        format!("Embedding({}, {})", self.weight.len(), self.embedding_dim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sum(xs: Vec<RcScalar>) -> RcScalar {
+        xs.into_iter()
+            .fold(RcScalar::new(Scalar::new(0f32)), |acc, x| acc + x)
+    }
+
+    #[test]
+    fn test_lookup() {
+        let embedding = Embedding::new(5, 3);
+
+        let output = embedding.lookup(&[4, 1, 4]);
+
+        assert_eq!(output.len(), 9);
+        assert_eq!(output[0], embedding.weight[4][0]);
+        assert_eq!(output[3], embedding.weight[1][0]);
+        assert_eq!(embedding.parameters().len(), 15);
+        assert_eq!(embedding.parameters_for(&[4, 1, 4]).len(), 6);
+    }
+
+    #[test]
+    fn test_sparse_gradient() {
+        let embedding = Embedding::new(4, 2);
+        let input = vec![
+            RcScalar::new(Scalar::new(2f32)),
+            RcScalar::new(Scalar::new(0f32)),
+            RcScalar::new(Scalar::new(2f32)),
+        ];
+
+        sum(embedding.feed_foward(input)).backwards();
+
+        let grads: Vec<f32> = embedding
+            .parameters()
+            .iter()
+            .map(|w| w.0.borrow().grad)
+            .collect();
+        assert_eq!(grads, vec![1.0, 1.0, 0.0, 0.0, 2.0, 2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_padding_idx() {
+        let embedding = Embedding::new(3, 2).padding_idx(0);
+
+        let output = embedding.lookup(&[0, 1]);
+        sum(output.clone()).backwards();
+
+        assert_eq!(output[0].0.borrow().data, 0.0);
+        assert_eq!(output[1].0.borrow().data, 0.0);
+        assert_eq!(embedding.weight[0][0].0.borrow().grad, 0.0);
+        assert_eq!(embedding.weight[1][0].0.borrow().grad, 1.0);
+        assert_eq!(embedding.parameters_for(&[0, 1]).len(), 2);
+    }
+
+    #[test]
+    fn test_max_norm() {
+        let embedding = Embedding::new(2, 2).max_norm(1.0);
+        embedding.weight[1][0].0.borrow_mut().data = 3.0;
+        embedding.weight[1][1].0.borrow_mut().data = 4.0;
+
+        let output = embedding.lookup(&[1]);
+
+        assert!((output[0].0.borrow().data - 0.6).abs() < 1e-6);
+        assert!((output[1].0.borrow().data - 0.8).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,20 @@
+use crate::module::Module;
+use crate::scalar::RcScalar;
+
+/// Marks the boundary between channel-major feature maps (from convolution & pooling layers)
+/// and dense layers. Feature maps are already stored flat, so the values pass through as they are.
+pub struct Flatten;
+
+impl Module for Flatten {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        input
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        "Flatten".to_string()
+    }
+}
@@ -0,0 +1,86 @@
+use crate::scalar::RcScalar;
+
+/// Gradient of the scalar function `f` at `xs`, as computed by `backwards()`.
+pub fn analytic_gradient<F: Fn(Vec<RcScalar>) -> RcScalar>(f: F, xs: &[f32]) -> Vec<f32> {
+    let inputs = RcScalar::vec_from(xs);
+    f(inputs.clone()).backwards();
+    inputs.iter().map(|x| x.0.borrow().grad).collect()
+}
+
+/// Gradient of the scalar function `f` at `xs`, estimated by central differences with step `h`.
+pub fn numeric_gradient<F: Fn(Vec<RcScalar>) -> RcScalar>(f: F, xs: &[f32], h: f32) -> Vec<f32> {
+    (0..xs.len())
+        .map(|i| {
+            let mut plus = xs.to_vec();
+            plus[i] += h;
+            let mut minus = xs.to_vec();
+            minus[i] -= h;
+            let f_plus = f(RcScalar::vec_from(&plus)).0.borrow().data;
+            let f_minus = f(RcScalar::vec_from(&minus)).0.borrow().data;
+            (f_plus - f_minus) / (2f32 * h)
+        })
+        .collect()
+}
+
+/// Largest absolute difference between the analytic & numeric gradients of `f` at `xs`.
+pub fn max_gradient_error<F: Fn(Vec<RcScalar>) -> RcScalar>(f: F, xs: &[f32], h: f32) -> f32 {
+    let analytic = analytic_gradient(&f, xs);
+    let numeric = numeric_gradient(&f, xs, h);
+    analytic
+        .iter()
+        .zip(numeric.iter())
+        .map(|(a, n)| (a - n).abs())
+        .fold(0f32, f32::max)
+}
+
+/// Like `max_gradient_error`, but w.r.t. `parameters` used inside `f` (e.g. a module's weights),
+/// which are nudged in place & restored afterwards.
+pub fn max_parameter_gradient_error<F: Fn() -> RcScalar>(
+    f: F,
+    parameters: &[RcScalar],
+    h: f32,
+) -> f32 {
+    for p in parameters.iter() {
+        p.0.borrow_mut().grad = 0f32;
+    }
+    f().backwards();
+
+    parameters
+        .iter()
+        .map(|p| {
+            let (data, analytic) = {
+                let borrowed = p.0.borrow();
+                (borrowed.data, borrowed.grad)
+            };
+            p.0.borrow_mut().data = data + h;
+            let f_plus = f().0.borrow().data;
+            p.0.borrow_mut().data = data - h;
+            let f_minus = f().0.borrow().data;
+            p.0.borrow_mut().data = data;
+            (analytic - (f_plus - f_minus) / (2f32 * h)).abs()
+        })
+        .fold(0f32, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+
+    #[test]
+    fn test_max_gradient_error() {
+        let f = |xs: Vec<RcScalar>| RcScalar::clone(&xs[0]) * RcScalar::clone(&xs[1]).tanh();
+
+        assert_eq!(analytic_gradient(f, &[2.0, 0.0]), vec![0.0, 2.0]);
+        assert!(max_gradient_error(f, &[0.3, -1.2], 1e-2) < 1e-3);
+    }
+
+    #[test]
+    fn test_max_parameter_gradient_error() {
+        let w = RcScalar::new(Scalar::new(0.7));
+        let f = || (RcScalar::clone(&w) * 2f32).tanh();
+
+        assert!(max_parameter_gradient_error(f, &[RcScalar::clone(&w)], 1e-2) < 1e-3);
+        assert_eq!(w.0.borrow().data, 0.7);
+    }
+}
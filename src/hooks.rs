@@ -0,0 +1,303 @@
+use crate::scalar::{Ops, RcScalar};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Receives a module's input & output for one sample, see `Sequential::register_forward_hook`.
+pub type ForwardHook = dyn FnMut(&[RcScalar], &[RcScalar]);
+
+/// Receives the gradient of a module's output for one sample & may return a replacement, see
+/// `Sequential::register_backward_hook`.
+pub type BackwardHook = dyn FnMut(&[f32]) -> Option<Vec<f32>>;
+
+/// Receives what a backward pass adds to a scalar's gradient & may return a replacement, see
+/// `RcScalar::register_hook`.
+pub type GradHook = dyn FnMut(f32) -> Option<f32>;
+
+type Entries<H> = Vec<(usize, Rc<RefCell<Box<H>>>)>;
+
+/// Hooks of one kind, in the order they were registered.
+///
+/// Clones share the same hooks.
+pub struct Hooks<H: ?Sized>(Rc<RefCell<Entries<H>>>);
+
+impl<H: ?Sized + 'static> Hooks<H> {
+    pub fn new() -> Self {
+        Hooks(Rc::new(RefCell::new(Vec::new())))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn add(&self, hook: Box<H>) -> HookHandle {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        self.0.borrow_mut().push((id, Rc::new(RefCell::new(hook))));
+        let entries: Weak<RefCell<Entries<H>>> = Rc::downgrade(&self.0);
+        HookHandle(Box::new(move || {
+            if let Some(entries) = entries.upgrade() {
+                entries.borrow_mut().retain(|(hook_id, _)| *hook_id != id);
+            }
+        }))
+    }
+
+    /// The hooks as they are now, so they can be called without holding on to the list.
+    pub(crate) fn snapshot(&self) -> Vec<Rc<RefCell<Box<H>>>> {
+        self.0
+            .borrow()
+            .iter()
+            .map(|(_, hook)| Rc::clone(hook))
+            .collect()
+    }
+}
+
+impl<H: ?Sized + 'static> Default for Hooks<H> {
+    fn default() -> Self {
+        Hooks::new()
+    }
+}
+
+impl<H: ?Sized> Clone for Hooks<H> {
+    fn clone(&self) -> Self {
+        Hooks(Rc::clone(&self.0))
+    }
+}
+
+impl<H: ?Sized> fmt::Debug for Hooks<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Hooks({})", self.0.borrow().len())
+    }
+}
+
+// Hooks are not part of a scalar's value
+impl<H: ?Sized> PartialEq for Hooks<H> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+/// Unregisters a hook with `remove`. Dropping the handle keeps the hook.
+pub struct HookHandle(Box<dyn FnOnce()>);
+
+impl HookHandle {
+    pub fn remove(self) {
+        (self.0)()
+    }
+}
+
+impl fmt::Debug for HookHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "HookHandle")
+    }
+}
+
+/// The hooks of one module of a `Sequential`.
+#[derive(Default)]
+pub(crate) struct ModuleHooks {
+    pub forward: Hooks<ForwardHook>,
+    pub backward: Hooks<BackwardHook>,
+}
+
+impl ModuleHooks {
+    /// Calls the forward hooks & routes the outputs through the backward hooks, if there are any.
+    pub fn apply(&self, input: &[RcScalar], output: Vec<RcScalar>) -> Vec<RcScalar> {
+        for hook in self.forward.snapshot() {
+            (*hook.borrow_mut())(input, &output);
+        }
+        if self.backward.is_empty() {
+            output
+        } else {
+            gate(output, self.backward.clone())
+        }
+    }
+}
+
+/// Copies of `outputs` whose gradients are held back until all of them are complete, then
+/// passed through `hooks` & on to `outputs` together.
+///
+/// Each copy is an identity of its output that also depends on one barrier node over all of
+/// `outputs`, so the backward pass reaches the barrier after every copy & before any of
+/// `outputs`. In `backwards()` the copies' gradient hooks collect their gradients instead of
+/// passing them on, the barrier's hook hands them out. `autograd`, which calls no hooks, sees
+/// plain identities.
+fn gate(outputs: Vec<RcScalar>, hooks: Hooks<BackwardHook>) -> Vec<RcScalar> {
+    let grads = Rc::new(RefCell::new(vec![0f32; outputs.len()]));
+    let barrier = RcScalar::from_op(0f32, outputs.clone(), Ops::Barrier);
+    let gated = outputs
+        .iter()
+        .enumerate()
+        .map(|(i, output)| {
+            let copy = RcScalar::from_op(
+                output.0.borrow().data,
+                vec![RcScalar::clone(output), RcScalar::clone(&barrier)],
+                Ops::Identity,
+            );
+            let grads = Rc::clone(&grads);
+            copy.register_hook(move |grad| {
+                grads.borrow_mut()[i] += grad;
+                Some(0f32)
+            });
+            copy
+        })
+        .collect();
+
+    barrier.register_hook(move |_| {
+        let mut grad: Vec<f32> = grads.replace(vec![0f32; outputs.len()]);
+        for hook in hooks.snapshot() {
+            if let Some(replaced) = (*hook.borrow_mut())(&grad) {
+                assert_eq!(
+                    replaced.len(),
+                    grad.len(),
+                    "backward hook returned {} gradients for {} outputs",
+                    replaced.len(),
+                    grad.len()
+                );
+                grad = replaced;
+            }
+        }
+        for (output, g) in outputs.iter().zip(grad) {
+            let mut output = output.0.borrow_mut();
+            if output.requires_grad {
+                output.grad += g;
+            }
+        }
+        Some(0f32)
+    });
+    gated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autograd::gradient;
+    use crate::model::Model;
+    use crate::scalar::Scalar;
+    use crate::sequential::Sequential;
+
+    #[test]
+    fn test_grad_hook() {
+        let x = RcScalar::new(Scalar::new(3f32));
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let seen_by_hook = Rc::clone(&seen);
+        let handle = x.register_hook(move |grad| {
+            seen_by_hook.borrow_mut().push(grad);
+            None
+        });
+        let doubled = x.register_hook(|grad| Some(2f32 * grad));
+
+        x.square().backwards();
+        assert_eq!(x.0.borrow().grad, 12f32);
+        // Hooks see what a pass adds, not the gradient accumulated so far
+        x.square().backwards();
+        assert_eq!(x.0.borrow().grad, 24f32);
+        assert_eq!(*seen.borrow(), vec![6f32, 6f32]);
+
+        handle.remove();
+        doubled.remove();
+        x.square().backwards();
+        assert_eq!(x.0.borrow().grad, 30f32);
+        assert_eq!(seen.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_module_hooks() {
+        let model_a = Model::from(Sequential::new().dense(2, 2).tanh().dense(2, 1));
+        let x = || {
+            vec![
+                RcScalar::new(Scalar::new(0.5f32)),
+                RcScalar::new(Scalar::new(-1f32)),
+            ]
+        };
+        let reference = model_a.feed_foward(x());
+        reference[0].backwards();
+        let reference_grads: Vec<f32> = model_a
+            .parameters()
+            .iter()
+            .map(|p| p.0.borrow().grad)
+            .collect();
+        for p in model_a.parameters() {
+            p.0.borrow_mut().grad = 0f32;
+        }
+
+        let activations = Rc::new(RefCell::new(Vec::new()));
+        let captured = Rc::clone(&activations);
+        let forward = model_a
+            .register_forward_hook("1", move |input, output| {
+                assert_eq!(input.len(), output.len());
+                captured
+                    .borrow_mut()
+                    .extend(output.iter().map(|y| y.0.borrow().data));
+            })
+            .unwrap();
+        let output_grads = Rc::new(RefCell::new(Vec::new()));
+        let captured = Rc::clone(&output_grads);
+        let backward = model_a
+            .register_backward_hook("1", move |grad| {
+                captured.borrow_mut().push(grad.to_vec());
+                None
+            })
+            .unwrap();
+        assert!(model_a.register_forward_hook("3", |_, _| {}).is_none());
+
+        // Observing hooks change nothing
+        let y = model_a.feed_foward(x());
+        y[0].backwards();
+        let grads: Vec<f32> = model_a
+            .parameters()
+            .iter()
+            .map(|p| p.0.borrow().grad)
+            .collect();
+        assert_eq!(grads, reference_grads);
+        assert_eq!(y[0].0.borrow().data, reference[0].0.borrow().data);
+        assert_eq!(activations.borrow().len(), 2);
+        let w = model_a.parameters();
+        assert_eq!(
+            output_grads.borrow()[0],
+            vec![w[6].0.borrow().data, w[7].0.borrow().data]
+        );
+
+        // Zeroing the gradient at the activation cuts the first layer off
+        forward.remove();
+        backward.remove();
+        let _cut = model_a
+            .register_backward_hook("1", |grad| Some(vec![0f32; grad.len()]))
+            .unwrap();
+        for p in model_a.parameters() {
+            p.0.borrow_mut().grad = 0f32;
+        }
+        model_a.feed_foward(x())[0].backwards();
+        assert!(model_a.parameters()[..6]
+            .iter()
+            .all(|p| p.0.borrow().grad == 0f32));
+        assert_eq!(model_a.parameters()[8].0.borrow().grad, 1f32);
+        assert_eq!(activations.borrow().len(), 2);
+        assert_eq!(output_grads.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_gate_autograd() {
+        let model_a = Model::from(Sequential::new().dense(2, 3).dense(3, 1));
+        let x = [0.5f32, -1f32];
+        let y = model_a.feed_foward(RcScalar::vec_from(&x));
+        let expected = gradient(&y[0], &model_a.parameters(), true).unwrap();
+
+        // The gate is a plain identity to autograd, which calls no hooks
+        let _hook = model_a.register_backward_hook("0", |_| None).unwrap();
+        let y = model_a.feed_foward(RcScalar::vec_from(&x));
+        assert_eq!(
+            gradient(&y[0], &model_a.parameters(), true).unwrap(),
+            expected
+        );
+        y[0].backwards();
+        for (p, g) in model_a.parameters().iter().zip(expected) {
+            assert!((p.0.borrow().grad - g).abs() < 1e-6);
+        }
+    }
+}
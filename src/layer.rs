@@ -1,18 +1,75 @@
+use crate::activation::Activation;
+use crate::module::Module;
 use crate::neuron::Neuron;
+use crate::optim::ParamGroup;
 use crate::scalar::RcScalar;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::vec::Vec;
 
 pub struct Layer {
     neurons: Vec<Neuron>,
 }
 
+/// How the weights of a layer are drawn, always uniformly from `[-bound, bound]`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Init {
+    /// `bound = 1`, what `Neuron::new` has always used.
+    Uniform,
+    /// Glorot/Xavier: `bound = sqrt(6 / (nin + nout))`, suited to tanh, sigmoid & linear layers.
+    Xavier,
+    /// He/Kaiming: `bound = sqrt(6 / nin)`, suited to ReLU layers.
+    He,
+}
+
+impl Init {
+    pub fn bound(&self, nin: usize, nout: usize) -> f32 {
+        match self {
+            Init::Uniform => 1f32,
+            Init::Xavier => (6f32 / (nin + nout) as f32).sqrt(),
+            Init::He => (6f32 / nin as f32).sqrt(),
+        }
+    }
+}
+
 impl Layer {
     pub fn new(nin: usize, nout: usize) -> Self {
+        Layer::with_activation(nin, nout, Activation::Tanh)
+    }
+
+    pub fn with_activation(nin: usize, nout: usize, activation: Activation) -> Self {
         //println!("layer#init ({}, {})", nin, nout);
-        let neurons: Vec<Neuron> = (0..nout).map(|_| Neuron::new(nin)).collect();
+        let neurons: Vec<Neuron> = (0..nout)
+            .map(|_| Neuron::with_activation(nin, activation))
+            .collect();
         Layer { neurons }
     }
 
+    pub fn with_init(nin: usize, nout: usize, activation: Activation, init: Init) -> Self {
+        let layer = Layer::with_activation(nin, nout, activation);
+        // Neurons draw from [-1, 1], so scaling by the bound gives [-bound, bound]
+        let bound = init.bound(nin, nout);
+        for neuron in layer.neurons.iter() {
+            for w in neuron.w.iter() {
+                w.0.borrow_mut().data *= bound;
+            }
+        }
+        layer
+    }
+
+    /// Redraws the weights from `rng` & zeroes the biases, e.g. to make initialization reproducible.
+    pub fn reset_parameters<R: Rng>(&self, init: Init, rng: &mut R) {
+        let nin = self.neurons.first().map_or(0, |neuron| neuron.w.len());
+        let bound = init.bound(nin, self.neurons.len());
+        for neuron in self.neurons.iter() {
+            for w in neuron.w.iter() {
+                w.0.borrow_mut().data = rng.gen_range(-bound..=bound);
+            }
+            neuron.b.0.borrow_mut().data = 0f32;
+        }
+    }
+
     pub fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
         //println!("layer#feed_foward");
         self.neurons
@@ -21,6 +78,22 @@ impl Layer {
             .collect()
     }
 
+    pub fn biases(&self) -> Vec<RcScalar> {
+        self.neurons
+            .iter()
+            .map(|neuron| RcScalar::clone(&neuron.b))
+            .collect()
+    }
+
+    /// One group per neuron, named `{prefix}.{index}`.
+    pub fn neuron_groups(&self, prefix: &str) -> Vec<ParamGroup> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .map(|(i, neuron)| ParamGroup::new(&format!("{}.{}", prefix, i), neuron.parameters()))
+            .collect()
+    }
+
     pub fn parameters(&self) -> Vec<RcScalar> {
         self.neurons
             .iter()
@@ -29,10 +102,43 @@ impl Layer {
     }
 }
 
+impl Module for Layer {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        Layer::feed_foward(self, input)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Layer::parameters(self)
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        Layer::biases(self)
+    }
+
+    fn name(&self) -> String {
+        let nin = self.neurons.first().map_or(0, |neuron| neuron.w.len());
+        let activation = self
+            .neurons
+            .first()
+            .map_or(Activation::Linear, |neuron| neuron.activation);
+        format!("Dense({}, {}, {:?})", nin, self.neurons.len(), activation)
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        self.neurons.first().map(|neuron| neuron.activation)
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        self.neurons.first().map(|neuron| neuron.w.len())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::scalar::Scalar;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_parameters() {
@@ -40,6 +146,36 @@ mod tests {
         let params: Vec<RcScalar> = layer_a.parameters();
 
         assert_eq!(params.len(), 16);
+
+        let groups = layer_a.neuron_groups("hidden");
+        assert_eq!(groups.len(), 4);
+        assert_eq!(groups[3].name, "hidden.3");
+        assert_eq!(groups[3].parameters.len(), 4);
+    }
+
+    #[test]
+    fn test_init() {
+        let layer_a = Layer::with_init(50, 25, Activation::Linear, Init::Xavier);
+        let bound = Init::Xavier.bound(50, 25);
+
+        assert!((bound - 6f32.sqrt() / 75f32.sqrt()).abs() < 1e-6);
+        assert!(layer_a
+            .parameters()
+            .iter()
+            .all(|p| p.0.borrow().data.abs() <= bound));
+
+        let layer_b = Layer::with_init(50, 25, Activation::Linear, Init::Xavier);
+        layer_a.reset_parameters(Init::He, &mut StdRng::seed_from_u64(1));
+        layer_b.reset_parameters(Init::He, &mut StdRng::seed_from_u64(1));
+        let values = |layer: &Layer| -> Vec<f32> {
+            layer
+                .parameters()
+                .iter()
+                .map(|p| p.0.borrow().data)
+                .collect()
+        };
+        assert_eq!(values(&layer_a), values(&layer_b));
+        assert!(values(&layer_a).iter().any(|w| w.abs() > bound));
     }
 
     #[test]
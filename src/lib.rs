@@ -0,0 +1,36 @@
+pub mod activation;
+pub mod anomaly;
+pub mod attention;
+pub mod autograd;
+pub mod callback;
+pub mod checkpoint;
+pub mod clip;
+pub mod config;
+pub mod conv;
+pub mod data;
+pub mod dropout;
+pub mod embedding;
+pub mod flatten;
+pub mod gradcheck;
+pub mod hooks;
+pub mod layer;
+pub mod logger;
+pub mod loss;
+pub mod lr_scheduler;
+pub mod metrics;
+pub mod model;
+pub mod module;
+pub mod neuron;
+pub mod normalization;
+pub mod optim;
+pub mod pool;
+pub mod recurrent;
+pub mod regularization;
+pub mod residual;
+pub mod scalar;
+pub mod sequential;
+pub mod softmax;
+pub mod stats;
+pub mod summary;
+pub mod tensorboard;
+pub mod trainer;
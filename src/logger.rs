@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// A header row, then one row per record. Metric columns are fixed by the first record.
+    Csv,
+    /// One JSON object per line, metrics next to the other fields.
+    Jsonl,
+}
+
+/// Which records a logger writes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogInterval {
+    /// One record per optimizer step, with the batch loss.
+    Step,
+    /// One record per epoch, with the mean loss & the epoch's metrics.
+    Epoch,
+}
+
+/// What the trainer reports at a step or at the end of an epoch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// Number of optimizer steps taken.
+    pub step: usize,
+    /// The epoch the record belongs to, counting from 1.
+    pub epoch: usize,
+    pub loss: f32,
+    pub lr: f32,
+    /// Gradient norm before clipping, averaged over the epoch for epoch records.
+    pub grad_norm: f32,
+    /// Seconds since training started.
+    pub wall_time: f64,
+    /// Other metrics by name, e.g. `val_loss`, none named like the fields above.
+    #[serde(flatten)]
+    pub metrics: BTreeMap<String, f32>,
+}
+
+const COLUMNS: [&str; 6] = ["step", "epoch", "loss", "lr", "grad_norm", "wall_time"];
+
+/// Writes training records to a CSV or JSON Lines file, see `Trainer::logger`.
+///
+/// Every record is written as a whole line with a single write & nothing is buffered, so an
+/// interrupted run leaves a file that is complete up to its last record. The file is created
+/// (or truncated) on the first record, unless `append` is set.
+pub struct MetricsLogger {
+    path: PathBuf,
+    format: LogFormat,
+    interval: LogInterval,
+    append: bool,
+    file: Option<File>,
+    metrics: Vec<String>,
+}
+
+impl MetricsLogger {
+    pub fn new<P: Into<PathBuf>>(path: P, format: LogFormat) -> Self {
+        MetricsLogger {
+            path: path.into(),
+            format,
+            interval: LogInterval::Epoch,
+            append: false,
+            file: None,
+            metrics: Vec::new(),
+        }
+    }
+
+    /// Per epoch by default.
+    pub fn interval(mut self, interval: LogInterval) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Adds to an existing file, e.g. when resuming from a checkpoint. A CSV file keeps the
+    /// columns of its header.
+    pub fn append(mut self, append: bool) -> Self {
+        self.append = append;
+        self
+    }
+
+    pub fn log_interval(&self) -> LogInterval {
+        self.interval
+    }
+
+    fn open(&mut self, first: &Record) -> io::Result<File> {
+        let existing = if self.append {
+            fs::read_to_string(&self.path).or_else(|e| match e.kind() {
+                io::ErrorKind::NotFound => Ok(String::new()),
+                _ => Err(e),
+            })?
+        } else {
+            String::new()
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(self.append)
+            .truncate(!self.append)
+            .open(&self.path)?;
+        if self.format == LogFormat::Jsonl {
+            return Ok(file);
+        }
+
+        match existing.lines().next() {
+            Some(header) => {
+                let columns: Vec<&str> = header.split(',').collect();
+                if columns.len() < COLUMNS.len() || columns[..COLUMNS.len()] != COLUMNS {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unexpected CSV header {:?}", header),
+                    ));
+                }
+                self.metrics = columns[COLUMNS.len()..]
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect();
+            }
+            None => {
+                self.metrics = first.metrics.keys().cloned().collect();
+                let mut header: Vec<&str> = COLUMNS.to_vec();
+                header.extend(self.metrics.iter().map(|m| m.as_str()));
+                file.write_all(format!("{}\n", header.join(",")).as_bytes())?;
+            }
+        }
+        Ok(file)
+    }
+
+    fn csv_line(&self, record: &Record) -> io::Result<String> {
+        if let Some(name) = record.metrics.keys().find(|m| !self.metrics.contains(m)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("metric {} is not a column of {}", name, self.path.display()),
+            ));
+        }
+        let mut fields = vec![
+            record.step.to_string(),
+            record.epoch.to_string(),
+            record.loss.to_string(),
+            record.lr.to_string(),
+            record.grad_norm.to_string(),
+            record.wall_time.to_string(),
+        ];
+        // Metrics missing from this record are left empty
+        fields.extend(self.metrics.iter().map(|m| {
+            record
+                .metrics
+                .get(m)
+                .map_or(String::new(), |v| v.to_string())
+        }));
+        Ok(fields.join(","))
+    }
+
+    /// Fails on a metric named like one of the other fields, e.g. `loss`, which would be
+    /// written twice.
+    pub fn log(&mut self, record: &Record) -> io::Result<()> {
+        if let Some(name) = record
+            .metrics
+            .keys()
+            .find(|m| COLUMNS.contains(&m.as_str()))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("metric {} has the name of a field of every record", name),
+            ));
+        }
+        let mut file = match self.file.take() {
+            Some(file) => file,
+            None => self.open(record)?,
+        };
+        let line = match self.format {
+            LogFormat::Csv => self.csv_line(record),
+            LogFormat::Jsonl => serde_json::to_string(record).map_err(io::Error::from),
+        };
+        let written = line.and_then(|line| file.write_all(format!("{}\n", line).as_bytes()));
+        self.file = Some(file);
+        written
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(step: usize, metrics: &[(&str, f32)]) -> Record {
+        Record {
+            step,
+            epoch: 1,
+            loss: 0.5,
+            lr: 0.1,
+            grad_norm: 2.0,
+            wall_time: 1.5,
+            metrics: metrics.iter().map(|&(m, v)| (m.to_string(), v)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_csv() {
+        let path = std::env::temp_dir().join("nn_from_scratch_test_logger.csv");
+        let mut logger = MetricsLogger::new(&path, LogFormat::Csv);
+        logger.log(&record(1, &[("val_loss", 0.25)])).unwrap();
+        logger.log(&record(2, &[])).unwrap();
+        assert!(logger.log(&record(3, &[("accuracy", 1.0)])).is_err());
+
+        let mut resumed = MetricsLogger::new(&path, LogFormat::Csv).append(true);
+        resumed.log(&record(4, &[("val_loss", 0.125)])).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            csv,
+            "step,epoch,loss,lr,grad_norm,wall_time,val_loss\n\
+             1,1,0.5,0.1,2,1.5,0.25\n\
+             2,1,0.5,0.1,2,1.5,\n\
+             4,1,0.5,0.1,2,1.5,0.125\n"
+        );
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let path = std::env::temp_dir().join("nn_from_scratch_test_logger.jsonl");
+        let mut logger = MetricsLogger::new(&path, LogFormat::Jsonl);
+        let records = vec![record(1, &[("val_loss", 0.25)]), record(2, &[])];
+        for record in records.iter() {
+            logger.log(record).unwrap();
+        }
+        let jsonl = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let read: Vec<Record> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(read, records);
+        assert!(jsonl.starts_with(r#"{"step":1,"epoch":1,"loss":0.5,"#));
+    }
+
+    #[test]
+    fn test_reserved_names() {
+        let path = std::env::temp_dir().join("nn_from_scratch_test_logger_reserved.jsonl");
+        for format in [LogFormat::Jsonl, LogFormat::Csv] {
+            let mut logger = MetricsLogger::new(&path, format);
+            for name in COLUMNS {
+                assert!(logger.log(&record(1, &[(name, 1.0)])).is_err());
+            }
+            assert!(!path.exists());
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::scalar::{RcScalar, Scalar};
+use crate::softmax::log_softmax;
+use serde::{Deserialize, Serialize};
+use std::iter::zip;
+
+/// A loss function picked by name, e.g. from a trainer's configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Loss {
+    Mse,
+    /// Expects the target to hold the class index as its single value.
+    CrossEntropy,
+}
+
+impl Loss {
+    /// Whether `target` suits a model with `nout` outputs: `nout` values for `Mse`, a single
+    /// class index below `nout` for `CrossEntropy`.
+    pub fn fits(&self, target: &[f32], nout: usize) -> bool {
+        match self {
+            Loss::Mse => target.len() == nout,
+            Loss::CrossEntropy => match target {
+                [class] => *class >= 0f32 && class.fract() == 0f32 && (*class as usize) < nout,
+                _ => false,
+            },
+        }
+    }
+
+    pub fn compute(&self, output: &[RcScalar], target: &[f32]) -> RcScalar {
+        match self {
+            Loss::Mse => {
+                let target: Vec<RcScalar> = target
+                    .iter()
+                    .map(|&y| RcScalar::new(Scalar::new(y)))
+                    .collect();
+                mse(output, &target)
+            }
+            Loss::CrossEntropy => {
+                assert_eq!(target.len(), 1, "cross entropy expects a class index");
+                cross_entropy(output, target[0] as usize)
+            }
+        }
+    }
+}
+
+/// Mean of the squared differences between prediction & target.
+pub fn mse(y_pred: &[RcScalar], y: &[RcScalar]) -> RcScalar {
+    assert_eq!(y_pred.len(), y.len());
+    let n = y.len() as f32;
+    zip(y_pred, y).fold(RcScalar::new(Scalar::new(0f32)), |acc, (a, b)| {
+        acc + (RcScalar::clone(a) - RcScalar::clone(b)).square()
+    }) * (1f32 / n)
+}
+
+/// Negative log-likelihood of class `target` under `softmax(logits)`.
+pub fn cross_entropy(logits: &[RcScalar], target: usize) -> RcScalar {
+    assert!(target < logits.len());
+    -log_softmax(logits).swap_remove(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mse() {
+        let y_pred = vec![
+            RcScalar::new(Scalar::new(1f32)),
+            RcScalar::new(Scalar::new(2f32)),
+        ];
+        let y = vec![
+            RcScalar::new(Scalar::new(0f32)),
+            RcScalar::new(Scalar::new(4f32)),
+        ];
+
+        let loss = mse(&y_pred, &y);
+        loss.backwards();
+
+        assert_eq!(loss.0.borrow().data, 2.5);
+        assert_eq!(y_pred[0].0.borrow().grad, 1.0);
+        assert_eq!(y_pred[1].0.borrow().grad, -2.0);
+    }
+
+    #[test]
+    fn test_cross_entropy() {
+        let logits = vec![
+            RcScalar::new(Scalar::new(0f32)),
+            RcScalar::new(Scalar::new(0f32)),
+        ];
+
+        let loss = cross_entropy(&logits, 0);
+        loss.backwards();
+
+        assert!((loss.0.borrow().data - 2f32.ln()).abs() < 1e-6);
+        assert!((logits[0].0.borrow().grad - -0.5).abs() < 1e-6);
+        assert!((logits[1].0.borrow().grad - 0.5).abs() < 1e-6);
+    }
+}
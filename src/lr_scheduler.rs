@@ -0,0 +1,563 @@
+use crate::optim::Optimizer;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::f32::consts::PI;
+
+/// Learning rate as a function of the number of steps taken.
+///
+/// A "step" is whatever the caller advances the scheduler on: call `step` after every batch
+/// to schedule per step, or after every epoch to schedule per epoch. `state`/`load_state`
+/// capture everything needed to resume the schedule from a checkpoint.
+pub trait LrScheduler {
+    /// Learning rate for the current step.
+    fn lr(&self) -> f32;
+
+    fn advance(&mut self);
+
+    /// Records a monitored metric (e.g. validation loss) before the next `step`.
+    /// Only schedules that react to a metric use it.
+    fn observe(&mut self, _metric: f32) {}
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.advance();
+        optimizer.set_learning_rate(self.lr());
+    }
+
+    fn state(&self) -> Value;
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()>;
+}
+
+impl LrScheduler for Box<dyn LrScheduler> {
+    fn lr(&self) -> f32 {
+        (**self).lr()
+    }
+
+    fn advance(&mut self) {
+        (**self).advance()
+    }
+
+    fn observe(&mut self, metric: f32) {
+        (**self).observe(metric)
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        (**self).step(optimizer)
+    }
+
+    fn state(&self) -> Value {
+        (**self).state()
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        (**self).load_state(state)
+    }
+}
+
+fn to_state<T: Serialize>(scheduler: &T) -> Value {
+    serde_json::to_value(scheduler).expect("scheduler state is always serializable")
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` steps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepLr {
+    base_lr: f32,
+    step_size: usize,
+    gamma: f32,
+    last_step: usize,
+}
+
+impl StepLr {
+    pub fn new(base_lr: f32, step_size: usize, gamma: f32) -> Self {
+        assert!(step_size > 0);
+        StepLr {
+            base_lr,
+            step_size,
+            gamma,
+            last_step: 0,
+        }
+    }
+}
+
+impl LrScheduler for StepLr {
+    fn lr(&self) -> f32 {
+        self.base_lr * self.gamma.powi((self.last_step / self.step_size) as i32)
+    }
+
+    fn advance(&mut self) {
+        self.last_step += 1;
+    }
+
+    fn state(&self) -> Value {
+        to_state(self)
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        *self = serde_json::from_value(state.clone())?;
+        Ok(())
+    }
+}
+
+/// Multiplies the learning rate by `gamma` every step.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExponentialLr {
+    base_lr: f32,
+    gamma: f32,
+    last_step: usize,
+}
+
+impl ExponentialLr {
+    pub fn new(base_lr: f32, gamma: f32) -> Self {
+        ExponentialLr {
+            base_lr,
+            gamma,
+            last_step: 0,
+        }
+    }
+}
+
+impl LrScheduler for ExponentialLr {
+    fn lr(&self) -> f32 {
+        self.base_lr * self.gamma.powi(self.last_step as i32)
+    }
+
+    fn advance(&mut self) {
+        self.last_step += 1;
+    }
+
+    fn state(&self) -> Value {
+        to_state(self)
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        *self = serde_json::from_value(state.clone())?;
+        Ok(())
+    }
+}
+
+/// Anneals from `base_lr` down to `eta_min` along half a cosine over `t_max` steps, then stays there.
+///
+/// With `warm_restarts(t_mult)` (SGDR) the schedule jumps back to `base_lr` at the end of every
+/// cycle instead, each cycle `t_mult` times longer than the previous one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CosineAnnealingLr {
+    base_lr: f32,
+    t_max: usize,
+    eta_min: f32,
+    t_mult: Option<usize>,
+    last_step: usize,
+}
+
+impl CosineAnnealingLr {
+    pub fn new(base_lr: f32, t_max: usize, eta_min: f32) -> Self {
+        assert!(t_max > 0);
+        CosineAnnealingLr {
+            base_lr,
+            t_max,
+            eta_min,
+            t_mult: None,
+            last_step: 0,
+        }
+    }
+
+    pub fn warm_restarts(mut self, t_mult: usize) -> Self {
+        assert!(t_mult > 0);
+        self.t_mult = Some(t_mult);
+        self
+    }
+}
+
+impl LrScheduler for CosineAnnealingLr {
+    fn lr(&self) -> f32 {
+        let (t_cur, t_i) = match self.t_mult {
+            None => (self.last_step.min(self.t_max), self.t_max),
+            Some(t_mult) => {
+                let (mut t_cur, mut t_i) = (self.last_step, self.t_max);
+                while t_cur >= t_i {
+                    t_cur -= t_i;
+                    t_i *= t_mult;
+                }
+                (t_cur, t_i)
+            }
+        };
+        self.eta_min
+            + (self.base_lr - self.eta_min) * (1f32 + (PI * t_cur as f32 / t_i as f32).cos()) / 2f32
+    }
+
+    fn advance(&mut self) {
+        self.last_step += 1;
+    }
+
+    fn state(&self) -> Value {
+        to_state(self)
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        *self = serde_json::from_value(state.clone())?;
+        Ok(())
+    }
+}
+
+/// Ramps the learning rate linearly up to `base_lr` over `warmup_steps` steps, then either keeps it
+/// there or hands over to another schedule (`then`), which starts counting once warmup is over.
+pub struct LinearWarmup {
+    base_lr: f32,
+    warmup_steps: usize,
+    last_step: usize,
+    after: Option<Box<dyn LrScheduler>>,
+}
+
+impl LinearWarmup {
+    pub fn new(base_lr: f32, warmup_steps: usize) -> Self {
+        assert!(warmup_steps > 0);
+        LinearWarmup {
+            base_lr,
+            warmup_steps,
+            last_step: 0,
+            after: None,
+        }
+    }
+
+    pub fn then<S: LrScheduler + 'static>(mut self, after: S) -> Self {
+        self.after = Some(Box::new(after));
+        self
+    }
+}
+
+impl LrScheduler for LinearWarmup {
+    fn lr(&self) -> f32 {
+        if self.last_step < self.warmup_steps {
+            return self.base_lr * (self.last_step + 1) as f32 / self.warmup_steps as f32;
+        }
+        match &self.after {
+            Some(after) => after.lr(),
+            None => self.base_lr,
+        }
+    }
+
+    fn advance(&mut self) {
+        self.last_step += 1;
+        if self.last_step > self.warmup_steps {
+            if let Some(after) = self.after.as_mut() {
+                after.advance();
+            }
+        }
+    }
+
+    fn observe(&mut self, metric: f32) {
+        if let Some(after) = self.after.as_mut() {
+            after.observe(metric);
+        }
+    }
+
+    fn state(&self) -> Value {
+        serde_json::json!({
+            "base_lr": self.base_lr,
+            "warmup_steps": self.warmup_steps,
+            "last_step": self.last_step,
+            "after": self.after.as_ref().map(|after| after.state()),
+        })
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        self.base_lr = serde_json::from_value(state["base_lr"].clone())?;
+        self.warmup_steps = serde_json::from_value(state["warmup_steps"].clone())?;
+        self.last_step = serde_json::from_value(state["last_step"].clone())?;
+        match (self.after.as_mut(), &state["after"]) {
+            (Some(after), saved) if !saved.is_null() => after.load_state(saved),
+            (None, Value::Null) => Ok(()),
+            (Some(_), _) => Err(serde::de::Error::custom(
+                "saved warmup has no schedule after it, this one has",
+            )),
+            (None, _) => Err(serde::de::Error::custom(
+                "saved warmup has a schedule after it, this one has none",
+            )),
+        }
+    }
+}
+
+/// The 1cycle policy: anneals up from `max_lr / div_factor` to `max_lr` over the first
+/// `pct_start` of `total_steps`, then down to `max_lr / div_factor / final_div_factor`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OneCycleLr {
+    max_lr: f32,
+    total_steps: usize,
+    pct_start: f32,
+    div_factor: f32,
+    final_div_factor: f32,
+    last_step: usize,
+}
+
+impl OneCycleLr {
+    pub fn new(max_lr: f32, total_steps: usize) -> Self {
+        assert!(total_steps > 1);
+        OneCycleLr {
+            max_lr,
+            total_steps,
+            pct_start: 0.3,
+            div_factor: 25f32,
+            final_div_factor: 1e4,
+            last_step: 0,
+        }
+    }
+
+    pub fn pct_start(mut self, pct_start: f32) -> Self {
+        assert!(pct_start > 0f32 && pct_start < 1f32);
+        self.pct_start = pct_start;
+        self
+    }
+
+    pub fn div_factors(mut self, div_factor: f32, final_div_factor: f32) -> Self {
+        self.div_factor = div_factor;
+        self.final_div_factor = final_div_factor;
+        self
+    }
+}
+
+fn cosine_between(start: f32, end: f32, fraction: f32) -> f32 {
+    end + (start - end) * (1f32 + (PI * fraction).cos()) / 2f32
+}
+
+impl LrScheduler for OneCycleLr {
+    fn lr(&self) -> f32 {
+        let initial_lr = self.max_lr / self.div_factor;
+        let min_lr = initial_lr / self.final_div_factor;
+        let last = (self.total_steps - 1) as f32;
+        let warm = (self.pct_start * last).max(1f32);
+        let t = self.last_step.min(self.total_steps - 1) as f32;
+        if t <= warm {
+            cosine_between(initial_lr, self.max_lr, t / warm)
+        } else {
+            cosine_between(self.max_lr, min_lr, (t - warm) / (last - warm))
+        }
+    }
+
+    fn advance(&mut self) {
+        self.last_step += 1;
+    }
+
+    fn state(&self) -> Value {
+        to_state(self)
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        *self = serde_json::from_value(state.clone())?;
+        Ok(())
+    }
+}
+
+/// Multiplies the learning rate by `factor` once the observed metric (lower is better) has not
+/// improved by a relative `threshold` for more than `patience` steps, never going below `min_lr`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReduceLrOnPlateau {
+    lr: f32,
+    factor: f32,
+    patience: usize,
+    threshold: f32,
+    min_lr: f32,
+    best: Option<f32>,
+    num_bad_steps: usize,
+    last_metric: Option<f32>,
+}
+
+impl ReduceLrOnPlateau {
+    pub fn new(base_lr: f32) -> Self {
+        ReduceLrOnPlateau {
+            lr: base_lr,
+            factor: 0.1,
+            patience: 10,
+            threshold: 1e-4,
+            min_lr: 0f32,
+            best: None,
+            num_bad_steps: 0,
+            last_metric: None,
+        }
+    }
+
+    pub fn factor(mut self, factor: f32) -> Self {
+        assert!(factor > 0f32 && factor < 1f32);
+        self.factor = factor;
+        self
+    }
+
+    pub fn patience(mut self, patience: usize) -> Self {
+        self.patience = patience;
+        self
+    }
+
+    pub fn threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn min_lr(mut self, min_lr: f32) -> Self {
+        self.min_lr = min_lr;
+        self
+    }
+}
+
+impl LrScheduler for ReduceLrOnPlateau {
+    fn lr(&self) -> f32 {
+        self.lr
+    }
+
+    fn advance(&mut self) {
+        let metric = match self.last_metric.take() {
+            Some(metric) => metric,
+            None => return,
+        };
+        match self.best {
+            Some(best) if metric >= best - self.threshold * best.abs() => {
+                self.num_bad_steps += 1;
+            }
+            _ => {
+                self.best = Some(metric);
+                self.num_bad_steps = 0;
+            }
+        }
+        if self.num_bad_steps > self.patience {
+            self.lr = (self.lr * self.factor).max(self.min_lr);
+            self.num_bad_steps = 0;
+        }
+    }
+
+    fn observe(&mut self, metric: f32) {
+        self.last_metric = Some(metric);
+    }
+
+    fn state(&self) -> Value {
+        to_state(self)
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        *self = serde_json::from_value(state.clone())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optim::Sgd;
+
+    fn lrs<S: LrScheduler>(scheduler: &mut S, steps: usize) -> Vec<f32> {
+        (0..steps)
+            .map(|_| {
+                let lr = scheduler.lr();
+                scheduler.advance();
+                lr
+            })
+            .collect()
+    }
+
+    fn assert_close(xs: &[f32], ys: &[f32]) {
+        assert_eq!(xs.len(), ys.len());
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            assert!((x - y).abs() < 1e-5, "{:?} != {:?}", xs, ys);
+        }
+    }
+
+    #[test]
+    fn test_step_and_exponential() {
+        assert_close(
+            &lrs(&mut StepLr::new(1.0, 2, 0.5), 5),
+            &[1.0, 1.0, 0.5, 0.5, 0.25],
+        );
+        assert_close(
+            &lrs(&mut ExponentialLr::new(1.0, 0.9), 3),
+            &[1.0, 0.9, 0.81],
+        );
+    }
+
+    #[test]
+    fn test_cosine_annealing() {
+        assert_close(
+            &lrs(&mut CosineAnnealingLr::new(1.0, 2, 0.0), 4),
+            &[1.0, 0.5, 0.0, 0.0],
+        );
+        // Cycles of 2 then 4 steps
+        assert_close(
+            &lrs(&mut CosineAnnealingLr::new(1.0, 2, 0.0).warm_restarts(2), 7),
+            &[1.0, 0.5, 1.0, 0.8535534, 0.5, 0.14644662, 1.0],
+        );
+    }
+
+    #[test]
+    fn test_linear_warmup() {
+        assert_close(
+            &lrs(&mut LinearWarmup::new(1.0, 4), 6),
+            &[0.25, 0.5, 0.75, 1.0, 1.0, 1.0],
+        );
+        assert_close(
+            &lrs(
+                &mut LinearWarmup::new(1.0, 2).then(StepLr::new(1.0, 1, 0.5)),
+                5,
+            ),
+            &[0.5, 1.0, 1.0, 0.5, 0.25],
+        );
+
+        // A saved schedule has to match this one's structure
+        let with_after = LinearWarmup::new(1.0, 2).then(StepLr::new(1.0, 1, 0.5));
+        assert!(LinearWarmup::new(1.0, 2)
+            .load_state(&with_after.state())
+            .is_err());
+        assert!(LinearWarmup::new(1.0, 2)
+            .then(StepLr::new(1.0, 1, 0.5))
+            .load_state(&LinearWarmup::new(1.0, 2).state())
+            .is_err());
+    }
+
+    #[test]
+    fn test_one_cycle() {
+        let values = lrs(&mut OneCycleLr::new(1.0, 11).pct_start(0.2), 11);
+
+        assert!((values[0] - 0.04).abs() < 1e-6);
+        assert!((values[2] - 1.0).abs() < 1e-6);
+        assert!(values[..3].windows(2).all(|w| w[0] < w[1]));
+        assert!(values[2..].windows(2).all(|w| w[0] > w[1]));
+        assert!((values[10] - 0.04 / 1e4).abs() < 1e-7);
+    }
+
+    #[test]
+    fn test_reduce_on_plateau() {
+        let mut scheduler = ReduceLrOnPlateau::new(1.0).patience(1).factor(0.5);
+        let mut values = Vec::new();
+        for metric in [3.0, 2.0, 2.0, 2.0, 2.5, 1.0] {
+            scheduler.observe(metric);
+            scheduler.advance();
+            values.push(scheduler.lr());
+        }
+
+        assert_close(&values, &[1.0, 1.0, 1.0, 0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_drives_optimizer() {
+        let mut optimizer = Sgd::new(Vec::new(), 1.0);
+        let mut scheduler = StepLr::new(1.0, 1, 0.1);
+
+        scheduler.step(&mut optimizer);
+        scheduler.step(&mut optimizer);
+
+        assert!((optimizer.learning_rate() - 0.01).abs() < 1e-7);
+    }
+
+    #[test]
+    fn test_state() {
+        let mut scheduler = LinearWarmup::new(1.0, 2).then(CosineAnnealingLr::new(1.0, 4, 0.0));
+        lrs(&mut scheduler, 4);
+        let state = scheduler.state();
+
+        let mut resumed = LinearWarmup::new(1.0, 2).then(CosineAnnealingLr::new(1.0, 4, 0.0));
+        resumed.load_state(&state).unwrap();
+
+        assert_eq!(lrs(&mut resumed, 3), lrs(&mut scheduler, 3));
+
+        let mut plateau = ReduceLrOnPlateau::new(1.0);
+        plateau.observe(0.5);
+        plateau.advance();
+        let mut resumed = ReduceLrOnPlateau::new(0.1);
+        resumed.load_state(&plateau.state()).unwrap();
+        assert_eq!(resumed, plateau);
+    }
+}
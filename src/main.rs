@@ -1,71 +1,511 @@
-mod layer;
-mod model;
-mod neuron;
-mod scalar;
-use crate::model::Model;
-use crate::scalar::{RcScalar, Scalar};
+use clap::{Parser, Subcommand, ValueEnum};
 use log::debug;
+use neural_network_from_scratch::activation::Activation;
+use neural_network_from_scratch::config::{
+    Config, DataConfig, LogConfig, ModelConfig, OptimizerConfig,
+};
+use neural_network_from_scratch::data::{check_samples, read_csv, read_rows};
+use neural_network_from_scratch::logger::{LogFormat, LogInterval};
+use neural_network_from_scratch::loss::Loss;
+use neural_network_from_scratch::metrics::{accuracy, argmax, mean_absolute_error, mean_loss};
+use neural_network_from_scratch::model::{Model, ModelState};
+use neural_network_from_scratch::scalar::RcScalar;
+use neural_network_from_scratch::softmax::softmax;
+use neural_network_from_scratch::trainer::TrainerConfig;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-fn main() {
-    env_logger::init();
-    debug!("Starting application...");
+#[derive(Parser)]
+#[command(about = "Train, evaluate and run multi-layer perceptrons")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
 
-    let xs: Vec<Vec<RcScalar>> = vec![
-        vec![
-            RcScalar::clone(&RcScalar::new(Scalar::new(2f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(3f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(-1f32))),
-        ],
-        vec![
-            RcScalar::clone(&RcScalar::new(Scalar::new(3f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(-1f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(0.5f32))),
-        ],
-        vec![
-            RcScalar::clone(&RcScalar::new(Scalar::new(0.5f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(1f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(1f32))),
-        ],
-        vec![
-            RcScalar::clone(&RcScalar::new(Scalar::new(1f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(1f32))),
-            RcScalar::clone(&RcScalar::new(Scalar::new(-1f32))),
-        ],
-    ];
-
-    let ys: Vec<Vec<RcScalar>> = vec![
-        vec![RcScalar::new(Scalar::new(1f32))],
-        vec![RcScalar::new(Scalar::new(-1f32))],
-        vec![RcScalar::new(Scalar::new(-1f32))],
-        vec![RcScalar::new(Scalar::new(1f32))],
-    ];
-
-    let model_a = Model::new(vec![3, 4, 4, 1]);
-
-    for i in 0..100 {
-        let y_preds: Vec<Vec<RcScalar>> = xs
-            .iter()
-            .map(|x: &Vec<RcScalar>| model_a.feed_foward(x.clone()))
-            .collect();
+#[derive(Subcommand)]
+enum Command {
+    /// Train a model on a CSV file whose last column(s) are the targets
+    Train {
+        /// TOML or JSON file describing the model, optimizer, data & training, used instead
+        /// of the options below save `--log`, `--log-interval`, `--tensorboard` & `--output`
+        #[arg(long, conflicts_with_all = [
+            "data", "layers", "activation", "loss", "optimizer", "lr", "momentum", "epochs",
+            "batch_size", "seed", "targets",
+        ])]
+        config: Option<PathBuf>,
+        #[arg(long, required_unless_present = "config")]
+        data: Option<PathBuf>,
+        /// Layer sizes from input to output, e.g. `4,16,3`
+        #[arg(long, value_delimiter = ',', required_unless_present = "config")]
+        layers: Vec<usize>,
+        /// Activation of the hidden layers, the output layer is linear
+        #[arg(long, value_enum, default_value_t = ActivationArg::Tanh)]
+        activation: ActivationArg,
+        /// `cross-entropy` expects a single target column holding the class index
+        #[arg(long, value_enum, default_value_t = LossArg::Mse)]
+        loss: LossArg,
+        #[arg(long, value_enum, default_value_t = OptimizerArg::Adam)]
+        optimizer: OptimizerArg,
+        #[arg(long, default_value_t = 0.01)]
+        lr: f32,
+        /// Momentum of SGD
+        #[arg(long, default_value_t = 0.0)]
+        momentum: f32,
+        #[arg(long, default_value_t = 10)]
+        epochs: usize,
+        #[arg(long, default_value_t = 32)]
+        batch_size: usize,
+        /// Seeds the weight initialization & the data shuffling
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Number of target columns, for regression with several outputs
+        #[arg(long, default_value_t = 1)]
+        targets: usize,
+        /// Metrics file to write, JSON Lines if it ends in `.jsonl`, CSV otherwise
+        #[arg(long)]
+        log: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = IntervalArg::Epoch)]
+        log_interval: IntervalArg,
+        /// Directory to write TensorBoard event files to
+        #[arg(long)]
+        tensorboard: Option<PathBuf>,
+        #[arg(long, short)]
+        output: PathBuf,
+    },
+    /// Compute the loss & a metric (accuracy or mean absolute error) on a CSV file
+    Eval {
+        #[arg(long)]
+        model: PathBuf,
+        #[arg(long)]
+        data: PathBuf,
+    },
+    /// Predict the outputs for CSV rows of inputs
+    Predict {
+        #[arg(long)]
+        model: PathBuf,
+        /// Defaults to standard input, as does `-`
+        #[arg(long)]
+        input: Option<PathBuf>,
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ActivationArg {
+    Linear,
+    Tanh,
+    Relu,
+    Sigmoid,
+}
+
+impl From<ActivationArg> for Activation {
+    fn from(activation: ActivationArg) -> Self {
+        match activation {
+            ActivationArg::Linear => Activation::Linear,
+            ActivationArg::Tanh => Activation::Tanh,
+            ActivationArg::Relu => Activation::Relu,
+            ActivationArg::Sigmoid => Activation::Sigmoid,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LossArg {
+    Mse,
+    CrossEntropy,
+}
+
+impl From<LossArg> for Loss {
+    fn from(loss: LossArg) -> Self {
+        match loss {
+            LossArg::Mse => Loss::Mse,
+            LossArg::CrossEntropy => Loss::CrossEntropy,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OptimizerArg {
+    Sgd,
+    Adam,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum IntervalArg {
+    Step,
+    Epoch,
+}
+
+impl From<IntervalArg> for LogInterval {
+    fn from(interval: IntervalArg) -> Self {
+        match interval {
+            IntervalArg::Step => LogInterval::Step,
+            IntervalArg::Epoch => LogInterval::Epoch,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+/// What `train` writes: the architecture alongside the weights, so the model can be rebuilt.
+#[derive(Serialize, Deserialize)]
+struct ModelFile {
+    model: ModelConfig,
+    loss: Loss,
+    state: ModelState,
+}
+
+impl ModelFile {
+    fn load(path: &Path) -> io::Result<(ModelFile, Model)> {
+        let json = fs::read_to_string(path).map_err(|e| with_path(path, e))?;
+        let file: ModelFile = serde_json::from_str(&json).map_err(|e| with_path(path, e.into()))?;
+        let mut model = file.model.build(&mut StdRng::seed_from_u64(0));
+        model.load_state(&file.state)?;
+        model.eval();
+        Ok((file, model))
+    }
+}
+
+fn with_path(path: &Path, error: io::Error) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Makes Ctrl-C set `flag`, which stops training after the current step so the logs & the model
+/// file are still written. A second Ctrl-C exits right away.
+fn stop_on_interrupt(flag: Arc<AtomicBool>) -> io::Result<()> {
+    let action = move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            // Exiting the process is all a signal handler can safely do beyond the atomic
+            unsafe { libc::_exit(130) };
+        }
+    };
+    unsafe { signal_hook_registry::register(libc::SIGINT, action) }?;
+    Ok(())
+}
+
+fn run(command: Command) -> io::Result<ExitCode> {
+    match command {
+        Command::Train {
+            config,
+            data,
+            layers,
+            activation,
+            loss,
+            optimizer,
+            lr,
+            momentum,
+            epochs,
+            batch_size,
+            seed,
+            targets,
+            log,
+            log_interval,
+            tensorboard,
+            output,
+        } => {
+            let mut config = match config {
+                Some(path) => Config::load(path)?,
+                None => {
+                    let loss = Loss::from(loss);
+                    let config = Config {
+                        model: ModelConfig::mlp(&layers, Activation::from(activation)),
+                        loss,
+                        optimizer: match optimizer {
+                            OptimizerArg::Sgd => OptimizerConfig::Sgd {
+                                lr,
+                                momentum,
+                                weight_decay: None,
+                            },
+                            OptimizerArg::Adam => OptimizerConfig::Adam {
+                                lr,
+                                betas: None,
+                                weight_decay: None,
+                            },
+                        },
+                        scheduler: None,
+                        penalty: None,
+                        data: DataConfig {
+                            train: data.expect("clap requires --data without --config"),
+                            validation: None,
+                            targets: if loss == Loss::CrossEntropy {
+                                1
+                            } else {
+                                targets
+                            },
+                        },
+                        training: TrainerConfig {
+                            epochs,
+                            batch_size,
+                            seed,
+                            ..TrainerConfig::default()
+                        },
+                        logs: Vec::new(),
+                        tensorboard: None,
+                    };
+                    config.validate()?;
+                    config
+                }
+            };
+            if let Some(path) = log {
+                let format = match path.extension().and_then(|e| e.to_str()) {
+                    Some("jsonl") => LogFormat::Jsonl,
+                    _ => LogFormat::Csv,
+                };
+                config.logs.push(LogConfig {
+                    path,
+                    format,
+                    interval: LogInterval::from(log_interval),
+                    append: false,
+                });
+            }
+            if tensorboard.is_some() {
+                config.tensorboard = tensorboard;
+            }
+            let (samples, validation) = config.read_data()?;
+
+            let mut trainer = config.build()?;
+            let interrupted = trainer.interrupt_flag();
+            stop_on_interrupt(Arc::clone(&interrupted))?;
+            for logs in trainer.fit(&samples, validation.as_deref())? {
+                let metrics: Vec<String> = logs
+                    .metrics
+                    .iter()
+                    .map(|(name, value)| format!("{} {}", name, value))
+                    .collect();
+                println!("epoch {}: {}", logs.epoch, metrics.join(", "));
+            }
 
-        let mut loss: RcScalar = RcScalar::new(Scalar::new(0f32));
-        for (row1, row2) in ys.iter().zip(y_preds.iter()) {
-            for (elem1, elem2) in row1.iter().zip(row2.iter()) {
-                loss = loss + (RcScalar::clone(elem1) - RcScalar::clone(elem2)).square();
+            let file = ModelFile {
+                model: config.model,
+                loss: config.loss,
+                state: trainer.model().state(),
+            };
+            fs::write(&output, serde_json::to_string(&file)?).map_err(|e| with_path(&output, e))?;
+            if interrupted.load(Ordering::SeqCst) {
+                eprintln!(
+                    "interrupted after {} epochs, {} written",
+                    trainer.epoch(),
+                    output.display()
+                );
+                return Ok(ExitCode::from(130));
             }
+            Ok(ExitCode::SUCCESS)
         }
+        Command::Eval { model, data } => {
+            let (file, model) = ModelFile::load(&model)?;
+            let outputs = file.model.output_size();
+            let targets = match file.loss {
+                Loss::Mse => outputs,
+                Loss::CrossEntropy => 1,
+            };
+            let samples = read_csv(&data, targets)?;
+            if samples.is_empty() {
+                return Err(invalid_input(format!("{} has no rows", data.display())));
+            }
+            check_samples(&samples, file.model.input, outputs, file.loss)
+                .map_err(|e| with_path(&data, e))?;
 
-        for p in model_a.parameters() {
-            p.0.borrow_mut().grad = 0f32;
+            println!("loss: {}", mean_loss(&model, file.loss, &samples));
+            match file.loss {
+                Loss::Mse => println!("mae: {}", mean_absolute_error(&model, &samples)),
+                Loss::CrossEntropy => println!("accuracy: {}", accuracy(&model, &samples)),
+            }
+            Ok(ExitCode::SUCCESS)
         }
+        Command::Predict {
+            model,
+            input,
+            format,
+        } => {
+            let (file, model) = ModelFile::load(&model)?;
+            let rows = match input {
+                Some(path) if path.as_os_str() != "-" => {
+                    let reader = fs::File::open(&path).map_err(|e| with_path(&path, e))?;
+                    read_rows(BufReader::new(reader)).map_err(|e| with_path(&path, e))?
+                }
+                _ => read_rows(io::stdin().lock())?,
+            };
+            let nin = file.model.input;
+            if let Some(row) = rows.first() {
+                if row.len() != nin {
+                    return Err(invalid_input(format!(
+                        "inputs have {} columns, the model takes {}",
+                        row.len(),
+                        nin
+                    )));
+                }
+            }
 
-        loss.backwards();
+            let predictions: Vec<Vec<f32>> = rows.iter().map(|row| model.predict(row)).collect();
+            let mut stdout = io::stdout().lock();
+            match (format, file.loss) {
+                (Format::Csv, Loss::Mse) => {
+                    for outputs in predictions {
+                        let line: Vec<String> = outputs.iter().map(|y| y.to_string()).collect();
+                        writeln!(stdout, "{}", line.join(","))?;
+                    }
+                }
+                (Format::Csv, Loss::CrossEntropy) => {
+                    for outputs in predictions {
+                        let mut line = vec![argmax(&outputs).to_string()];
+                        line.extend(probabilities(&outputs).iter().map(|p| p.to_string()));
+                        writeln!(stdout, "{}", line.join(","))?;
+                    }
+                }
+                (Format::Json, Loss::Mse) => {
+                    writeln!(stdout, "{}", serde_json::to_string(&predictions)?)?;
+                }
+                (Format::Json, Loss::CrossEntropy) => {
+                    let json: Vec<serde_json::Value> = predictions
+                        .iter()
+                        .map(|outputs| {
+                            serde_json::json!({
+                                "class": argmax(outputs),
+                                "probabilities": probabilities(outputs),
+                            })
+                        })
+                        .collect();
+                    writeln!(stdout, "{}", serde_json::to_string(&json)?)?;
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn probabilities(logits: &[f32]) -> Vec<f32> {
+    let logits = RcScalar::vec_from(logits);
+    softmax(&logits).iter().map(|p| p.0.borrow().data).collect()
+}
 
-        for p in model_a.parameters() {
-            let mut borrowed = p.0.borrow_mut();
-            borrowed.data -= 0.01 * borrowed.grad;
+fn main() -> ExitCode {
+    env_logger::init();
+    debug!("Starting application...");
+
+    match run(Cli::parse().command) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn train_args<'a>(extra: &[&'a str]) -> Vec<&'a str> {
+        let mut args = vec!["nn", "train", "--config", "run.toml", "-o", "model.json"];
+        args.extend_from_slice(extra);
+        args
+    }
+
+    #[test]
+    fn test_config_conflicts() {
+        for flag in [
+            ["--data", "train.csv"],
+            ["--lr", "0.1"],
+            ["--epochs", "5"],
+            ["--batch-size", "8"],
+            ["--seed", "2"],
+            ["--loss", "cross-entropy"],
+            ["--optimizer", "sgd"],
+        ] {
+            assert!(
+                Cli::try_parse_from(train_args(&flag)).is_err(),
+                "{:?}",
+                flag
+            );
+        }
+        assert!(Cli::try_parse_from(train_args(&["--log", "steps.csv"])).is_ok());
+    }
+
+    #[test]
+    fn test_train_then_eval() {
+        let dir =
+            std::env::temp_dir().join(format!("nn_from_scratch_test_cli_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data = dir.join("train.csv");
+        let rows: Vec<String> = (0..20)
+            .map(|i| {
+                let x = i as f32 / 10f32 - 1f32;
+                format!("{},{},{}", x, -x, (x > 0f32) as usize)
+            })
+            .collect();
+        fs::write(&data, format!("x1,x2,y\n{}\n", rows.join("\n"))).unwrap();
+        let config = dir.join("run.toml");
+        fs::write(
+            &config,
+            format!(
+                r#"
+loss = "cross_entropy"
+
+[model]
+input = 2
+layers = [
+    {{ type = "dense", size = 4, activation = "relu" }},
+    {{ type = "batch_norm" }},
+    {{ type = "dropout", p = 0.5 }},
+    {{ type = "dense", size = 2 }},
+]
+
+[optimizer]
+type = "adam"
+lr = 0.01
+
+[data]
+train = "{}"
+
+[training]
+epochs = 2
+batch_size = 4
+"#,
+                data.display()
+            ),
+        )
+        .unwrap();
+        let output = dir.join("model.json");
+        let args = [
+            "nn",
+            "train",
+            "--config",
+            config.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ];
+        run(Cli::try_parse_from(args).unwrap().command).unwrap();
 
-        println!("Iter: {}, loss: {}", i, loss.0.borrow().data);
+        // Loaded for inference: batch norm takes single rows, dropout is off
+        let (_, model) = ModelFile::load(&output).unwrap();
+        assert!(!model.is_training());
+        let x = [0.3, -0.3];
+        assert_eq!(model.predict(&x), model.predict(&x));
+        let args = [
+            "nn",
+            "eval",
+            "--model",
+            output.to_str().unwrap(),
+            "--data",
+            data.to_str().unwrap(),
+        ];
+        run(Cli::try_parse_from(args).unwrap().command).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
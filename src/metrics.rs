@@ -0,0 +1,105 @@
+use crate::loss::Loss;
+use crate::model::Model;
+use crate::scalar::RcScalar;
+use crate::trainer::Sample;
+
+/// Mean of `loss` over samples, one at a time.
+pub fn mean_loss(model: &Model, loss: Loss, data: &[Sample]) -> f32 {
+    let total: f32 = data
+        .iter()
+        .map(|sample| {
+            let input = RcScalar::vec_from(&sample.input);
+            let value = loss.compute(&model.feed_foward(input), &sample.target);
+            let value = value.0.borrow().data;
+            value
+        })
+        .sum();
+    total / data.len() as f32
+}
+
+/// Fraction of samples whose largest output is at the class index held in `target[0]`.
+pub fn accuracy(model: &Model, data: &[Sample]) -> f32 {
+    let correct = data
+        .iter()
+        .filter(|sample| argmax(&model.predict(&sample.input)) == sample.target[0] as usize)
+        .count();
+    correct as f32 / data.len() as f32
+}
+
+/// Mean over samples & outputs of `|output - target|`.
+pub fn mean_absolute_error(model: &Model, data: &[Sample]) -> f32 {
+    let total: f32 = data
+        .iter()
+        .map(|sample| {
+            let output = model.predict(&sample.input);
+            output
+                .iter()
+                .zip(sample.target.iter())
+                .map(|(y, t)| (y - t).abs())
+                .sum::<f32>()
+                / output.len() as f32
+        })
+        .sum();
+    total / data.len() as f32
+}
+
+/// Index of the largest value, the first one on ties.
+///
+/// NaN values are skipped. When nothing is left above negative infinity, e.g. every value is
+/// NaN because the model diverged, the index is 0: check the outputs first if that matters.
+pub fn argmax(values: &[f32]) -> usize {
+    values
+        .iter()
+        .enumerate()
+        .fold((0, f32::NEG_INFINITY), |best, (i, &v)| {
+            if v > best.1 {
+                (i, v)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sequential::Sequential;
+
+    #[test]
+    fn test_metrics() {
+        // Identity on 2 inputs: the output is the input itself
+        let model = Model::from(Sequential::new().dense(2, 2));
+        for (i, p) in model.parameters().iter().enumerate() {
+            p.0.borrow_mut().data = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0][i];
+        }
+        let sample = |input: [f32; 2], target: f32| Sample {
+            input: input.to_vec(),
+            target: vec![target],
+        };
+        let data = vec![sample([0.9, 0.1], 0.0), sample([0.2, 0.4], 0.0)];
+
+        assert_eq!(accuracy(&model, &data), 0.5);
+        let expected = ((1f32 + (-0.8f32).exp()).ln() + (1f32 + 0.2f32.exp()).ln()) / 2f32;
+        assert!((mean_loss(&model, Loss::CrossEntropy, &data) - expected).abs() < 1e-5);
+        assert_eq!(argmax(&[1.0, 3.0, 3.0]), 1);
+        assert_eq!(argmax(&[f32::NAN, -1.0, f32::NAN]), 1);
+        assert_eq!(argmax(&[f32::NAN, f32::NAN]), 0);
+
+        let regression = Model::from(Sequential::new().dense(1, 1));
+        for p in regression.parameters() {
+            p.0.borrow_mut().data = 1.0;
+        }
+        let data = vec![
+            Sample {
+                input: vec![1.0],
+                target: vec![2.5],
+            },
+            Sample {
+                input: vec![0.0],
+                target: vec![2.0],
+            },
+        ];
+        assert_eq!(mean_absolute_error(&regression, &data), 0.75);
+    }
+}
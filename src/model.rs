@@ -1,8 +1,36 @@
+use crate::hooks::HookHandle;
 use crate::layer::Layer;
+use crate::metrics::argmax;
+use crate::module::Module;
+use crate::optim::ParamGroup;
+use crate::regularization::weights;
 use crate::scalar::RcScalar;
+use crate::sequential::Sequential;
+use crate::softmax::softmax;
+use crate::stats::LayerStats;
+use crate::summary::Summary;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
 
 pub struct Model {
-    layers: Vec<Layer>,
+    layers: Sequential,
+    training: bool,
+}
+
+/// Values of one module's parameters & buffers, in the order the module reports them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleState {
+    pub name: String,
+    pub parameters: Vec<f32>,
+    pub buffers: Vec<f32>,
+}
+
+/// Everything needed to restore a model's weights into a model of the same architecture.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelState {
+    pub modules: Vec<ModuleState>,
 }
 
 impl Model {
@@ -10,32 +38,249 @@ impl Model {
         //println!("model#init");
         let layers = shape
             .windows(2)
-            .map(|window: &[usize]| Layer::new(window[0], window[1]))
-            .collect();
-        Model { layers }
+            .fold(Sequential::new(), |layers, window: &[usize]| {
+                layers.push(Layer::new(window[0], window[1]))
+            });
+        Model::from(layers)
+    }
+
+    pub fn layers(&self) -> &Sequential {
+        &self.layers
     }
 
     pub fn parameters(&self) -> Vec<RcScalar> {
+        self.layers.parameters()
+    }
+
+    pub fn biases(&self) -> Vec<RcScalar> {
+        self.layers.biases()
+    }
+
+    /// One group per named module holding parameters, e.g. `"0"`, `"1"` or the names given to
+    /// `Sequential::push_named`.
+    pub fn param_groups(&self) -> Vec<ParamGroup> {
         self.layers
-            .iter()
-            .flat_map(|layer: &Layer| layer.parameters())
+            .named_modules()
+            .into_iter()
+            .map(|(name, module)| ParamGroup::new(name, module.parameters()))
+            .filter(|group| !group.parameters.is_empty())
             .collect()
     }
 
+    pub fn param_group(&self, name: &str) -> Option<ParamGroup> {
+        self.layers
+            .get(name)
+            .map(|module| ParamGroup::new(name, module.parameters()))
+    }
+
+    /// Parameters that are not biases, i.e. what weight penalties apply to by default.
+    pub fn weights(&self) -> Vec<RcScalar> {
+        weights(&self.layers)
+    }
+
+    /// The width of a sample, if the first module fixes it. See `Module::input_size`.
+    pub fn input_size(&self) -> Option<usize> {
+        self.layers.input_size()
+    }
+
     pub fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
         //println!("model#feed_foward");
-        self.layers
+        self.layers.feed_foward(input)
+    }
+
+    pub fn feed_foward_batch(&self, inputs: Vec<Vec<RcScalar>>) -> Vec<Vec<RcScalar>> {
+        self.layers.feed_foward_batch(inputs)
+    }
+
+    /// Output values for one sample of plain numbers.
+    pub fn predict(&self, input: &[f32]) -> Vec<f32> {
+        self.feed_foward(RcScalar::vec_from(input))
             .iter()
-            .fold(input, |x: Vec<RcScalar>, layer: &Layer| {
-                layer.feed_foward(x)
-            })
+            .map(|y: &RcScalar| y.0.borrow().data)
+            .collect()
+    }
+
+    /// Class probabilities for one sample, treating the model outputs as logits.
+    pub fn predict_proba(&self, input: Vec<RcScalar>) -> Vec<f32> {
+        softmax(&self.feed_foward(input))
+            .iter()
+            .map(|p: &RcScalar| p.0.borrow().data)
+            .collect()
+    }
+
+    /// Index of the largest output for one sample, 0 if every output is NaN, see `argmax`.
+    pub fn predict_class(&self, input: Vec<RcScalar>) -> usize {
+        let outputs: Vec<f32> = self
+            .feed_foward(input)
+            .iter()
+            .map(|y: &RcScalar| y.0.borrow().data)
+            .collect();
+        argmax(&outputs)
+    }
+
+    /// Per-layer shapes, activations, parameter counts & graph size for inputs of
+    /// `input_size`, from a forward pass of one sample. `println!("{}", model.summary(3))`
+    /// prints it as a table.
+    ///
+    /// The sample goes through in evaluation mode, so dropout & batch statistics do not change
+    /// the counts; the model is left in the mode it was in.
+    pub fn summary(&mut self, input_size: usize) -> Summary {
+        let training = self.training;
+        self.eval();
+        let summary = Summary::new(self.layers.named_modules(), input_size);
+        if training {
+            self.train();
+        }
+        summary
+    }
+
+    /// Statistics of the weights, biases & gradients of every module with parameters, e.g. to
+    /// spot vanishing gradients after a backward pass.
+    pub fn layer_stats(&self) -> Vec<LayerStats> {
+        self.layers
+            .named_modules()
+            .into_iter()
+            .filter_map(|(name, module)| LayerStats::new(name, module))
+            .collect()
+    }
+
+    /// See `Sequential::register_forward_hook`.
+    pub fn register_forward_hook<F>(&self, name: &str, hook: F) -> Option<HookHandle>
+    where
+        F: FnMut(&[RcScalar], &[RcScalar]) + 'static,
+    {
+        self.layers.register_forward_hook(name, hook)
+    }
+
+    /// See `Sequential::register_backward_hook`.
+    pub fn register_backward_hook<F>(&self, name: &str, hook: F) -> Option<HookHandle>
+    where
+        F: FnMut(&[f32]) -> Option<Vec<f32>> + 'static,
+    {
+        self.layers.register_backward_hook(name, hook)
+    }
+
+    pub fn train(&mut self) {
+        self.layers.set_training(true);
+        self.training = true;
+    }
+
+    pub fn eval(&mut self) {
+        self.layers.set_training(false);
+        self.training = false;
+    }
+
+    /// Whether the model is in training mode, as it is when created.
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    /// Restarts the random draws of every module, e.g. dropout masks, from `seed`.
+    pub fn seed(&self, seed: u64) {
+        self.layers.seed(seed);
+    }
+
+    pub fn state(&self) -> ModelState {
+        ModelState {
+            modules: self
+                .layers
+                .named_modules()
+                .iter()
+                .map(|(name, layer)| ModuleState {
+                    name: name.to_string(),
+                    parameters: layer
+                        .parameters()
+                        .iter()
+                        .map(|p: &RcScalar| p.0.borrow().data)
+                        .collect(),
+                    buffers: layer.buffers(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Restores weights & buffers, failing if `state` was taken from a different architecture.
+    pub fn load_state(&self, state: &ModelState) -> io::Result<()> {
+        let layers = self.layers.named_modules();
+        if state.modules.len() != layers.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "expected {} modules, found {}",
+                    layers.len(),
+                    state.modules.len()
+                ),
+            ));
+        }
+        for ((name, layer), module_state) in layers.iter().zip(state.modules.iter()) {
+            let parameters = layer.parameters();
+            if *name != module_state.name
+                || parameters.len() != module_state.parameters.len()
+                || layer.buffers().len() != module_state.buffers.len()
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("module {} does not match the saved architecture", name),
+                ));
+            }
+        }
+        for ((_, layer), module_state) in layers.iter().zip(state.modules.iter()) {
+            for (p, value) in layer
+                .parameters()
+                .iter()
+                .zip(module_state.parameters.iter())
+            {
+                p.0.borrow_mut().data = *value;
+            }
+            layer.load_buffers(&module_state.buffers);
+        }
+        Ok(())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let json = serde_json::to_string(&self.state())?;
+        fs::write(path, json)
+    }
+
+    pub fn load<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let state: ModelState = serde_json::from_str(&fs::read_to_string(path)?)?;
+        self.load_state(&state)
+    }
+}
+
+impl From<Sequential> for Model {
+    fn from(layers: Sequential) -> Self {
+        Model {
+            layers,
+            training: true,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::loss::cross_entropy;
+    use crate::optim::{Optimizer, Sgd};
     use crate::scalar::Scalar;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::f32::consts::PI;
+
+    // Three interleaved arms of half a turn each, with a little angular noise
+    fn spiral(points_per_class: usize, rng: &mut StdRng) -> Vec<(Vec<f32>, usize)> {
+        let mut data = Vec::new();
+        for class in 0..3 {
+            for i in 0..points_per_class {
+                let r = 0.1 + 0.9 * i as f32 / points_per_class as f32;
+                let theta =
+                    class as f32 * 2f32 * PI / 3f32 + r * PI + rng.gen_range(-0.1f32..0.1f32);
+                data.push((vec![r * theta.cos(), r * theta.sin()], class));
+            }
+        }
+        data
+    }
 
     #[test]
     fn test_parameters() {
@@ -62,4 +307,115 @@ mod tests {
 
         assert_eq!(output.len(), 1);
     }
+
+    #[test]
+    fn test_freeze() {
+        let model_a = Model::from(Sequential::new().dense(2, 3).tanh().dense(3, 1));
+        let groups = model_a.param_groups();
+        assert_eq!(
+            groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>(),
+            vec!["0", "2"]
+        );
+        model_a.param_group("0").unwrap().freeze();
+        let before = model_a.state();
+
+        let mut optimizer = Sgd::with_groups(groups, 0.1);
+        let y = model_a.feed_foward(RcScalar::vec_from(&[1.0, -1.0]));
+        (RcScalar::clone(&y[0]) + -5f32).square().backwards();
+        optimizer.step();
+
+        let after = model_a.state();
+        assert_eq!(after.modules[0], before.modules[0]);
+        assert_ne!(after.modules[2], before.modules[2]);
+        assert!(model_a.param_group("0").unwrap().is_frozen());
+        assert!(!model_a.param_group("2").unwrap().is_frozen());
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join("nn_from_scratch_test_save_load.json");
+        let model_a = Model::from(
+            Sequential::new()
+                .dense(2, 3)
+                .batch_norm(3)
+                .layer_norm(3)
+                .dense(3, 1),
+        );
+        let xs: Vec<Vec<RcScalar>> = (0..4)
+            .map(|i| {
+                vec![
+                    RcScalar::new(Scalar::new(i as f32)),
+                    RcScalar::new(Scalar::new(1f32 - i as f32)),
+                ]
+            })
+            .collect();
+        // A training pass moves the running statistics away from their defaults
+        model_a.feed_foward_batch(xs);
+        model_a.save(&path).unwrap();
+
+        let model_b = Model::from(
+            Sequential::new()
+                .dense(2, 3)
+                .batch_norm(3)
+                .layer_norm(3)
+                .dense(3, 1),
+        );
+        model_b.load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(model_a.state(), model_b.state());
+        assert_ne!(model_b.state().modules[1].buffers[0], 0f32);
+
+        let model_c = Model::new(vec![2, 3, 1]);
+        assert!(model_c.load_state(&model_a.state()).is_err());
+    }
+
+    #[test]
+    fn test_spiral_classification() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut data = spiral(80, &mut rng);
+        // A random quarter of the points is held out, interleaved with the training points
+        data.shuffle(&mut rng);
+        let test = data.split_off(180);
+        let model_a = Model::from(Sequential::new().dense(2, 24).tanh().dense(24, 3));
+
+        let parameters = model_a.parameters();
+        let mut velocity = vec![0f32; parameters.len()];
+        for _ in 0..30 {
+            data.shuffle(&mut rng);
+            for batch in data.chunks(15) {
+                let mut loss = RcScalar::new(Scalar::new(0f32));
+                for (x, class) in batch {
+                    loss =
+                        loss + cross_entropy(&model_a.feed_foward(RcScalar::vec_from(x)), *class);
+                }
+                loss = loss * (1f32 / batch.len() as f32);
+
+                for p in parameters.iter() {
+                    p.0.borrow_mut().grad = 0f32;
+                }
+                loss.backwards();
+                for (p, v) in parameters.iter().zip(velocity.iter_mut()) {
+                    let mut borrowed = p.0.borrow_mut();
+                    *v = 0.9 * *v - 0.1 * borrowed.grad;
+                    borrowed.data += *v;
+                }
+            }
+        }
+
+        let correct = test
+            .iter()
+            .filter(|(x, class)| model_a.predict_class(RcScalar::vec_from(x)) == *class)
+            .count();
+        assert!(
+            correct as f32 / test.len() as f32 > 0.95,
+            "held-out accuracy {}/{}",
+            correct,
+            test.len()
+        );
+
+        let proba = model_a.predict_proba(RcScalar::vec_from(&data[0].0));
+        assert_eq!(proba.len(), 3);
+        assert!((proba.iter().sum::<f32>() - 1f32).abs() < 1e-5);
+    }
 }
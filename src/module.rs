@@ -0,0 +1,54 @@
+use crate::activation::Activation;
+use crate::scalar::RcScalar;
+
+/// A building block of a model: maps a vector of scalars to another vector of scalars.
+///
+/// Modules that look across the whole batch (e.g. `BatchNorm1d`) override `feed_foward_batch`,
+/// everything else only has to implement the per-sample `feed_foward`.
+pub trait Module {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar>;
+
+    fn feed_foward_batch(&self, inputs: Vec<Vec<RcScalar>>) -> Vec<Vec<RcScalar>> {
+        inputs
+            .into_iter()
+            .map(|input: Vec<RcScalar>| self.feed_foward(input))
+            .collect()
+    }
+
+    /// Trainable scalars, updated by the optimizer.
+    fn parameters(&self) -> Vec<RcScalar>;
+
+    /// The subset of `parameters` that are additive offsets, exempt from weight penalties by default.
+    fn biases(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    /// Short description of the module, e.g. `Dense(3, 4, Tanh)`.
+    fn name(&self) -> String;
+
+    /// The elementwise non-linearity the module applies, if any, as shown by `Model::summary`.
+    fn activation(&self) -> Option<Activation> {
+        None
+    }
+
+    /// The width of input the module takes, or `None` if it takes more than one, e.g. a
+    /// sequence of any length, or any width at all.
+    fn input_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Non-trainable state (e.g. running statistics) that must survive save/load.
+    fn buffers(&self) -> Vec<f32> {
+        Vec::new()
+    }
+
+    fn load_buffers(&self, buffers: &[f32]) {
+        assert!(buffers.is_empty(), "module has no buffers to load");
+    }
+
+    /// Switches between training and evaluation behaviour. No-op for most modules.
+    fn set_training(&mut self, _training: bool) {}
+
+    /// Restarts the module's random draws (e.g. dropout masks) from `seed`. No-op for most modules.
+    fn seed(&self, _seed: u64) {}
+}
@@ -1,14 +1,15 @@
+use crate::activation::Activation;
 use crate::scalar::{RcScalar, Scalar};
 use rand::Rng;
 use std::fmt;
 use std::iter::zip;
 use std::vec::Vec;
 
-
 #[derive(Debug, Clone)]
 pub struct Neuron {
     pub w: Vec<RcScalar>,
     pub b: RcScalar,
+    pub activation: Activation,
 }
 
 impl fmt::Display for Neuron {
@@ -26,20 +27,26 @@ impl fmt::Display for Neuron {
 
 impl Neuron {
     pub fn new(nin: usize) -> Self {
+        Neuron::with_activation(nin, Activation::Tanh)
+    }
+
+    pub fn with_activation(nin: usize, activation: Activation) -> Self {
         let mut rng = rand::thread_rng();
         let w: Vec<RcScalar> = (0..nin)
             .map(|_| RcScalar::new(Scalar::new(rng.gen_range(-1.0..1.0))))
             .collect();
         let b = RcScalar::new(Scalar::new(0.0));
-        Self { w, b }
+        Self { w, b, activation }
     }
 
     pub fn feed_foward(self, scalars: &Vec<RcScalar>) -> RcScalar {
         assert_eq!(self.w.len(), scalars.len());
-        zip(self.w, scalars)
-            .map(|(a, b)| a * b.clone())
-            .fold(self.b, |acc, x| acc + x)
-            .tanh()
+        let activation = self.activation;
+        activation.apply(
+            zip(self.w, scalars)
+                .map(|(a, b)| a * b.clone())
+                .fold(self.b, |acc, x| acc + x),
+        )
     }
 
     pub fn parameters(&self) -> Vec<RcScalar> {
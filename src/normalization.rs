@@ -0,0 +1,344 @@
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+use std::cell::RefCell;
+
+const DEFAULT_EPS: f32 = 1e-5;
+const DEFAULT_MOMENTUM: f32 = 0.1;
+
+fn mean(scalars: &[RcScalar]) -> RcScalar {
+    let n = scalars.len() as f32;
+    scalars
+        .iter()
+        .fold(RcScalar::new(Scalar::new(0f32)), |acc, x| {
+            acc + RcScalar::clone(x)
+        })
+        * (1f32 / n)
+}
+
+/// Biased variance, as used to normalize within a batch.
+fn variance(scalars: &[RcScalar], mean: &RcScalar) -> RcScalar {
+    let n = scalars.len() as f32;
+    scalars
+        .iter()
+        .fold(RcScalar::new(Scalar::new(0f32)), |acc, x| {
+            acc + (RcScalar::clone(x) - RcScalar::clone(mean)).square()
+        })
+        * (1f32 / n)
+}
+
+/// Normalizes every feature over the batch, then scales & shifts it by learnable `gamma` & `beta`.
+///
+/// In training mode the batch statistics are used and folded into running estimates,
+/// in evaluation mode the running estimates are used instead. A single sample has no batch
+/// statistics, so it is normalized with the running estimates in either mode, leaving them as
+/// they are.
+pub struct BatchNorm1d {
+    gamma: Vec<RcScalar>,
+    beta: Vec<RcScalar>,
+    running_mean: RefCell<Vec<f32>>,
+    running_var: RefCell<Vec<f32>>,
+    momentum: f32,
+    eps: f32,
+    training: bool,
+}
+
+impl BatchNorm1d {
+    pub fn new(num_features: usize) -> Self {
+        BatchNorm1d {
+            gamma: (0..num_features)
+                .map(|_| RcScalar::new(Scalar::new(1f32)))
+                .collect(),
+            beta: (0..num_features)
+                .map(|_| RcScalar::new(Scalar::new(0f32)))
+                .collect(),
+            running_mean: RefCell::new(vec![0f32; num_features]),
+            running_var: RefCell::new(vec![1f32; num_features]),
+            momentum: DEFAULT_MOMENTUM,
+            eps: DEFAULT_EPS,
+            training: true,
+        }
+    }
+
+    pub fn running_mean(&self) -> Vec<f32> {
+        self.running_mean.borrow().clone()
+    }
+
+    pub fn running_var(&self) -> Vec<f32> {
+        self.running_var.borrow().clone()
+    }
+
+    fn feed_foward_train(&self, inputs: Vec<Vec<RcScalar>>) -> Vec<Vec<RcScalar>> {
+        let n = inputs.len();
+        let mut outputs: Vec<Vec<RcScalar>> = vec![Vec::with_capacity(self.gamma.len()); n];
+        let mut running_mean = self.running_mean.borrow_mut();
+        let mut running_var = self.running_var.borrow_mut();
+
+        for (j, (gamma, beta)) in self.gamma.iter().zip(self.beta.iter()).enumerate() {
+            let column: Vec<RcScalar> = inputs.iter().map(|x| RcScalar::clone(&x[j])).collect();
+            let mu = mean(&column);
+            let var = variance(&column, &mu);
+            let inv_std = (RcScalar::clone(&var) + self.eps).powf(-0.5);
+
+            for (output, x) in outputs.iter_mut().zip(column) {
+                let x_hat = (x - RcScalar::clone(&mu)) * RcScalar::clone(&inv_std);
+                output.push(x_hat * RcScalar::clone(gamma) + RcScalar::clone(beta));
+            }
+
+            // Running variance is tracked unbiased, the batch itself is normalized with the biased one
+            let unbiased_var = var.0.borrow().data * n as f32 / (n - 1) as f32;
+            running_mean[j] =
+                (1f32 - self.momentum) * running_mean[j] + self.momentum * mu.0.borrow().data;
+            running_var[j] = (1f32 - self.momentum) * running_var[j] + self.momentum * unbiased_var;
+        }
+        outputs
+    }
+
+    fn feed_foward_eval(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        assert_eq!(input.len(), self.gamma.len());
+        let running_mean = self.running_mean.borrow();
+        let running_var = self.running_var.borrow();
+        input
+            .into_iter()
+            .enumerate()
+            .map(|(j, x)| {
+                let inv_std = 1f32 / (running_var[j] + self.eps).sqrt();
+                (x + -running_mean[j]) * inv_std * RcScalar::clone(&self.gamma[j])
+                    + RcScalar::clone(&self.beta[j])
+            })
+            .collect()
+    }
+}
+
+impl Module for BatchNorm1d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        self.feed_foward_batch(vec![input]).remove(0)
+    }
+
+    fn feed_foward_batch(&self, inputs: Vec<Vec<RcScalar>>) -> Vec<Vec<RcScalar>> {
+        if self.training && inputs.len() > 1 {
+            self.feed_foward_train(inputs)
+        } else {
+            inputs
+                .into_iter()
+                .map(|input| self.feed_foward_eval(input))
+                .collect()
+        }
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.gamma.iter().chain(self.beta.iter()).cloned().collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.beta.clone()
+    }
+
+    fn name(&self) -> String {
+        format!("BatchNorm1d({})", self.gamma.len())
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        Some(self.gamma.len())
+    }
+
+    fn buffers(&self) -> Vec<f32> {
+        let mut buffers = self.running_mean();
+        buffers.extend(self.running_var());
+        buffers
+    }
+
+    fn load_buffers(&self, buffers: &[f32]) {
+        let num_features = self.gamma.len();
+        assert_eq!(buffers.len(), 2 * num_features);
+        *self.running_mean.borrow_mut() = buffers[..num_features].to_vec();
+        *self.running_var.borrow_mut() = buffers[num_features..].to_vec();
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+}
+
+/// Normalizes the features of every sample on its own, then scales & shifts them by
+/// learnable `gamma` & `beta`. Behaves the same in training and evaluation mode.
+pub struct LayerNorm {
+    gamma: Vec<RcScalar>,
+    beta: Vec<RcScalar>,
+    eps: f32,
+}
+
+impl LayerNorm {
+    pub fn new(normalized_shape: usize) -> Self {
+        LayerNorm {
+            gamma: (0..normalized_shape)
+                .map(|_| RcScalar::new(Scalar::new(1f32)))
+                .collect(),
+            beta: (0..normalized_shape)
+                .map(|_| RcScalar::new(Scalar::new(0f32)))
+                .collect(),
+            eps: DEFAULT_EPS,
+        }
+    }
+}
+
+impl Module for LayerNorm {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        assert_eq!(input.len(), self.gamma.len());
+        let mu = mean(&input);
+        let var = variance(&input, &mu);
+        let inv_std = (var + self.eps).powf(-0.5);
+
+        input
+            .into_iter()
+            .zip(self.gamma.iter().zip(self.beta.iter()))
+            .map(|(x, (gamma, beta))| {
+                (x - RcScalar::clone(&mu)) * RcScalar::clone(&inv_std) * RcScalar::clone(gamma)
+                    + RcScalar::clone(beta)
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.gamma.iter().chain(self.beta.iter()).cloned().collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.beta.clone()
+    }
+
+    fn name(&self) -> String {
+        format!("LayerNorm({})", self.gamma.len())
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        Some(self.gamma.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_scalars(rows: &[Vec<f32>]) -> Vec<Vec<RcScalar>> {
+        rows.iter().map(|row| RcScalar::vec_from(row)).collect()
+    }
+
+    // Weighted sum of the outputs, so that the gradient w.r.t. the inputs is not trivially zero
+    fn weighted_loss(outputs: &[Vec<RcScalar>]) -> RcScalar {
+        let mut loss = RcScalar::new(Scalar::new(0f32));
+        for (i, row) in outputs.iter().enumerate() {
+            for (j, y) in row.iter().enumerate() {
+                loss = loss + RcScalar::clone(y) * (1f32 + i as f32 + 0.5 * j as f32);
+            }
+        }
+        loss
+    }
+
+    fn assert_gradients_match<M: Module>(module: &M, rows: Vec<Vec<f32>>) {
+        let inputs = to_scalars(&rows);
+        let loss = weighted_loss(&module.feed_foward_batch(inputs.clone()));
+        loss.backwards();
+
+        let h = 1e-2;
+        for i in 0..rows.len() {
+            for j in 0..rows[i].len() {
+                let mut plus = rows.clone();
+                plus[i][j] += h;
+                let mut minus = rows.clone();
+                minus[i][j] -= h;
+                let loss_plus = weighted_loss(&module.feed_foward_batch(to_scalars(&plus)));
+                let loss_minus = weighted_loss(&module.feed_foward_batch(to_scalars(&minus)));
+                let numeric = (loss_plus.0.borrow().data - loss_minus.0.borrow().data) / (2f32 * h);
+                let analytic = inputs[i][j].0.borrow().grad;
+                assert!(
+                    (numeric - analytic).abs() < 2e-2,
+                    "grad mismatch at ({}, {}): numeric {} vs analytic {}",
+                    i,
+                    j,
+                    numeric,
+                    analytic
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_norm_train() {
+        let batch_norm = BatchNorm1d::new(2);
+        let outputs = batch_norm.feed_foward_batch(to_scalars(&[
+            vec![1.0, -2.0],
+            vec![3.0, 0.0],
+            vec![5.0, 2.0],
+        ]));
+
+        for j in 0..2 {
+            let column: Vec<f32> = outputs.iter().map(|y| y[j].0.borrow().data).collect();
+            let mean: f32 = column.iter().sum::<f32>() / 3.0;
+            let var: f32 = column.iter().map(|y| (y - mean).powi(2)).sum::<f32>() / 3.0;
+            assert!(mean.abs() < 1e-5);
+            assert!((var - 1.0).abs() < 1e-3);
+        }
+
+        // Batch means are 3 & 0, unbiased variances are 4 & 4
+        assert!((batch_norm.running_mean()[0] - 0.3).abs() < 1e-6);
+        assert!((batch_norm.running_mean()[1] - 0.0).abs() < 1e-6);
+        assert!((batch_norm.running_var()[0] - 1.3).abs() < 1e-5);
+        assert!((batch_norm.running_var()[1] - 1.3).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_batch_norm_eval() {
+        let mut batch_norm = BatchNorm1d::new(1);
+        batch_norm.load_buffers(&[2.0, 4.0]);
+        batch_norm.set_training(false);
+
+        let output = batch_norm.feed_foward(vec![RcScalar::new(Scalar::new(6.0))]);
+
+        assert!((output[0].0.borrow().data - 4.0 / (4.0f32 + DEFAULT_EPS).sqrt()).abs() < 1e-6);
+        assert_eq!(batch_norm.running_mean(), vec![2.0]);
+
+        // A lone sample in training mode falls back to the running estimates
+        batch_norm.set_training(true);
+        let single = batch_norm.feed_foward(vec![RcScalar::new(Scalar::new(6.0))]);
+        assert_eq!(single[0].0.borrow().data, output[0].0.borrow().data);
+        assert_eq!(batch_norm.running_var(), vec![4.0]);
+    }
+
+    #[test]
+    fn test_batch_norm_backward() {
+        let batch_norm = BatchNorm1d::new(2);
+        batch_norm.gamma[1].0.borrow_mut().data = 1.5;
+        assert_gradients_match(
+            &batch_norm,
+            vec![
+                vec![0.5, -1.0],
+                vec![1.5, 0.25],
+                vec![-0.75, 2.0],
+                vec![0.1, 0.3],
+            ],
+        );
+    }
+
+    #[test]
+    fn test_layer_norm() {
+        let layer_norm = LayerNorm::new(4);
+        let output = layer_norm.feed_foward(to_scalars(&[vec![1.0, 2.0, 3.0, 6.0]]).remove(0));
+        let values: Vec<f32> = output.iter().map(|y| y.0.borrow().data).collect();
+
+        let mean: f32 = values.iter().sum::<f32>() / 4.0;
+        let var: f32 = values.iter().map(|y| (y - mean).powi(2)).sum::<f32>() / 4.0;
+        assert!(mean.abs() < 1e-5);
+        assert!((var - 1.0).abs() < 1e-3);
+        assert_eq!(layer_norm.parameters().len(), 8);
+    }
+
+    #[test]
+    fn test_layer_norm_backward() {
+        let layer_norm = LayerNorm::new(3);
+        layer_norm.beta[0].0.borrow_mut().data = 0.5;
+        assert_gradients_match(
+            &layer_norm,
+            vec![vec![0.5, -1.0, 2.0], vec![1.5, 0.25, -0.3]],
+        );
+    }
+}
@@ -0,0 +1,452 @@
+use crate::regularization::Regularizer;
+use crate::scalar::RcScalar;
+use serde::de::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Updates parameters from the gradients left by `backwards()`.
+pub trait Optimizer {
+    fn step(&mut self);
+
+    fn parameters(&self) -> &[RcScalar];
+
+    fn learning_rate(&self) -> f32;
+
+    fn set_learning_rate(&mut self, lr: f32);
+
+    /// Learning rate & per-parameter buffers (momentum, moments, ...), enough to resume training.
+    fn state(&self) -> Value;
+
+    /// Restores `state`, failing if it was taken for a different number of parameters.
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()>;
+
+    fn zero_grad(&self) {
+        for p in self.parameters() {
+            p.0.borrow_mut().grad = 0f32;
+        }
+    }
+}
+
+impl Optimizer for Box<dyn Optimizer> {
+    fn step(&mut self) {
+        (**self).step()
+    }
+
+    fn parameters(&self) -> &[RcScalar] {
+        (**self).parameters()
+    }
+
+    fn learning_rate(&self) -> f32 {
+        (**self).learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        (**self).set_learning_rate(lr)
+    }
+
+    fn state(&self) -> Value {
+        (**self).state()
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        (**self).load_state(state)
+    }
+
+    fn zero_grad(&self) {
+        (**self).zero_grad()
+    }
+}
+
+/// A named set of parameters sharing optimizer settings.
+pub struct ParamGroup {
+    pub name: String,
+    pub parameters: Vec<RcScalar>,
+    /// Multiplies the optimizer's learning rate for this group, so schedulers keep the ratio.
+    pub lr_scale: f32,
+}
+
+impl ParamGroup {
+    pub fn new(name: &str, parameters: Vec<RcScalar>) -> Self {
+        ParamGroup {
+            name: name.to_string(),
+            parameters,
+            lr_scale: 1f32,
+        }
+    }
+
+    pub fn lr_scale(mut self, lr_scale: f32) -> Self {
+        self.lr_scale = lr_scale;
+        self
+    }
+
+    /// Stops gradients from accumulating into the group & optimizers from updating it.
+    pub fn freeze(&self) {
+        for p in self.parameters.iter() {
+            p.set_requires_grad(false);
+        }
+    }
+
+    pub fn unfreeze(&self) {
+        for p in self.parameters.iter() {
+            p.set_requires_grad(true);
+        }
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.parameters.iter().all(|p| !p.requires_grad())
+    }
+}
+
+fn flatten_groups(groups: Vec<ParamGroup>) -> (Vec<RcScalar>, Vec<f32>) {
+    let mut parameters = Vec::new();
+    let mut lr_scales = Vec::new();
+    for group in groups {
+        lr_scales.extend(vec![group.lr_scale; group.parameters.len()]);
+        parameters.extend(group.parameters);
+    }
+    (parameters, lr_scales)
+}
+
+// Decoupled weight decay at each parameter's scaled learning rate. Parameters the optimizer
+// does not update are decayed at the base rate.
+fn decay(regularizers: &[Regularizer], parameters: &[RcScalar], lr_scales: &[f32], lr: f32) {
+    if regularizers.is_empty() {
+        return;
+    }
+    let scales: HashMap<usize, f32> = parameters
+        .iter()
+        .zip(lr_scales.iter())
+        .map(|(p, &scale)| (p.0.borrow().uid, scale))
+        .collect();
+    for regularizer in regularizers.iter() {
+        regularizer.decay_with(|w| lr * scales.get(&w.0.borrow().uid).copied().unwrap_or(1f32));
+    }
+}
+
+fn check_len(name: &str, expected: usize, found: usize) -> serde_json::Result<()> {
+    if expected != found {
+        return Err(serde_json::Error::custom(format!(
+            "expected {} values in {}, found {}",
+            expected, name, found
+        )));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct SgdState {
+    lr: f32,
+    velocity: Vec<f32>,
+}
+
+/// Stochastic gradient descent, optionally with (heavy-ball) momentum.
+pub struct Sgd {
+    parameters: Vec<RcScalar>,
+    lr_scales: Vec<f32>,
+    lr: f32,
+    momentum: f32,
+    velocity: Vec<f32>,
+    weight_decay: Vec<Regularizer>,
+}
+
+impl Sgd {
+    pub fn new(parameters: Vec<RcScalar>, lr: f32) -> Self {
+        Sgd::with_groups(vec![ParamGroup::new("default", parameters)], lr)
+    }
+
+    pub fn with_groups(groups: Vec<ParamGroup>, lr: f32) -> Self {
+        let (parameters, lr_scales) = flatten_groups(groups);
+        let velocity = vec![0f32; parameters.len()];
+        Sgd {
+            parameters,
+            lr_scales,
+            lr,
+            momentum: 0f32,
+            velocity,
+            weight_decay: Vec::new(),
+        }
+    }
+
+    pub fn momentum(mut self, momentum: f32) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    /// Decoupled weight decay, applied to the regularizer's parameters on every step at their
+    /// group's learning rate.
+    pub fn weight_decay(mut self, regularizer: Regularizer) -> Self {
+        self.weight_decay.push(regularizer);
+        self
+    }
+}
+
+impl Optimizer for Sgd {
+    fn step(&mut self) {
+        decay(
+            &self.weight_decay,
+            &self.parameters,
+            &self.lr_scales,
+            self.lr,
+        );
+        for ((p, v), scale) in self
+            .parameters
+            .iter()
+            .zip(self.velocity.iter_mut())
+            .zip(self.lr_scales.iter())
+        {
+            let mut borrowed = p.0.borrow_mut();
+            if !borrowed.requires_grad {
+                continue;
+            }
+            *v = self.momentum * *v + borrowed.grad;
+            borrowed.data -= self.lr * scale * *v;
+        }
+    }
+
+    fn parameters(&self) -> &[RcScalar] {
+        &self.parameters
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.lr = lr;
+    }
+
+    fn state(&self) -> Value {
+        serde_json::json!(SgdState {
+            lr: self.lr,
+            velocity: self.velocity.clone(),
+        })
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        let state: SgdState = serde_json::from_value(state.clone())?;
+        check_len("velocity", self.velocity.len(), state.velocity.len())?;
+        self.lr = state.lr;
+        self.velocity = state.velocity;
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AdamState {
+    lr: f32,
+    m: Vec<f32>,
+    v: Vec<f32>,
+    t: i32,
+}
+
+/// Adam: per-parameter step sizes from bias-corrected estimates of the gradient's first & second moments.
+pub struct Adam {
+    parameters: Vec<RcScalar>,
+    lr_scales: Vec<f32>,
+    lr: f32,
+    beta_1: f32,
+    beta_2: f32,
+    eps: f32,
+    m: Vec<f32>,
+    v: Vec<f32>,
+    t: i32,
+    weight_decay: Vec<Regularizer>,
+}
+
+impl Adam {
+    pub fn new(parameters: Vec<RcScalar>, lr: f32) -> Self {
+        Adam::with_groups(vec![ParamGroup::new("default", parameters)], lr)
+    }
+
+    pub fn with_groups(groups: Vec<ParamGroup>, lr: f32) -> Self {
+        let (parameters, lr_scales) = flatten_groups(groups);
+        let n = parameters.len();
+        Adam {
+            parameters,
+            lr_scales,
+            lr,
+            beta_1: 0.9,
+            beta_2: 0.999,
+            eps: 1e-8,
+            m: vec![0f32; n],
+            v: vec![0f32; n],
+            t: 0,
+            weight_decay: Vec::new(),
+        }
+    }
+
+    pub fn betas(mut self, beta_1: f32, beta_2: f32) -> Self {
+        self.beta_1 = beta_1;
+        self.beta_2 = beta_2;
+        self
+    }
+
+    /// Decoupled weight decay (AdamW), applied to the regularizer's parameters on every step at
+    /// their group's learning rate.
+    pub fn weight_decay(mut self, regularizer: Regularizer) -> Self {
+        self.weight_decay.push(regularizer);
+        self
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self) {
+        self.t += 1;
+        decay(
+            &self.weight_decay,
+            &self.parameters,
+            &self.lr_scales,
+            self.lr,
+        );
+        let bias_correction_1 = 1f32 - self.beta_1.powi(self.t);
+        let bias_correction_2 = 1f32 - self.beta_2.powi(self.t);
+        for (i, p) in self.parameters.iter().enumerate() {
+            let mut borrowed = p.0.borrow_mut();
+            if !borrowed.requires_grad {
+                continue;
+            }
+            self.m[i] = self.beta_1 * self.m[i] + (1f32 - self.beta_1) * borrowed.grad;
+            self.v[i] = self.beta_2 * self.v[i] + (1f32 - self.beta_2) * borrowed.grad.powi(2);
+            let m_hat = self.m[i] / bias_correction_1;
+            let v_hat = self.v[i] / bias_correction_2;
+            borrowed.data -= self.lr * self.lr_scales[i] * m_hat / (v_hat.sqrt() + self.eps);
+        }
+    }
+
+    fn parameters(&self) -> &[RcScalar] {
+        &self.parameters
+    }
+
+    fn learning_rate(&self) -> f32 {
+        self.lr
+    }
+
+    fn set_learning_rate(&mut self, lr: f32) {
+        self.lr = lr;
+    }
+
+    fn state(&self) -> Value {
+        serde_json::json!(AdamState {
+            lr: self.lr,
+            m: self.m.clone(),
+            v: self.v.clone(),
+            t: self.t,
+        })
+    }
+
+    fn load_state(&mut self, state: &Value) -> serde_json::Result<()> {
+        let state: AdamState = serde_json::from_value(state.clone())?;
+        check_len("m", self.m.len(), state.m.len())?;
+        check_len("v", self.v.len(), state.v.len())?;
+        self.lr = state.lr;
+        self.m = state.m;
+        self.v = state.v;
+        self.t = state.t;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regularization::Penalty;
+    use crate::scalar::Scalar;
+
+    fn with_grad(data: f32, grad: f32) -> RcScalar {
+        let p = RcScalar::new(Scalar::new(data));
+        p.0.borrow_mut().grad = grad;
+        p
+    }
+
+    #[test]
+    fn test_sgd() {
+        let p = with_grad(1.0, 2.0);
+        let mut optimizer = Sgd::new(vec![RcScalar::clone(&p)], 0.1).momentum(0.5);
+
+        optimizer.step();
+        assert!((p.0.borrow().data - 0.8).abs() < 1e-6);
+        optimizer.step();
+        // velocity = 0.5 * 2 + 2 = 3
+        assert!((p.0.borrow().data - 0.5).abs() < 1e-6);
+
+        optimizer.zero_grad();
+        assert_eq!(p.0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_adam() {
+        let p = with_grad(1.0, -4.0);
+        let mut optimizer = Adam::new(vec![RcScalar::clone(&p)], 0.1);
+
+        // The first bias-corrected step is lr * sign(grad), whatever the gradient's scale
+        optimizer.step();
+        assert!((p.0.borrow().data - 1.1).abs() < 1e-5);
+
+        optimizer.set_learning_rate(0.01);
+        assert_eq!(optimizer.learning_rate(), 0.01);
+    }
+
+    #[test]
+    fn test_state() {
+        let p = with_grad(1.0, -4.0);
+        let mut optimizer = Adam::new(vec![RcScalar::clone(&p)], 0.1);
+        optimizer.step();
+
+        let mut resumed = Adam::new(vec![RcScalar::clone(&p)], 0.5);
+        resumed.load_state(&optimizer.state()).unwrap();
+        assert_eq!(resumed.state(), optimizer.state());
+        assert_eq!(resumed.learning_rate(), 0.1);
+
+        let mut other = Sgd::new(vec![RcScalar::clone(&p), RcScalar::clone(&p)], 0.1);
+        assert!(other.load_state(&Sgd::new(vec![p], 0.1).state()).is_err());
+    }
+
+    #[test]
+    fn test_param_groups() {
+        let head = with_grad(1.0, 1.0);
+        let body = with_grad(1.0, 1.0);
+        let frozen = with_grad(1.0, 1.0);
+        let groups = vec![
+            ParamGroup::new("head", vec![RcScalar::clone(&head)]),
+            ParamGroup::new("body", vec![RcScalar::clone(&body)]).lr_scale(0.1),
+            ParamGroup::new("frozen", vec![RcScalar::clone(&frozen)]),
+        ];
+        groups[2].freeze();
+        assert!(groups[2].is_frozen());
+        let mut optimizer = Sgd::with_groups(groups, 0.5);
+
+        optimizer.step();
+
+        assert_eq!(head.0.borrow().data, 0.5);
+        assert!((body.0.borrow().data - 0.95).abs() < 1e-6);
+        assert_eq!(frozen.0.borrow().data, 1.0);
+    }
+
+    #[test]
+    fn test_weight_decay() {
+        let p = with_grad(2.0, 0.0);
+        let regularizer = Regularizer::from_parameters(Penalty::L2(0.5), vec![RcScalar::clone(&p)]);
+        let mut optimizer = Adam::new(vec![RcScalar::clone(&p)], 0.1).weight_decay(regularizer);
+
+        // A zero gradient leaves Adam's own update at zero, only the decay moves the weight
+        optimizer.step();
+        assert!((p.0.borrow().data - 1.9).abs() < 1e-6);
+
+        // Decay follows the group's learning rate too
+        let head = with_grad(2.0, 0.0);
+        let body = with_grad(2.0, 0.0);
+        let regularizer = Regularizer::from_parameters(
+            Penalty::L2(0.5),
+            vec![RcScalar::clone(&head), RcScalar::clone(&body)],
+        );
+        let groups = vec![
+            ParamGroup::new("head", vec![RcScalar::clone(&head)]),
+            ParamGroup::new("body", vec![RcScalar::clone(&body)]).lr_scale(0.1),
+        ];
+        let mut optimizer = Sgd::with_groups(groups, 0.1).weight_decay(regularizer);
+        optimizer.step();
+        assert!((head.0.borrow().data - 1.9).abs() < 1e-6);
+        assert!((body.0.borrow().data - 1.99).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+
+// Slides a window over every channel of channel-major input & reduces each window to one scalar
+fn pool1d<F: Fn(&[RcScalar]) -> RcScalar>(
+    input: &[RcScalar],
+    channels: usize,
+    kernel_size: usize,
+    stride: usize,
+    reduce: F,
+) -> Vec<RcScalar> {
+    assert_eq!(input.len() % channels, 0);
+    let len = input.len() / channels;
+    assert!(
+        len >= kernel_size,
+        "input is shorter than the pooling window"
+    );
+    let out_len = (len - kernel_size) / stride + 1;
+
+    (0..channels)
+        .flat_map(|c| {
+            let channel = &input[c * len..(c + 1) * len];
+            (0..out_len)
+                .map(|t| reduce(&channel[t * stride..t * stride + kernel_size]))
+                .collect::<Vec<RcScalar>>()
+        })
+        .collect()
+}
+
+// Same as `pool1d`, with square windows over every channel of CHW input
+fn pool2d<F: Fn(&[RcScalar]) -> RcScalar>(
+    input: &[RcScalar],
+    channels: usize,
+    input_size: (usize, usize),
+    kernel_size: usize,
+    stride: usize,
+    reduce: F,
+) -> Vec<RcScalar> {
+    let (height, width) = input_size;
+    assert_eq!(input.len(), channels * height * width);
+    let (out_height, out_width) = pool2d_output_size(input_size, kernel_size, stride);
+
+    let mut output = Vec::with_capacity(channels * out_height * out_width);
+    for c in 0..channels {
+        for oy in 0..out_height {
+            for ox in 0..out_width {
+                let window: Vec<RcScalar> = (0..kernel_size)
+                    .flat_map(|ky| {
+                        let row = (c * height + oy * stride + ky) * width + ox * stride;
+                        input[row..row + kernel_size].iter().cloned()
+                    })
+                    .collect();
+                output.push(reduce(&window));
+            }
+        }
+    }
+    output
+}
+
+fn pool2d_output_size(
+    input_size: (usize, usize),
+    kernel_size: usize,
+    stride: usize,
+) -> (usize, usize) {
+    let (height, width) = input_size;
+    assert!(
+        height >= kernel_size && width >= kernel_size,
+        "input is smaller than the pooling window"
+    );
+    (
+        (height - kernel_size) / stride + 1,
+        (width - kernel_size) / stride + 1,
+    )
+}
+
+// Picks the largest scalar itself rather than a copy, so the gradient flows only to the max
+fn max(window: &[RcScalar]) -> RcScalar {
+    window.iter().fold(RcScalar::clone(&window[0]), |best, x| {
+        if x.0.borrow().data > best.0.borrow().data {
+            RcScalar::clone(x)
+        } else {
+            best
+        }
+    })
+}
+
+fn mean(window: &[RcScalar]) -> RcScalar {
+    window
+        .iter()
+        .fold(RcScalar::new(Scalar::new(0f32)), |acc, x| {
+            acc + RcScalar::clone(x)
+        })
+        * (1f32 / window.len() as f32)
+}
+
+/// Max over sliding windows of every channel. The stride defaults to the window size.
+pub struct MaxPool1d {
+    channels: usize,
+    kernel_size: usize,
+    stride: usize,
+}
+
+impl MaxPool1d {
+    pub fn new(channels: usize, kernel_size: usize) -> Self {
+        MaxPool1d {
+            channels,
+            kernel_size,
+            stride: kernel_size,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.stride = stride;
+        self
+    }
+}
+
+impl Module for MaxPool1d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        pool1d(&input, self.channels, self.kernel_size, self.stride, max)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "MaxPool1d({}, kernel_size={}, stride={})",
+            self.channels, self.kernel_size, self.stride
+        )
+    }
+}
+
+/// Mean over sliding windows of every channel. The stride defaults to the window size.
+pub struct AvgPool1d {
+    channels: usize,
+    kernel_size: usize,
+    stride: usize,
+}
+
+impl AvgPool1d {
+    pub fn new(channels: usize, kernel_size: usize) -> Self {
+        AvgPool1d {
+            channels,
+            kernel_size,
+            stride: kernel_size,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.stride = stride;
+        self
+    }
+}
+
+impl Module for AvgPool1d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        pool1d(&input, self.channels, self.kernel_size, self.stride, mean)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "AvgPool1d({}, kernel_size={}, stride={})",
+            self.channels, self.kernel_size, self.stride
+        )
+    }
+}
+
+/// Max over square sliding windows of every channel of CHW input. The stride defaults to the window size.
+pub struct MaxPool2d {
+    channels: usize,
+    input_size: (usize, usize),
+    kernel_size: usize,
+    stride: usize,
+}
+
+impl MaxPool2d {
+    pub fn new(channels: usize, input_size: (usize, usize), kernel_size: usize) -> Self {
+        MaxPool2d {
+            channels,
+            input_size,
+            kernel_size,
+            stride: kernel_size,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.stride = stride;
+        self
+    }
+
+    pub fn output_size(&self) -> (usize, usize) {
+        pool2d_output_size(self.input_size, self.kernel_size, self.stride)
+    }
+}
+
+impl Module for MaxPool2d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        pool2d(
+            &input,
+            self.channels,
+            self.input_size,
+            self.kernel_size,
+            self.stride,
+            max,
+        )
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "MaxPool2d({}, kernel_size={}, stride={})",
+            self.channels, self.kernel_size, self.stride
+        )
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        let (height, width) = self.input_size;
+        Some(self.channels * height * width)
+    }
+}
+
+/// Mean over square sliding windows of every channel of CHW input. The stride defaults to the window size.
+pub struct AvgPool2d {
+    channels: usize,
+    input_size: (usize, usize),
+    kernel_size: usize,
+    stride: usize,
+}
+
+impl AvgPool2d {
+    pub fn new(channels: usize, input_size: (usize, usize), kernel_size: usize) -> Self {
+        AvgPool2d {
+            channels,
+            input_size,
+            kernel_size,
+            stride: kernel_size,
+        }
+    }
+
+    pub fn stride(mut self, stride: usize) -> Self {
+        assert!(stride > 0);
+        self.stride = stride;
+        self
+    }
+
+    pub fn output_size(&self) -> (usize, usize) {
+        pool2d_output_size(self.input_size, self.kernel_size, self.stride)
+    }
+}
+
+impl Module for AvgPool2d {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        pool2d(
+            &input,
+            self.channels,
+            self.input_size,
+            self.kernel_size,
+            self.stride,
+            mean,
+        )
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!(
+            "AvgPool2d({}, kernel_size={}, stride={})",
+            self.channels, self.kernel_size, self.stride
+        )
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        let (height, width) = self.input_size;
+        Some(self.channels * height * width)
+    }
+}
+
+/// Mean of every channel, whatever its size: turns channel-major feature maps into one value per channel.
+pub struct GlobalAvgPool {
+    channels: usize,
+}
+
+impl GlobalAvgPool {
+    pub fn new(channels: usize) -> Self {
+        GlobalAvgPool { channels }
+    }
+}
+
+impl Module for GlobalAvgPool {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        assert_eq!(input.len() % self.channels, 0);
+        input
+            .chunks(input.len() / self.channels)
+            .map(mean)
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        format!("GlobalAvgPool({})", self.channels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::max_gradient_error;
+
+    fn data(ys: &[RcScalar]) -> Vec<f32> {
+        ys.iter().map(|y| y.0.borrow().data).collect()
+    }
+
+    #[test]
+    fn test_max_pool() {
+        let pool = MaxPool1d::new(2, 2);
+        let input = RcScalar::vec_from(&[1.0, 3.0, 2.0, 0.0, 5.0, -1.0, -2.0, -3.0, 4.0, 4.5]);
+
+        let output = pool.feed_foward(input.clone());
+        assert_eq!(data(&output), vec![3.0, 2.0, -1.0, 4.0]);
+
+        output
+            .into_iter()
+            .fold(RcScalar::new(Scalar::new(0f32)), |acc, y| acc + y)
+            .backwards();
+        let grads: Vec<f32> = input.iter().map(|x| x.0.borrow().grad).collect();
+        assert_eq!(
+            grads,
+            vec![0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_avg_pool() {
+        let pool = AvgPool1d::new(1, 3).stride(1);
+        let input = RcScalar::vec_from(&[1.0, 2.0, 3.0, 6.0]);
+
+        let output = pool.feed_foward(input.clone());
+        assert_eq!(data(&output), vec![2.0, 11.0 / 3.0]);
+
+        output[0].backwards();
+        assert!((input[0].0.borrow().grad - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(input[3].0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_max_pool2d() {
+        let pool = MaxPool2d::new(1, (4, 4), 2);
+        let xs: Vec<f32> = (0..16).map(|i| ((i * 7) % 16) as f32).collect();
+        let input = RcScalar::vec_from(&xs);
+
+        let output = pool.feed_foward(input.clone());
+
+        // [0 7 14 5 / 12 3 10 1 / 8 15 6 13 / 4 11 2 9]
+        assert_eq!(data(&output), vec![12.0, 14.0, 15.0, 13.0]);
+        assert_eq!(pool.output_size(), (2, 2));
+
+        let f = |input: Vec<RcScalar>| {
+            pool.feed_foward(input)
+                .into_iter()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, y| acc + y)
+        };
+        assert!(max_gradient_error(f, &xs, 1e-2) < 1e-3);
+    }
+
+    #[test]
+    fn test_avg_pool2d() {
+        let pool = AvgPool2d::new(2, (3, 3), 2).stride(1);
+        let xs: Vec<f32> = (0..18).map(|i| (i as f32 * 0.3).cos()).collect();
+
+        assert_eq!(pool.feed_foward(RcScalar::vec_from(&xs)).len(), 2 * 2 * 2);
+        let f = |input: Vec<RcScalar>| {
+            pool.feed_foward(input)
+                .into_iter()
+                .enumerate()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, (i, y)| {
+                    acc + y.square() * (1f32 + i as f32)
+                })
+        };
+        assert!(max_gradient_error(f, &xs, 1e-2) < 1e-2);
+    }
+
+    #[test]
+    fn test_global_avg_pool() {
+        let pool = GlobalAvgPool::new(2);
+
+        let output = pool.feed_foward(RcScalar::vec_from(&[
+            1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0,
+        ]));
+
+        assert_eq!(data(&output), vec![2.5, 6.5]);
+    }
+}
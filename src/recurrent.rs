@@ -0,0 +1,446 @@
+use crate::activation::Activation;
+use crate::layer::Layer;
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+
+/// One time step of a recurrent network.
+///
+/// The carried state starts with the hidden state; cells that carry more (the LSTM cell state)
+/// append it after that.
+pub trait RecurrentCell {
+    fn input_size(&self) -> usize;
+
+    fn hidden_size(&self) -> usize;
+
+    fn state_size(&self) -> usize {
+        self.hidden_size()
+    }
+
+    fn step(&self, input: &[RcScalar], state: &[RcScalar]) -> Vec<RcScalar>;
+
+    fn parameters(&self) -> Vec<RcScalar>;
+
+    fn biases(&self) -> Vec<RcScalar>;
+
+    fn name(&self) -> String;
+}
+
+fn concat(xs: &[RcScalar], ys: &[RcScalar]) -> Vec<RcScalar> {
+    xs.iter().chain(ys.iter()).cloned().collect()
+}
+
+/// `h' = tanh(W [x, h] + b)`
+pub struct RnnCell {
+    input_size: usize,
+    hidden_size: usize,
+    hidden: Layer,
+}
+
+impl RnnCell {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        RnnCell {
+            input_size,
+            hidden_size,
+            hidden: Layer::with_activation(input_size + hidden_size, hidden_size, Activation::Tanh),
+        }
+    }
+}
+
+impl RecurrentCell for RnnCell {
+    fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn step(&self, input: &[RcScalar], state: &[RcScalar]) -> Vec<RcScalar> {
+        self.hidden.feed_foward(concat(input, state))
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.hidden.parameters()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.hidden.biases()
+    }
+
+    fn name(&self) -> String {
+        format!("RnnCell({}, {})", self.input_size, self.hidden_size)
+    }
+}
+
+/// Long short-term memory cell, carrying `[h, c]`:
+///
+/// `c' = f * c + i * g`, `h' = o * tanh(c')` with the input, forget & output gates `i`, `f`, `o`
+/// and the candidate `g` all computed from `[x, h]`.
+pub struct LstmCell {
+    input_size: usize,
+    hidden_size: usize,
+    input_gate: Layer,
+    forget_gate: Layer,
+    candidate: Layer,
+    output_gate: Layer,
+}
+
+impl LstmCell {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        let nin = input_size + hidden_size;
+        let forget_gate = Layer::with_activation(nin, hidden_size, Activation::Sigmoid);
+        // Start out remembering, so that gradients reach early time steps from the beginning
+        for b in forget_gate.biases() {
+            b.0.borrow_mut().data = 1f32;
+        }
+        LstmCell {
+            input_size,
+            hidden_size,
+            input_gate: Layer::with_activation(nin, hidden_size, Activation::Sigmoid),
+            forget_gate,
+            candidate: Layer::with_activation(nin, hidden_size, Activation::Tanh),
+            output_gate: Layer::with_activation(nin, hidden_size, Activation::Sigmoid),
+        }
+    }
+}
+
+impl RecurrentCell for LstmCell {
+    fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn state_size(&self) -> usize {
+        2 * self.hidden_size
+    }
+
+    fn step(&self, input: &[RcScalar], state: &[RcScalar]) -> Vec<RcScalar> {
+        let (h, c) = state.split_at(self.hidden_size);
+        let xh = concat(input, h);
+        let i = self.input_gate.feed_foward(xh.clone());
+        let f = self.forget_gate.feed_foward(xh.clone());
+        let g = self.candidate.feed_foward(xh.clone());
+        let o = self.output_gate.feed_foward(xh);
+
+        let c_next: Vec<RcScalar> = (0..self.hidden_size)
+            .map(|k| {
+                RcScalar::clone(&f[k]) * RcScalar::clone(&c[k])
+                    + RcScalar::clone(&i[k]) * RcScalar::clone(&g[k])
+            })
+            .collect();
+        let h_next: Vec<RcScalar> = (0..self.hidden_size)
+            .map(|k| RcScalar::clone(&o[k]) * c_next[k].tanh())
+            .collect();
+        concat(&h_next, &c_next)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        [
+            &self.input_gate,
+            &self.forget_gate,
+            &self.candidate,
+            &self.output_gate,
+        ]
+        .iter()
+        .flat_map(|layer| layer.parameters())
+        .collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        [
+            &self.input_gate,
+            &self.forget_gate,
+            &self.candidate,
+            &self.output_gate,
+        ]
+        .iter()
+        .flat_map(|layer| layer.biases())
+        .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("LstmCell({}, {})", self.input_size, self.hidden_size)
+    }
+}
+
+/// Gated recurrent unit:
+///
+/// `h' = (1 - z) * n + z * h` with `n = tanh(W_n x + b_n + r * (U_n h + c_n))` and the reset &
+/// update gates `r`, `z` computed from `[x, h]`.
+pub struct GruCell {
+    input_size: usize,
+    hidden_size: usize,
+    reset_gate: Layer,
+    update_gate: Layer,
+    candidate_input: Layer,
+    candidate_hidden: Layer,
+}
+
+impl GruCell {
+    pub fn new(input_size: usize, hidden_size: usize) -> Self {
+        let nin = input_size + hidden_size;
+        GruCell {
+            input_size,
+            hidden_size,
+            reset_gate: Layer::with_activation(nin, hidden_size, Activation::Sigmoid),
+            update_gate: Layer::with_activation(nin, hidden_size, Activation::Sigmoid),
+            candidate_input: Layer::with_activation(input_size, hidden_size, Activation::Linear),
+            candidate_hidden: Layer::with_activation(hidden_size, hidden_size, Activation::Linear),
+        }
+    }
+}
+
+impl RecurrentCell for GruCell {
+    fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    fn hidden_size(&self) -> usize {
+        self.hidden_size
+    }
+
+    fn step(&self, input: &[RcScalar], state: &[RcScalar]) -> Vec<RcScalar> {
+        let xh = concat(input, state);
+        let r = self.reset_gate.feed_foward(xh.clone());
+        let z = self.update_gate.feed_foward(xh);
+        let n_x = self.candidate_input.feed_foward(input.to_vec());
+        let n_h = self.candidate_hidden.feed_foward(state.to_vec());
+
+        (0..self.hidden_size)
+            .map(|k| {
+                let n = (RcScalar::clone(&n_x[k])
+                    + RcScalar::clone(&r[k]) * RcScalar::clone(&n_h[k]))
+                .tanh();
+                (-RcScalar::clone(&z[k]) + 1f32) * n
+                    + RcScalar::clone(&z[k]) * RcScalar::clone(&state[k])
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        [
+            &self.reset_gate,
+            &self.update_gate,
+            &self.candidate_input,
+            &self.candidate_hidden,
+        ]
+        .iter()
+        .flat_map(|layer| layer.parameters())
+        .collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        [
+            &self.reset_gate,
+            &self.update_gate,
+            &self.candidate_input,
+            &self.candidate_hidden,
+        ]
+        .iter()
+        .flat_map(|layer| layer.biases())
+        .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("GruCell({}, {})", self.input_size, self.hidden_size)
+    }
+}
+
+/// Unrolls a cell over a time-major sequence `[x_0, x_1, ...]`, flattened to one vector
+/// of `sequence length * input size` scalars, starting from a zero state.
+///
+/// Outputs either the hidden state of every step (flattened the same way) or only the last one.
+/// Calling `backwards()` on anything computed from the output then back-propagates through time;
+/// with `truncate_bptt(k)` the state is detached every `k` steps, so gradients flow back at most
+/// `k` steps.
+pub struct Recurrent<C: RecurrentCell> {
+    cell: C,
+    return_sequences: bool,
+    bptt_length: Option<usize>,
+}
+
+impl<C: RecurrentCell> Recurrent<C> {
+    pub fn new(cell: C) -> Self {
+        Recurrent {
+            cell,
+            return_sequences: false,
+            bptt_length: None,
+        }
+    }
+
+    pub fn return_sequences(mut self, return_sequences: bool) -> Self {
+        self.return_sequences = return_sequences;
+        self
+    }
+
+    pub fn truncate_bptt(mut self, bptt_length: usize) -> Self {
+        assert!(bptt_length > 0);
+        self.bptt_length = Some(bptt_length);
+        self
+    }
+
+    pub fn cell(&self) -> &C {
+        &self.cell
+    }
+}
+
+impl<C: RecurrentCell> Module for Recurrent<C> {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let input_size = self.cell.input_size();
+        let hidden_size = self.cell.hidden_size();
+        assert_eq!(input.len() % input_size, 0);
+
+        let mut state: Vec<RcScalar> = (0..self.cell.state_size())
+            .map(|_| RcScalar::new(Scalar::new(0f32)))
+            .collect();
+        let mut hidden_states: Vec<RcScalar> = Vec::new();
+        for (t, x) in input.chunks(input_size).enumerate() {
+            if let Some(k) = self.bptt_length {
+                if t > 0 && t % k == 0 {
+                    state = state.iter().map(|s| s.detach()).collect();
+                }
+            }
+            state = self.cell.step(x, &state);
+            if self.return_sequences {
+                hidden_states.extend(state[..hidden_size].iter().cloned());
+            }
+        }
+
+        if self.return_sequences {
+            hidden_states
+        } else {
+            state.truncate(hidden_size);
+            state
+        }
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.cell.parameters()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.cell.biases()
+    }
+
+    fn name(&self) -> String {
+        format!("Recurrent({})", self.cell.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradcheck::max_gradient_error;
+    use crate::layer::Init;
+    use crate::loss::mse;
+    use crate::model::Model;
+    use crate::sequential::Sequential;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn weighted_sum(ys: Vec<RcScalar>) -> RcScalar {
+        ys.into_iter()
+            .enumerate()
+            .fold(RcScalar::new(Scalar::new(0f32)), |acc, (i, y)| {
+                acc + y * (1f32 + i as f32)
+            })
+    }
+
+    #[test]
+    fn test_output_shapes() {
+        let xs = RcScalar::vec_from(&[0.1; 4 * 3]);
+
+        let rnn = Recurrent::new(RnnCell::new(3, 5));
+        let lstm = Recurrent::new(LstmCell::new(3, 5)).return_sequences(true);
+        let gru = Recurrent::new(GruCell::new(3, 5));
+
+        assert_eq!(rnn.feed_foward(xs.clone()).len(), 5);
+        assert_eq!(lstm.feed_foward(xs.clone()).len(), 4 * 5);
+        assert_eq!(gru.feed_foward(xs).len(), 5);
+        assert_eq!(rnn.parameters().len(), (3 + 5 + 1) * 5);
+        assert_eq!(lstm.parameters().len(), 4 * (3 + 5 + 1) * 5);
+        assert_eq!(
+            gru.parameters().len(),
+            2 * (3 + 5 + 1) * 5 + (3 + 1) * 5 + (5 + 1) * 5
+        );
+    }
+
+    #[test]
+    fn test_backward_through_time() {
+        let xs: Vec<f32> = (0..8).map(|i| (i as f32 * 0.7).sin()).collect();
+
+        let rnn = Recurrent::new(RnnCell::new(2, 3)).return_sequences(true);
+        let lstm = Recurrent::new(LstmCell::new(2, 3)).return_sequences(true);
+        let gru = Recurrent::new(GruCell::new(2, 3)).return_sequences(true);
+
+        assert!(max_gradient_error(|x| weighted_sum(rnn.feed_foward(x)), &xs, 1e-2) < 1e-2);
+        assert!(max_gradient_error(|x| weighted_sum(lstm.feed_foward(x)), &xs, 1e-2) < 1e-2);
+        assert!(max_gradient_error(|x| weighted_sum(gru.feed_foward(x)), &xs, 1e-2) < 1e-2);
+    }
+
+    #[test]
+    fn test_truncated_bptt() {
+        let rnn = Recurrent::new(GruCell::new(1, 2)).truncate_bptt(2);
+        let input = RcScalar::vec_from(&[0.5, -0.5, 0.25, 1.0]);
+
+        weighted_sum(rnn.feed_foward(input.clone())).backwards();
+
+        // Last output only sees steps 2 & 3 after the state was detached at step 2
+        assert_eq!(input[0].0.borrow().grad, 0.0);
+        assert_eq!(input[1].0.borrow().grad, 0.0);
+        assert_ne!(input[2].0.borrow().grad, 0.0);
+        assert_ne!(input[3].0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_learn_sum() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let data: Vec<(Vec<f32>, f32)> = (0..64)
+            .map(|_| {
+                let xs: Vec<f32> = (0..4).map(|_| rng.gen_range(-0.5f32..0.5f32)).collect();
+                let sum = xs.iter().sum();
+                (xs, sum)
+            })
+            .collect();
+        let cell = RnnCell::new(1, 6);
+        cell.hidden.reset_parameters(Init::Xavier, &mut rng);
+        let head = Layer::with_activation(6, 1, Activation::Linear);
+        head.reset_parameters(Init::Xavier, &mut rng);
+        let model_a = Model::from(Sequential::new().push(Recurrent::new(cell)).push(head));
+        let loss_of = |batch: &[(Vec<f32>, f32)]| {
+            batch
+                .iter()
+                .fold(RcScalar::new(Scalar::new(0f32)), |acc, (xs, sum)| {
+                    acc + mse(
+                        &model_a.feed_foward(RcScalar::vec_from(xs)),
+                        &RcScalar::vec_from(&[*sum]),
+                    )
+                })
+                * (1f32 / batch.len() as f32)
+        };
+
+        let parameters = model_a.parameters();
+        let mut velocity = vec![0f32; parameters.len()];
+        for _ in 0..60 {
+            for batch in data.chunks(8) {
+                let loss = loss_of(batch);
+                for p in parameters.iter() {
+                    p.0.borrow_mut().grad = 0f32;
+                }
+                loss.backwards();
+                for (p, v) in parameters.iter().zip(velocity.iter_mut()) {
+                    let mut borrowed = p.0.borrow_mut();
+                    *v = 0.9 * *v - 0.05 * borrowed.grad;
+                    borrowed.data += *v;
+                }
+            }
+        }
+
+        // The sums have a variance of 4/12 ~ 0.33, predicting their mean would score that much
+        let final_loss = loss_of(&data).0.borrow().data;
+        assert!(final_loss < 0.01, "loss {}", final_loss);
+    }
+}
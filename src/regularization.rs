@@ -0,0 +1,180 @@
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// A weight penalty, `l1 * sum(|w|) + l2 / 2 * sum(w^2)`.
+///
+/// The L2 term is halved so its gradient is `l2 * w`, making the loss-term and decoupled-decay
+/// forms of the same penalty line up for plain SGD.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Penalty {
+    L1(f32),
+    L2(f32),
+    ElasticNet { l1: f32, l2: f32 },
+}
+
+impl Penalty {
+    fn coefficients(&self) -> (f32, f32) {
+        match *self {
+            Penalty::L1(l1) => (l1, 0f32),
+            Penalty::L2(l2) => (0f32, l2),
+            Penalty::ElasticNet { l1, l2 } => (l1, l2),
+        }
+    }
+}
+
+/// Parameters that are not biases, according to `module.biases()`.
+pub fn weights(module: &dyn Module) -> Vec<RcScalar> {
+    let biases: HashSet<usize> = module
+        .biases()
+        .iter()
+        .map(|b: &RcScalar| b.0.borrow().uid)
+        .collect();
+    module
+        .parameters()
+        .into_iter()
+        .filter(|p: &RcScalar| !biases.contains(&p.0.borrow().uid))
+        .collect()
+}
+
+/// A penalty attached to a group of parameters.
+///
+/// It is applied either as a term added to the loss, so it flows through `backwards()` like
+/// any other, or as decoupled decay done by the optimizer next to its update (as in AdamW).
+pub struct Regularizer {
+    penalty: Penalty,
+    parameters: Vec<RcScalar>,
+}
+
+impl Regularizer {
+    /// Penalizes the weights of `module`, leaving its biases alone.
+    pub fn new(penalty: Penalty, module: &dyn Module) -> Self {
+        Regularizer::from_parameters(penalty, weights(module))
+    }
+
+    /// Penalizes every parameter of `module`, biases included.
+    pub fn with_biases(penalty: Penalty, module: &dyn Module) -> Self {
+        Regularizer::from_parameters(penalty, module.parameters())
+    }
+
+    pub fn from_parameters(penalty: Penalty, parameters: Vec<RcScalar>) -> Self {
+        Regularizer {
+            penalty,
+            parameters,
+        }
+    }
+
+    pub fn penalty(&self) -> Penalty {
+        self.penalty
+    }
+
+    pub fn parameters(&self) -> &[RcScalar] {
+        &self.parameters
+    }
+
+    /// The penalty as a node of the autograd graph, to be added to the loss.
+    pub fn loss(&self) -> RcScalar {
+        let (l1, l2) = self.penalty.coefficients();
+        self.parameters
+            .iter()
+            .fold(RcScalar::new(Scalar::new(0f32)), |acc, w| {
+                let mut acc = acc;
+                if l1 != 0f32 {
+                    acc = acc + w.abs() * l1;
+                }
+                if l2 != 0f32 {
+                    acc = acc + w.square() * (l2 / 2f32);
+                }
+                acc
+            })
+    }
+
+    /// Shrinks the parameters directly, without going through their gradients.
+    ///
+    /// The L1 part is a soft threshold, so weights it pulls past zero end up exactly at zero.
+    pub fn decay(&self, lr: f32) {
+        self.decay_with(|_| lr);
+    }
+
+    /// `decay` with each parameter's own learning rate, e.g. scaled by its `ParamGroup`.
+    pub fn decay_with<F: Fn(&RcScalar) -> f32>(&self, lr: F) {
+        let (l1, l2) = self.penalty.coefficients();
+        for w in self.parameters.iter() {
+            let lr = lr(w);
+            let mut borrowed = w.0.borrow_mut();
+            if !borrowed.requires_grad {
+                continue;
+            }
+            borrowed.data -= lr * l2 * borrowed.data;
+            borrowed.data = borrowed.data.signum() * (borrowed.data.abs() - lr * l1).max(0f32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::Layer;
+    use crate::sequential::Sequential;
+
+    fn data(parameters: &[RcScalar]) -> Vec<f32> {
+        parameters.iter().map(|p| p.0.borrow().data).collect()
+    }
+
+    #[test]
+    fn test_biases_excluded() {
+        let model = Sequential::new().push(Layer::new(3, 2)).batch_norm(2);
+
+        // 2 * 3 dense weights + 2 gammas
+        assert_eq!(weights(&model).len(), 8);
+        assert_eq!(
+            Regularizer::new(Penalty::L2(0.1), &model)
+                .parameters()
+                .len(),
+            8
+        );
+        assert_eq!(
+            Regularizer::with_biases(Penalty::L2(0.1), &model)
+                .parameters()
+                .len(),
+            12
+        );
+    }
+
+    #[test]
+    fn test_loss() {
+        let parameters = vec![
+            RcScalar::new(Scalar::new(2f32)),
+            RcScalar::new(Scalar::new(-1f32)),
+        ];
+        let regularizer = Regularizer::from_parameters(
+            Penalty::ElasticNet { l1: 0.5, l2: 0.2 },
+            parameters.clone(),
+        );
+
+        let loss = regularizer.loss();
+        loss.backwards();
+
+        // 0.5 * 3 + 0.1 * 5
+        assert!((loss.0.borrow().data - 2.0).abs() < 1e-6);
+        assert!((parameters[0].0.borrow().grad - 0.9).abs() < 1e-6);
+        assert!((parameters[1].0.borrow().grad - -0.7).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_decay() {
+        let parameters = vec![
+            RcScalar::new(Scalar::new(2f32)),
+            RcScalar::new(Scalar::new(-0.05f32)),
+        ];
+
+        Regularizer::from_parameters(Penalty::L2(0.5), parameters.clone()).decay(0.1);
+        assert_eq!(data(&parameters), vec![1.9, -0.0475]);
+
+        Regularizer::from_parameters(Penalty::L1(1.0), parameters.clone()).decay(0.1);
+        let decayed = data(&parameters);
+        assert!((decayed[0] - 1.8).abs() < 1e-6);
+        assert_eq!(decayed[1], 0.0);
+    }
+}
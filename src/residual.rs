@@ -0,0 +1,184 @@
+use crate::activation::Activation;
+use crate::layer::Layer;
+use crate::module::Module;
+use crate::scalar::RcScalar;
+use crate::sequential::Sequential;
+
+/// Skip connection computing `x + f(x)`, where `f` is the inner stack of modules.
+///
+/// When `f` changes the width, the shortcut goes through a linear projection instead of
+/// being the identity. Since `x` feeds both paths, `backwards()` accumulates both gradients.
+pub struct Residual {
+    inner: Sequential,
+    projection: Option<Layer>,
+}
+
+impl Residual {
+    pub fn new(inner: Sequential) -> Self {
+        Residual {
+            inner,
+            projection: None,
+        }
+    }
+
+    pub fn with_projection(inner: Sequential, nin: usize, nout: usize) -> Self {
+        Residual {
+            inner,
+            projection: Some(Layer::with_activation(nin, nout, Activation::Linear)),
+        }
+    }
+
+    fn shortcut(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        match &self.projection {
+            Some(projection) => projection.feed_foward(input),
+            None => input,
+        }
+    }
+}
+
+fn add(xs: Vec<RcScalar>, ys: Vec<RcScalar>) -> Vec<RcScalar> {
+    assert_eq!(
+        xs.len(),
+        ys.len(),
+        "residual branch width differs from the shortcut, use Residual::with_projection"
+    );
+    xs.into_iter().zip(ys).map(|(x, y)| x + y).collect()
+}
+
+impl Module for Residual {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let branch = self.inner.feed_foward(input.clone());
+        add(self.shortcut(input), branch)
+    }
+
+    fn feed_foward_batch(&self, inputs: Vec<Vec<RcScalar>>) -> Vec<Vec<RcScalar>> {
+        let branches = self.inner.feed_foward_batch(inputs.clone());
+        inputs
+            .into_iter()
+            .zip(branches)
+            .map(|(input, branch)| add(self.shortcut(input), branch))
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        let mut parameters = self.inner.parameters();
+        if let Some(projection) = &self.projection {
+            parameters.extend(projection.parameters());
+        }
+        parameters
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        let mut biases = self.inner.biases();
+        if let Some(projection) = &self.projection {
+            biases.extend(projection.biases());
+        }
+        biases
+    }
+
+    fn name(&self) -> String {
+        match &self.projection {
+            Some(projection) => format!("Residual({}, {})", self.inner.name(), projection.name()),
+            None => format!("Residual({})", self.inner.name()),
+        }
+    }
+
+    fn input_size(&self) -> Option<usize> {
+        match &self.projection {
+            Some(projection) => projection.input_size(),
+            None => self.inner.input_size(),
+        }
+    }
+
+    fn buffers(&self) -> Vec<f32> {
+        self.inner.buffers()
+    }
+
+    fn load_buffers(&self, buffers: &[f32]) {
+        self.inner.load_buffers(buffers);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.inner.set_training(training);
+    }
+
+    fn seed(&self, seed: u64) {
+        self.inner.seed(seed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar::Scalar;
+
+    #[test]
+    fn test_feed_forward() {
+        let block = Residual::new(Sequential::new().dense(3, 3).tanh());
+        let x: Vec<RcScalar> = vec![
+            RcScalar::new(Scalar::new(-3f32)),
+            RcScalar::new(Scalar::new(2f32)),
+            RcScalar::new(Scalar::new(0f32)),
+        ];
+
+        let branch: Vec<RcScalar> = block.inner.feed_foward(x.clone());
+        let output: Vec<RcScalar> = block.feed_foward(x.clone());
+
+        for ((y, b), x) in output.iter().zip(branch.iter()).zip(x.iter()) {
+            assert!((y.0.borrow().data - (b.0.borrow().data + x.0.borrow().data)).abs() < 1e-6);
+        }
+        assert_eq!(block.parameters().len(), 12);
+    }
+
+    #[test]
+    fn test_backward_through_both_paths() {
+        let block = Residual::new(Sequential::new().dense(1, 1));
+        let w = block.parameters()[0].0.borrow().data;
+        let x = RcScalar::new(Scalar::new(0.5));
+
+        let output = block.feed_foward(vec![RcScalar::clone(&x)]);
+        output[0].backwards();
+
+        // d/dx (x + w*x + b) = 1 + w
+        assert!((x.0.borrow().grad - (1f32 + w)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_projection() {
+        let block = Residual::with_projection(Sequential::new().dense(3, 2).relu(), 3, 2);
+        let x: Vec<RcScalar> = (0..3)
+            .map(|i| RcScalar::new(Scalar::new(i as f32)))
+            .collect();
+
+        let output: Vec<RcScalar> = block.feed_foward(x);
+
+        assert_eq!(output.len(), 2);
+        // (3+1)*2 inner + (3+1)*2 projection
+        assert_eq!(block.parameters().len(), 16);
+    }
+
+    #[test]
+    fn test_nested_in_sequential() {
+        let net = Sequential::new()
+            .dense(2, 4)
+            .push(Residual::new(
+                Sequential::new().dense(4, 4).batch_norm(4).relu(),
+            ))
+            .residual(Sequential::new().dense(4, 4).tanh())
+            .dense(4, 1);
+        let xs: Vec<Vec<RcScalar>> = (0..3)
+            .map(|i| {
+                vec![
+                    RcScalar::new(Scalar::new(i as f32)),
+                    RcScalar::new(Scalar::new(1f32)),
+                ]
+            })
+            .collect();
+
+        let outputs = net.feed_foward_batch(xs);
+
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].len(), 1);
+        assert_eq!(net.buffers().len(), 8);
+    }
+}
@@ -1,18 +1,20 @@
+use crate::anomaly::{self, Phase};
+use crate::autograd::check_not_freed;
+use crate::hooks::{GradHook, HookHandle, Hooks};
+use log::debug;
 use std::cell::RefCell;
 use std::collections::HashSet;
 use std::fmt;
+use std::io;
 use std::ops;
 use std::rc::Rc;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::vec::Vec;
-use log::debug;
 
-
-static GLOBAL_COUTER: AtomicU8 = AtomicU8::new(0);
+static GLOBAL_COUTER: AtomicUsize = AtomicUsize::new(0);
 
 fn get_id() -> usize {
-    GLOBAL_COUTER.fetch_add(1, Ordering::SeqCst);
-    GLOBAL_COUTER.load(Ordering::SeqCst) as usize
+    GLOBAL_COUTER.fetch_add(1, Ordering::SeqCst) + 1
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -21,6 +23,16 @@ pub enum Ops {
     Mul,
     Tanh,
     Pow2,
+    Pow(f32),
+    Exp,
+    Log,
+    Relu,
+    Abs,
+    /// `prev[0]` unchanged. Any further inputs only order the backward pass: no gradient flows
+    /// to them.
+    Identity,
+    /// Depends on its inputs without passing them any gradient.
+    Barrier,
     Null,
 }
 
@@ -31,6 +43,13 @@ pub struct Scalar {
     pub grad: f32,
     pub prev: Vec<RcScalar>,
     pub ops: Ops,
+    /// Whether `backward` accumulates into `grad`. Clear it on a leaf to freeze it,
+    /// results of operations require a gradient if any of their inputs do.
+    pub requires_grad: bool,
+    /// The module the scalar was created in, only recorded in anomaly mode.
+    pub site: Option<Rc<str>>,
+    /// Gradient hooks, see `RcScalar::register_hook`.
+    pub hooks: Option<Hooks<GradHook>>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,55 +68,168 @@ impl RcScalar {
         RcScalar(Rc::new(RefCell::new(scalar)))
     }
 
-    pub fn clone(&self) -> Self {
-        RcScalar(Rc::clone(&self.0))
+    /// One new leaf per value, e.g. to feed a sample of plain numbers to a model.
+    pub fn vec_from(values: &[f32]) -> Vec<RcScalar> {
+        values
+            .iter()
+            .map(|&x| RcScalar::new(Scalar::new(x)))
+            .collect()
+    }
+
+    pub(crate) fn from_op(data: f32, prev: Vec<RcScalar>, ops: Ops) -> Self {
+        let requires_grad = prev.iter().any(|p| p.0.borrow().requires_grad);
+        let scalar = Scalar {
+            uid: get_id(),
+            data,
+            grad: 0f32,
+            prev,
+            ops,
+            requires_grad,
+            site: anomaly::site(),
+            hooks: None,
+        };
+        if !data.is_finite() && anomaly::is_enabled() {
+            anomaly::raise(Phase::Forward, &scalar);
+        }
+        RcScalar(Rc::new(RefCell::new(scalar)))
+    }
+
+    pub fn requires_grad(&self) -> bool {
+        self.0.borrow().requires_grad
+    }
+
+    pub fn set_requires_grad(&self, requires_grad: bool) {
+        self.0.borrow_mut().requires_grad = requires_grad;
+    }
+
+    /// Calls `hook` in every backward pass with what the pass adds to this scalar's gradient,
+    /// once that is complete & before it flows on to the scalar's inputs. Returning `Some`
+    /// replaces it. Frozen scalars do not call their hooks.
+    pub fn register_hook<F: FnMut(f32) -> Option<f32> + 'static>(&self, hook: F) -> HookHandle {
+        self.0
+            .borrow_mut()
+            .hooks
+            .get_or_insert_with(Hooks::new)
+            .add(Box::new(hook))
+    }
+
+    // `before` is the gradient the scalar had before this backward pass
+    fn run_hooks(&self, before: f32) {
+        let hooks = match &self.0.borrow().hooks {
+            Some(hooks) if self.0.borrow().requires_grad => hooks.snapshot(),
+            _ => return,
+        };
+        let mut grad = self.0.borrow().grad - before;
+        for hook in hooks {
+            if let Some(replaced) = (*hook.borrow_mut())(grad) {
+                grad = replaced;
+            }
+        }
+        self.0.borrow_mut().grad = before + grad;
     }
 
     pub fn square(&self) -> Self {
         debug!("Scalar#debug() on ({})", self);
-        RcScalar(Rc::new(RefCell::new(Scalar {
-            uid: get_id(),
-            data: self.0.borrow().data.powf(2f32),
-            grad: 0.0,
-            prev: vec![RcScalar::clone(self)],
-            ops: Ops::Pow2,
-        })))
+        RcScalar::from_op(
+            self.0.borrow().data.powf(2f32),
+            vec![RcScalar::clone(self)],
+            Ops::Pow2,
+        )
     }
 
     pub fn tanh(&self) -> Self {
         debug!("Scalar#Tanh() on ({})", self);
-        RcScalar(Rc::new(RefCell::new(Scalar {
-            uid: get_id(),
-            data: self.0.borrow().data.tanh(),
-            grad: 0.0,
-            prev: vec![RcScalar::clone(self)],
-            ops: Ops::Tanh,
-        })))
+        RcScalar::from_op(
+            self.0.borrow().data.tanh(),
+            vec![RcScalar::clone(self)],
+            Ops::Tanh,
+        )
+    }
+
+    pub fn exp(&self) -> Self {
+        debug!("Scalar#exp() on ({})", self);
+        RcScalar::from_op(
+            self.0.borrow().data.exp(),
+            vec![RcScalar::clone(self)],
+            Ops::Exp,
+        )
     }
 
+    pub fn ln(&self) -> Self {
+        debug!("Scalar#ln() on ({})", self);
+        RcScalar::from_op(
+            self.0.borrow().data.ln(),
+            vec![RcScalar::clone(self)],
+            Ops::Log,
+        )
+    }
+
+    pub fn relu(&self) -> Self {
+        debug!("Scalar#relu() on ({})", self);
+        RcScalar::from_op(
+            self.0.borrow().data.max(0f32),
+            vec![RcScalar::clone(self)],
+            Ops::Relu,
+        )
+    }
+
+    pub fn abs(&self) -> Self {
+        debug!("Scalar#abs() on ({})", self);
+        RcScalar::from_op(
+            self.0.borrow().data.abs(),
+            vec![RcScalar::clone(self)],
+            Ops::Abs,
+        )
+    }
+
+    pub fn sigmoid(&self) -> Self {
+        debug!("Scalar#sigmoid() on ({})", self);
+        ((-RcScalar::clone(self)).exp() + 1f32).powf(-1f32)
+    }
+
+    pub fn powf(&self, exponent: f32) -> Self {
+        debug!("Scalar#powf() on ({}, {})", self, exponent);
+        RcScalar::from_op(
+            self.0.borrow().data.powf(exponent),
+            vec![RcScalar::clone(self)],
+            Ops::Pow(exponent),
+        )
+    }
+
+    /// New leaf with the same value: gradients stop flowing here.
+    pub fn detach(&self) -> Self {
+        RcScalar::new(Scalar::new(self.0.borrow().data))
+    }
+
+    /// Panics if the graph was freed by `autograd::gradient`, see `try_backwards`.
     pub fn backwards(&self) {
-        debug!("Scalar#backward() on {}", self);
-        // Sort in topological order
-        let mut ordered_list: Vec<RcScalar> = Vec::new();
-        let mut visited: HashSet<RcScalar> = HashSet::new();
-        let mut to_visit: Vec<RcScalar> = vec![self.clone()];
-
-        while let Some(c_scalar) = to_visit.pop() {
-            if !visited.contains(&c_scalar) {
-                ordered_list.push(c_scalar.clone());
-                visited.insert(c_scalar.clone());
-                // Assuming bfs is a function defined elsewhere in your code
-                for child in c_scalar.0.borrow().prev.iter() {
-                    to_visit.push(child.clone());
-                }
-            }
+        if let Err(e) = self.try_backwards() {
+            panic!("{}", e);
         }
+    }
 
+    /// Like `backwards()`, but fails without touching any gradient if the graph was freed by
+    /// `autograd::gradient`.
+    pub fn try_backwards(&self) -> io::Result<()> {
+        debug!("Scalar#backward() on {}", self);
+        let ordered_list = topological_order(std::slice::from_ref(self));
+        check_not_freed(&ordered_list)?;
+
+        // Hooks see what this pass adds to a gradient, the output's is set rather than added to
+        let before: Vec<f32> = ordered_list
+            .iter()
+            .map(|s| match &s.0.borrow().hooks {
+                Some(_) if !Rc::ptr_eq(&s.0, &self.0) => s.0.borrow().grad,
+                _ => 0f32,
+            })
+            .collect();
         self.0.borrow_mut().grad = 1.0;
-        // Iterate over list & for eaach, run backward()
-        for rc_scalar in ordered_list {
+        // Iterate from the output back to the leaves & for each, run backward()
+        for (rc_scalar, before) in ordered_list.iter().zip(before).rev() {
+            rc_scalar.run_hooks(before);
             rc_scalar.0.borrow_mut().backward();
         }
+        Ok(())
     }
 }
 
@@ -109,41 +241,128 @@ impl Scalar {
             grad: 0f32,
             prev: Vec::new(),
             ops: Ops::Null,
+            requires_grad: true,
+            site: anomaly::site(),
+            hooks: None,
         };
         debug!("Scalar#init() on ({})", new_scalar);
         new_scalar
     }
 
     pub fn backward(&mut self) {
+        if !self.requires_grad {
+            return;
+        }
+        assert!(
+            !self.prev.is_empty() || self.ops == Ops::Null,
+            "backward through node {}, whose graph was freed by autograd::gradient",
+            self.uid
+        );
+        // Computed before writing, as `x * x` points at the same scalar twice
+        let grads = self.input_grads(self.grad);
+        for (scalar, grad) in self.prev.iter().zip(grads) {
+            accumulate(scalar, grad);
+        }
+        if anomaly::is_enabled() && self.prev.iter().any(|p| !p.0.borrow().grad.is_finite()) {
+            anomaly::raise(Phase::Backward, self);
+        }
+    }
+
+    /// What a gradient of `grad` on this scalar contributes to the gradient of each input.
+    pub(crate) fn input_grads(&self, grad: f32) -> Vec<f32> {
         match self.ops {
-            Ops::Add => {
-                for scalar in self.prev.iter() {
-                    scalar.0.borrow_mut().grad += self.grad
-                }
-            }
+            Ops::Add => vec![grad; self.prev.len()],
             Ops::Mul => {
                 assert_eq!(self.prev.len(), 2);
-                let mut scalar_1 = self.prev[0].0.borrow_mut();
-                let mut scalar_2 = self.prev[1].0.borrow_mut();
-                scalar_1.grad += self.grad * scalar_2.data;
-                scalar_2.grad += self.grad * scalar_1.data;
-                // println!("{}",scalar_1.grad);ca
+                let data_1 = self.prev[0].0.borrow().data;
+                let data_2 = self.prev[1].0.borrow().data;
+                vec![grad * data_2, grad * data_1]
             }
             Ops::Pow2 => {
                 assert_eq!(self.prev.len(), 1);
-                let mut scalar_1 = self.prev[0].0.borrow_mut();
-                scalar_1.grad += 2f32 * self.grad * scalar_1.data;
+                let data_1 = self.prev[0].0.borrow().data;
+                vec![2f32 * grad * data_1]
+            }
+            Ops::Pow(exponent) => {
+                assert_eq!(self.prev.len(), 1);
+                let data_1 = self.prev[0].0.borrow().data;
+                vec![grad * exponent * data_1.powf(exponent - 1f32)]
             }
             Ops::Tanh => {
                 assert_eq!(self.prev.len(), 1);
-                let mut scalar_1 = self.prev[0].0.borrow_mut();
-                scalar_1.grad += self.grad * (1f32 - scalar_1.data.tanh().powf(2f32));
+                vec![grad * (1f32 - self.data.powf(2f32))]
+            }
+            Ops::Exp => {
+                assert_eq!(self.prev.len(), 1);
+                vec![grad * self.data]
+            }
+            Ops::Log => {
+                assert_eq!(self.prev.len(), 1);
+                let data_1 = self.prev[0].0.borrow().data;
+                vec![grad / data_1]
+            }
+            Ops::Relu => {
+                assert_eq!(self.prev.len(), 1);
+                if self.data > 0f32 {
+                    vec![grad]
+                } else {
+                    vec![0f32]
+                }
+            }
+            Ops::Abs => {
+                assert_eq!(self.prev.len(), 1);
+                // Subgradient 0 at 0, so an L1 penalty leaves exact zeros alone
+                let data_1 = self.prev[0].0.borrow().data;
+                if data_1 != 0f32 {
+                    vec![grad * data_1.signum()]
+                } else {
+                    vec![0f32]
+                }
+            }
+            Ops::Identity => {
+                let mut grads = vec![0f32; self.prev.len()];
+                grads[0] = grad;
+                grads
             }
-            _ => (),
+            Ops::Barrier => vec![0f32; self.prev.len()],
+            Ops::Null => Vec::new(),
         }
     }
 }
 
+/// Every scalar `roots` depend on, including themselves, each after all of its inputs.
+pub(crate) fn topological_order(roots: &[RcScalar]) -> Vec<RcScalar> {
+    // Post-order DFS
+    let mut ordered_list: Vec<RcScalar> = Vec::new();
+    let mut visited: HashSet<usize> = HashSet::new();
+    let mut to_visit: Vec<(RcScalar, bool)> = roots.iter().map(|r| (r.clone(), false)).collect();
+
+    while let Some((c_scalar, expanded)) = to_visit.pop() {
+        if expanded {
+            ordered_list.push(c_scalar);
+            continue;
+        }
+        if !visited.insert(c_scalar.0.borrow().uid) {
+            continue;
+        }
+        to_visit.push((c_scalar.clone(), true));
+        for child in c_scalar.0.borrow().prev.iter() {
+            if !visited.contains(&child.0.borrow().uid) {
+                to_visit.push((child.clone(), false));
+            }
+        }
+    }
+    ordered_list
+}
+
+/// Adds to the gradient of `scalar`, unless it is frozen.
+fn accumulate(scalar: &RcScalar, grad: f32) {
+    let mut borrowed = scalar.0.borrow_mut();
+    if borrowed.requires_grad {
+        borrowed.grad += grad;
+    }
+}
+
 impl fmt::Display for Scalar {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Scalar(uid={},data={})", self.uid, self.data)
@@ -167,13 +386,11 @@ impl ops::Add for RcScalar {
 
     fn add(self, other: Self) -> Self::Output {
         debug!("Scalar#Add() on ({}, {})", self, other);
-        RcScalar(Rc::new(RefCell::new(Scalar {
-            uid: get_id(),
-            data: self.0.borrow().data + other.0.borrow().data,
-            grad: 0f32,
-            prev: vec![RcScalar::clone(&self), RcScalar::clone(&other)],
-            ops: Ops::Add,
-        })))
+        RcScalar::from_op(
+            self.0.borrow().data + other.0.borrow().data,
+            vec![RcScalar::clone(&self), RcScalar::clone(&other)],
+            Ops::Add,
+        )
     }
 }
 
@@ -182,13 +399,11 @@ impl ops::Mul for RcScalar {
 
     fn mul(self, other: Self) -> Self::Output {
         debug!("Scalar#Mul() on ({}, {})", self, other);
-        RcScalar(Rc::new(RefCell::new(Scalar {
-            uid: get_id(),
-            data: self.0.borrow().data * other.0.borrow().data,
-            grad: 0f32,
-            prev: vec![RcScalar::clone(&self), RcScalar::clone(&other)],
-            ops: Ops::Mul,
-        })))
+        RcScalar::from_op(
+            self.0.borrow().data * other.0.borrow().data,
+            vec![RcScalar::clone(&self), RcScalar::clone(&other)],
+            Ops::Mul,
+        )
     }
 }
 
@@ -198,13 +413,20 @@ impl ops::Mul<f32> for RcScalar {
     fn mul(self, other: f32) -> Self::Output {
         debug!("Scalar#Mul() on ({}, {})", self, other);
         let other_rcscalar = RcScalar::new(Scalar::new(other));
-        RcScalar(Rc::new(RefCell::new(Scalar {
-            uid: get_id(),
-            data: self.0.borrow().data * other,
-            grad: 0f32,
-            prev: vec![RcScalar::clone(&self), RcScalar::clone(&other_rcscalar)],
-            ops: Ops::Mul,
-        })))
+        RcScalar::from_op(
+            self.0.borrow().data * other,
+            vec![RcScalar::clone(&self), RcScalar::clone(&other_rcscalar)],
+            Ops::Mul,
+        )
+    }
+}
+
+impl ops::Add<f32> for RcScalar {
+    type Output = Self;
+
+    fn add(self, other: f32) -> Self::Output {
+        debug!("Scalar#Add() on ({}, {})", self, other);
+        self + RcScalar::new(Scalar::new(other))
     }
 }
 
@@ -231,62 +453,62 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_Add() {
+    fn test_add() {
         let scalar_a: RcScalar = RcScalar::new(Scalar::new(0.001));
         let scalar_b: RcScalar = RcScalar::new(Scalar::new(0.002));
 
-        let a_Add_b: RcScalar = RcScalar::clone(&scalar_a) + RcScalar::clone(&scalar_b);
+        let a_add_b: RcScalar = RcScalar::clone(&scalar_a) + RcScalar::clone(&scalar_b);
 
-        assert_eq!(a_Add_b.0.borrow().data, 0.003);
-        assert_eq!(a_Add_b.0.borrow().grad, 0.0);
-        assert_eq!(a_Add_b.0.borrow().ops, Ops::Add);
-        assert_eq!(a_Add_b.0.borrow().prev.len(), 2);
+        assert_eq!(a_add_b.0.borrow().data, 0.003);
+        assert_eq!(a_add_b.0.borrow().grad, 0.0);
+        assert_eq!(a_add_b.0.borrow().ops, Ops::Add);
+        assert_eq!(a_add_b.0.borrow().prev.len(), 2);
 
-        assert_eq!(a_Add_b.0.borrow().prev[0].0.borrow().data, 0.001);
-        assert_eq!(a_Add_b.0.borrow().prev[0].0.borrow().grad, 0.0);
-        assert_eq!(a_Add_b.0.borrow().prev[0].0.borrow().ops, Ops::Null);
-        assert_eq!(a_Add_b.0.borrow().prev[0].0.borrow().prev.len(), 0);
+        assert_eq!(a_add_b.0.borrow().prev[0].0.borrow().data, 0.001);
+        assert_eq!(a_add_b.0.borrow().prev[0].0.borrow().grad, 0.0);
+        assert_eq!(a_add_b.0.borrow().prev[0].0.borrow().ops, Ops::Null);
+        assert_eq!(a_add_b.0.borrow().prev[0].0.borrow().prev.len(), 0);
 
-        assert_eq!(a_Add_b.0.borrow().prev[1].0.borrow().data, 0.002);
-        assert_eq!(a_Add_b.0.borrow().prev[1].0.borrow().grad, 0.0);
-        assert_eq!(a_Add_b.0.borrow().prev[1].0.borrow().ops, Ops::Null);
-        assert_eq!(a_Add_b.0.borrow().prev[1].0.borrow().prev.len(), 0);
+        assert_eq!(a_add_b.0.borrow().prev[1].0.borrow().data, 0.002);
+        assert_eq!(a_add_b.0.borrow().prev[1].0.borrow().grad, 0.0);
+        assert_eq!(a_add_b.0.borrow().prev[1].0.borrow().ops, Ops::Null);
+        assert_eq!(a_add_b.0.borrow().prev[1].0.borrow().prev.len(), 0);
 
         scalar_a.0.borrow_mut().backward();
         assert_eq!(scalar_a.0.borrow().grad, 0.0);
 
-        a_Add_b.backwards();
-        assert_eq!(a_Add_b.0.borrow().grad, 1.0);
+        a_add_b.backwards();
+        assert_eq!(a_add_b.0.borrow().grad, 1.0);
         assert_eq!(scalar_a.0.borrow().grad, 1.0);
         assert_eq!(scalar_b.0.borrow().grad, 1.0);
     }
 
     #[test]
-    fn test_Mul() {
+    fn test_mul() {
         let scalar_a: RcScalar = RcScalar::new(Scalar::new(0.001));
         let scalar_b: RcScalar = RcScalar::new(Scalar::new(0.002));
-        let a_Mul_b: RcScalar = RcScalar::clone(&scalar_a) * RcScalar::clone(&scalar_b);
+        let a_mul_b: RcScalar = RcScalar::clone(&scalar_a) * RcScalar::clone(&scalar_b);
 
-        assert_eq!(a_Mul_b.0.borrow().data, 0.0000020000002);
-        assert_eq!(a_Mul_b.0.borrow().grad, 0.0);
-        assert_eq!(a_Mul_b.0.borrow().ops, Ops::Mul);
-        assert_eq!(a_Mul_b.0.borrow().prev.len(), 2);
+        assert_eq!(a_mul_b.0.borrow().data, 0.0000020000002);
+        assert_eq!(a_mul_b.0.borrow().grad, 0.0);
+        assert_eq!(a_mul_b.0.borrow().ops, Ops::Mul);
+        assert_eq!(a_mul_b.0.borrow().prev.len(), 2);
 
-        assert_eq!(a_Mul_b.0.borrow().prev[0].0.borrow().data, 0.001);
-        assert_eq!(a_Mul_b.0.borrow().prev[0].0.borrow().grad, 0.0);
-        assert_eq!(a_Mul_b.0.borrow().prev[0].0.borrow().ops, Ops::Null);
-        assert_eq!(a_Mul_b.0.borrow().prev[0].0.borrow().prev.len(), 0);
+        assert_eq!(a_mul_b.0.borrow().prev[0].0.borrow().data, 0.001);
+        assert_eq!(a_mul_b.0.borrow().prev[0].0.borrow().grad, 0.0);
+        assert_eq!(a_mul_b.0.borrow().prev[0].0.borrow().ops, Ops::Null);
+        assert_eq!(a_mul_b.0.borrow().prev[0].0.borrow().prev.len(), 0);
 
-        assert_eq!(a_Mul_b.0.borrow().prev[1].0.borrow().data, 0.002);
-        assert_eq!(a_Mul_b.0.borrow().prev[1].0.borrow().grad, 0.0);
-        assert_eq!(a_Mul_b.0.borrow().prev[1].0.borrow().ops, Ops::Null);
-        assert_eq!(a_Mul_b.0.borrow().prev[1].0.borrow().prev.len(), 0);
+        assert_eq!(a_mul_b.0.borrow().prev[1].0.borrow().data, 0.002);
+        assert_eq!(a_mul_b.0.borrow().prev[1].0.borrow().grad, 0.0);
+        assert_eq!(a_mul_b.0.borrow().prev[1].0.borrow().ops, Ops::Null);
+        assert_eq!(a_mul_b.0.borrow().prev[1].0.borrow().prev.len(), 0);
 
         scalar_a.0.borrow_mut().backward();
         assert_eq!(scalar_a.0.borrow().grad, 0.0);
 
-        a_Mul_b.backwards();
-        assert_eq!(a_Mul_b.0.borrow().grad, 1.0);
+        a_mul_b.backwards();
+        assert_eq!(a_mul_b.0.borrow().grad, 1.0);
         assert_eq!(scalar_a.0.borrow().grad, 0.002);
         assert_eq!(scalar_b.0.borrow().grad, 0.001);
     }
@@ -387,6 +609,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_activations() {
+        let scalar_a: RcScalar = RcScalar::new(Scalar::new(0.5));
+        let scalar_b: RcScalar = RcScalar::new(Scalar::new(-0.5));
+
+        let relu_a = scalar_a.relu();
+        let relu_b = scalar_b.relu();
+        assert_eq!(relu_a.0.borrow().data, 0.5);
+        assert_eq!(relu_b.0.borrow().data, 0.0);
+        relu_a.backwards();
+        relu_b.backwards();
+        assert_eq!(scalar_a.0.borrow().grad, 1.0);
+        assert_eq!(scalar_b.0.borrow().grad, 0.0);
+
+        let scalar_c: RcScalar = RcScalar::new(Scalar::new(0.5));
+        let sigmoid_c = scalar_c.sigmoid();
+        let expected = 1.0 / (1.0 + (-0.5f32).exp());
+        assert!((sigmoid_c.0.borrow().data - expected).abs() < 1e-6);
+        sigmoid_c.backwards();
+        assert!((scalar_c.0.borrow().grad - expected * (1.0 - expected)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_requires_grad() {
+        let frozen: RcScalar = RcScalar::new(Scalar::new(2.0));
+        let x: RcScalar = RcScalar::new(Scalar::new(3.0));
+        frozen.set_requires_grad(false);
+
+        let scaled = RcScalar::clone(&frozen) * RcScalar::clone(&frozen);
+        assert!(!scaled.requires_grad());
+        let y = scaled * RcScalar::clone(&x);
+        assert!(y.requires_grad());
+        y.backwards();
+
+        assert_eq!(frozen.0.borrow().grad, 0.0);
+        assert_eq!(x.0.borrow().grad, 4.0);
+    }
+
+    #[test]
+    fn test_abs() {
+        let scalar_a: RcScalar = RcScalar::new(Scalar::new(-1.5));
+        let scalar_b: RcScalar = RcScalar::new(Scalar::new(0.0));
+
+        let abs = scalar_a.abs() + scalar_b.abs();
+        assert_eq!(abs.0.borrow().data, 1.5);
+        abs.backwards();
+        assert_eq!(scalar_a.0.borrow().grad, -1.0);
+        assert_eq!(scalar_b.0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_exp_ln() {
+        let scalar_a: RcScalar = RcScalar::new(Scalar::new(2.0));
+        let exp_a = scalar_a.exp();
+        let ln_exp_a = exp_a.ln();
+
+        assert!((ln_exp_a.0.borrow().data - 2.0).abs() < 1e-6);
+        ln_exp_a.backwards();
+        assert!((exp_a.0.borrow().grad - (-2f32).exp()).abs() < 1e-6);
+        assert!((scalar_a.0.borrow().grad - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_square_of_itself() {
+        let scalar_a: RcScalar = RcScalar::new(Scalar::new(3.0));
+        let a_mul_a = RcScalar::clone(&scalar_a) * RcScalar::clone(&scalar_a);
+
+        a_mul_a.backwards();
+        assert_eq!(a_mul_a.0.borrow().data, 9.0);
+        assert_eq!(scalar_a.0.borrow().grad, 6.0);
+    }
+
     #[test]
     fn integration() {
         let a: RcScalar = RcScalar::new(Scalar::new(-3f32));
@@ -398,15 +692,15 @@ mod tests {
         let cd: RcScalar = RcScalar::clone(&c) * RcScalar::clone(&d);
         let ab_cd: RcScalar = RcScalar::clone(&ab) + RcScalar::clone(&cd);
         let ab_cd_e: RcScalar = RcScalar::clone(&ab_cd) + RcScalar::clone(&e);
-        let ab_cd_e_Tanh = RcScalar::clone(&ab_cd_e).Tanh();
+        let ab_cd_e_tanh = RcScalar::clone(&ab_cd_e).tanh();
 
-        assert_eq!(ab_cd_e_Tanh.0.borrow().data, 0.70691997);
+        assert!((ab_cd_e_tanh.0.borrow().data - 0.70691997).abs() < 1e-6);
 
-        ab_cd_e_Tanh.backwards();
-        assert_eq!(a.0.borrow().grad, 1.0005283);
-        assert_eq!(b.0.borrow().grad, -1.5007925);
-        assert_eq!(c.0.borrow().grad, 0.50026417);
+        ab_cd_e_tanh.backwards();
+        assert!((a.0.borrow().grad - 1.0005283).abs() < 1e-6);
+        assert!((b.0.borrow().grad - -1.5007925).abs() < 1e-6);
+        assert!((c.0.borrow().grad - 0.50026417).abs() < 1e-6);
         assert_eq!(d.0.borrow().grad, 0.0);
-        assert_eq!(e.0.borrow().grad, 0.50026417);
+        assert!((e.0.borrow().grad - 0.50026417).abs() < 1e-6);
     }
 }
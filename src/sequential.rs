@@ -0,0 +1,362 @@
+use crate::activation::Activation;
+use crate::anomaly;
+use crate::dropout::Dropout;
+use crate::flatten::Flatten;
+use crate::hooks::{HookHandle, ModuleHooks};
+use crate::layer::Layer;
+use crate::module::Module;
+use crate::normalization::{BatchNorm1d, LayerNorm};
+use crate::residual::Residual;
+use crate::scalar::RcScalar;
+use crate::softmax::{LogSoftmax, Softmax};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Container applying named modules one after the other.
+///
+/// ```
+/// # use neural_network_from_scratch::sequential::Sequential;
+/// let net = Sequential::new().dense(3, 16).relu().dropout(0.1).dense(16, 1);
+/// ```
+///
+/// Modules added without an explicit name are named after their position (`"0"`, `"1"`, ...),
+/// or the next free index if a module was already given that name.
+#[derive(Default)]
+pub struct Sequential {
+    modules: Vec<(String, Box<dyn Module>)>,
+    // Parallel to `modules`
+    hooks: Vec<ModuleHooks>,
+}
+
+impl Sequential {
+    pub fn new() -> Self {
+        Sequential {
+            modules: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn push<M: Module + 'static>(self, module: M) -> Self {
+        let name = (self.modules.len()..)
+            .map(|i| i.to_string())
+            .find(|name| self.get(name).is_none())
+            .unwrap();
+        self.push_named(&name, module)
+    }
+
+    pub fn push_named<M: Module + 'static>(mut self, name: &str, module: M) -> Self {
+        assert!(
+            self.get(name).is_none(),
+            "a module named {} already exists",
+            name
+        );
+        self.modules.push((name.to_string(), Box::new(module)));
+        self.hooks.push(ModuleHooks::default());
+        self
+    }
+
+    /// Fully-connected layer without activation; chain `.relu()`, `.tanh()`, ... for one.
+    pub fn dense(self, nin: usize, nout: usize) -> Self {
+        self.push(Layer::with_activation(nin, nout, Activation::Linear))
+    }
+
+    pub fn tanh(self) -> Self {
+        self.push(Activation::Tanh)
+    }
+
+    pub fn relu(self) -> Self {
+        self.push(Activation::Relu)
+    }
+
+    pub fn sigmoid(self) -> Self {
+        self.push(Activation::Sigmoid)
+    }
+
+    pub fn softmax(self) -> Self {
+        self.push(Softmax)
+    }
+
+    pub fn log_softmax(self) -> Self {
+        self.push(LogSoftmax)
+    }
+
+    pub fn flatten(self) -> Self {
+        self.push(Flatten)
+    }
+
+    pub fn dropout(self, p: f32) -> Self {
+        self.push(Dropout::new(p))
+    }
+
+    pub fn batch_norm(self, num_features: usize) -> Self {
+        self.push(BatchNorm1d::new(num_features))
+    }
+
+    pub fn layer_norm(self, normalized_shape: usize) -> Self {
+        self.push(LayerNorm::new(normalized_shape))
+    }
+
+    /// Wraps `inner` in a skip connection, see `Residual`.
+    pub fn residual(self, inner: Sequential) -> Self {
+        self.push(Residual::new(inner))
+    }
+
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Module> {
+        self.modules
+            .iter()
+            .find(|(module_name, _)| module_name == name)
+            .map(|(_, module)| module.as_ref())
+    }
+
+    pub fn named_modules(&self) -> Vec<(&str, &dyn Module)> {
+        self.modules
+            .iter()
+            .map(|(name, module)| (name.as_str(), module.as_ref()))
+            .collect()
+    }
+
+    fn hooks(&self, name: &str) -> Option<&ModuleHooks> {
+        self.modules
+            .iter()
+            .position(|(module_name, _)| module_name == name)
+            .map(|i| &self.hooks[i])
+    }
+
+    /// Calls `hook` with the input & output of module `name` for every sample it processes,
+    /// e.g. to record activations. `None` if there is no such module.
+    pub fn register_forward_hook<F>(&self, name: &str, hook: F) -> Option<HookHandle>
+    where
+        F: FnMut(&[RcScalar], &[RcScalar]) + 'static,
+    {
+        self.hooks(name)
+            .map(|hooks| hooks.forward.add(Box::new(hook)))
+    }
+
+    /// Calls `hook` in backward passes with the gradient of module `name`'s output for a
+    /// sample, once it is complete & before it flows into the module. Returning `Some`
+    /// replaces it, e.g. to clip or add noise. `None` if there is no such module.
+    ///
+    /// Only outputs computed while the hook is registered go through it.
+    pub fn register_backward_hook<F>(&self, name: &str, hook: F) -> Option<HookHandle>
+    where
+        F: FnMut(&[f32]) -> Option<Vec<f32>> + 'static,
+    {
+        self.hooks(name)
+            .map(|hooks| hooks.backward.add(Box::new(hook)))
+    }
+
+    fn forward_module(&self, i: usize, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        let (name, module) = &self.modules[i];
+        let hooks = &self.hooks[i];
+        let _scope = anomaly::scope(|| format!("{} ({})", name, module.name()));
+        if hooks.forward.is_empty() && hooks.backward.is_empty() {
+            return module.feed_foward(input);
+        }
+        let output = module.feed_foward(input.clone());
+        hooks.apply(&input, output)
+    }
+}
+
+impl Module for Sequential {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        (0..self.modules.len()).fold(input, |x: Vec<RcScalar>, i| self.forward_module(i, x))
+    }
+
+    fn feed_foward_batch(&self, inputs: Vec<Vec<RcScalar>>) -> Vec<Vec<RcScalar>> {
+        (0..self.modules.len()).fold(inputs, |xs: Vec<Vec<RcScalar>>, i| {
+            let (name, module) = &self.modules[i];
+            let hooks = &self.hooks[i];
+            let _scope = anomaly::scope(|| format!("{} ({})", name, module.name()));
+            if hooks.forward.is_empty() && hooks.backward.is_empty() {
+                return module.feed_foward_batch(xs);
+            }
+            let outputs = module.feed_foward_batch(xs.clone());
+            xs.iter()
+                .zip(outputs)
+                .map(|(input, output)| hooks.apply(input, output))
+                .collect()
+        })
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        self.modules
+            .iter()
+            .flat_map(|(_, module)| module.parameters())
+            .collect()
+    }
+
+    fn biases(&self) -> Vec<RcScalar> {
+        self.modules
+            .iter()
+            .flat_map(|(_, module)| module.biases())
+            .collect()
+    }
+
+    fn name(&self) -> String {
+        format!("Sequential({})", self.modules.len())
+    }
+
+    /// That of the first module, looking past elementwise activations.
+    fn input_size(&self) -> Option<usize> {
+        for (_, module) in self.modules.iter() {
+            match module.input_size() {
+                None if module.activation().is_some() => continue,
+                size => return size,
+            }
+        }
+        None
+    }
+
+    fn buffers(&self) -> Vec<f32> {
+        self.modules
+            .iter()
+            .flat_map(|(_, module)| module.buffers())
+            .collect()
+    }
+
+    fn load_buffers(&self, buffers: &[f32]) {
+        let mut offset = 0;
+        for (_, module) in self.modules.iter() {
+            let len = module.buffers().len();
+            module.load_buffers(&buffers[offset..offset + len]);
+            offset += len;
+        }
+        assert_eq!(offset, buffers.len());
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for (_, module) in self.modules.iter_mut() {
+            module.set_training(training);
+        }
+    }
+
+    /// Each module gets its own seed, drawn from `seed`, so no two draw the same masks.
+    fn seed(&self, seed: u64) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for (_, module) in self.modules.iter() {
+            module.seed(rng.gen());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conv::Conv1d;
+    use crate::pool::MaxPool1d;
+    use crate::scalar::Scalar;
+
+    #[test]
+    fn test_parameters() {
+        let net = Sequential::new()
+            .dense(3, 16)
+            .relu()
+            .dropout(0.1)
+            .dense(16, 1);
+
+        // (3+1)*16 + (16+1)*1 = 64 + 17 = 81
+        assert_eq!(net.parameters().len(), 81);
+        assert_eq!(net.len(), 4);
+    }
+
+    #[test]
+    fn test_named_modules() {
+        let net = Sequential::new()
+            .dense(2, 4)
+            .push_named("norm", LayerNorm::new(4))
+            .tanh();
+
+        let names: Vec<(String, String)> = net
+            .named_modules()
+            .iter()
+            .map(|(name, module)| (name.to_string(), module.name()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("0".to_string(), "Dense(2, 4, Linear)".to_string()),
+                ("norm".to_string(), "LayerNorm(4)".to_string()),
+                ("2".to_string(), "Tanh".to_string()),
+            ]
+        );
+        assert_eq!(net.get("norm").unwrap().parameters().len(), 8);
+
+        // Positions already taken by a name are skipped
+        let net = Sequential::new().push_named("1", Flatten).tanh().relu();
+        let names: Vec<&str> = net.named_modules().iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_feed_forward() {
+        let mut net = Sequential::new()
+            .dense(3, 4)
+            .batch_norm(4)
+            .relu()
+            .dropout(0.5)
+            .dense(4, 2)
+            .sigmoid();
+        let xs: Vec<Vec<RcScalar>> = (0..3)
+            .map(|i| {
+                (0..3)
+                    .map(|j| RcScalar::new(Scalar::new((i * j) as f32)))
+                    .collect()
+            })
+            .collect();
+
+        let outputs = net.feed_foward_batch(xs.clone());
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs[0].len(), 2);
+
+        net.set_training(false);
+        let output = net.feed_foward(xs[0].clone());
+        assert_eq!(output.len(), 2);
+        assert!(output
+            .iter()
+            .all(|y| y.0.borrow().data > 0.0 && y.0.borrow().data < 1.0));
+    }
+
+    #[test]
+    fn test_conv1d_into_dense() {
+        // 2 channels of length 8 -> 4 channels of length 8 -> 4 channels of length 4 -> 1
+        let net = Sequential::new()
+            .push(Conv1d::new(2, 4, 3).padding(1))
+            .relu()
+            .push(MaxPool1d::new(4, 2))
+            .flatten()
+            .dense(16, 1);
+        let x: Vec<RcScalar> = (0..16)
+            .map(|i| RcScalar::new(Scalar::new(i as f32 / 16f32)))
+            .collect();
+
+        let output = net.feed_foward(x);
+
+        assert_eq!(output.len(), 1);
+        // 2*4*3 + 4 conv, 16 + 1 dense
+        assert_eq!(net.parameters().len(), 45);
+        // Any length is a whole number of steps for the convolution
+        assert_eq!(net.input_size(), None);
+    }
+
+    #[test]
+    fn test_input_size() {
+        assert_eq!(Sequential::new().input_size(), None);
+        assert_eq!(Sequential::new().tanh().dense(3, 2).input_size(), Some(3));
+        assert_eq!(
+            Sequential::new().batch_norm(4).dense(4, 2).input_size(),
+            Some(4)
+        );
+        assert_eq!(
+            Sequential::new().dropout(0.5).dense(3, 2).input_size(),
+            None
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+
+// The max is only used to shift the logits for numerical stability, so it is taken as a
+// constant: softmax(x) == softmax(x - c) for any c, hence no gradient needs to flow through it.
+fn shift_by_max(logits: &[RcScalar]) -> Vec<RcScalar> {
+    let max = logits
+        .iter()
+        .map(|x| x.0.borrow().data)
+        .fold(f32::NEG_INFINITY, f32::max);
+    logits.iter().map(|x| RcScalar::clone(x) + -max).collect()
+}
+
+pub fn softmax(logits: &[RcScalar]) -> Vec<RcScalar> {
+    let exps: Vec<RcScalar> = shift_by_max(logits).iter().map(|x| x.exp()).collect();
+    let inv_sum = exps
+        .iter()
+        .fold(RcScalar::new(Scalar::new(0f32)), |acc, x| {
+            acc + RcScalar::clone(x)
+        })
+        .powf(-1f32);
+    exps.into_iter()
+        .map(|x| x * RcScalar::clone(&inv_sum))
+        .collect()
+}
+
+/// `ln(softmax(x))`, computed as `x - max - ln(sum(exp(x - max)))` so it never takes `ln(0)`.
+pub fn log_softmax(logits: &[RcScalar]) -> Vec<RcScalar> {
+    let shifted = shift_by_max(logits);
+    let log_sum_exp = shifted
+        .iter()
+        .fold(RcScalar::new(Scalar::new(0f32)), |acc, x| acc + x.exp())
+        .ln();
+    shifted
+        .into_iter()
+        .map(|x| x - RcScalar::clone(&log_sum_exp))
+        .collect()
+}
+
+pub struct Softmax;
+
+impl Module for Softmax {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        softmax(&input)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        "Softmax".to_string()
+    }
+}
+
+pub struct LogSoftmax;
+
+impl Module for LogSoftmax {
+    fn feed_foward(&self, input: Vec<RcScalar>) -> Vec<RcScalar> {
+        log_softmax(&input)
+    }
+
+    fn parameters(&self) -> Vec<RcScalar> {
+        Vec::new()
+    }
+
+    fn name(&self) -> String {
+        "LogSoftmax".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_softmax() {
+        // Large logits would overflow exp() without the shift
+        let logits: Vec<RcScalar> = vec![
+            RcScalar::new(Scalar::new(1000f32)),
+            RcScalar::new(Scalar::new(1001f32)),
+            RcScalar::new(Scalar::new(1002f32)),
+        ];
+
+        let probs: Vec<f32> = softmax(&logits).iter().map(|p| p.0.borrow().data).collect();
+        let log_probs: Vec<f32> = log_softmax(&logits)
+            .iter()
+            .map(|p| p.0.borrow().data)
+            .collect();
+
+        let expected = [0.09003057f32, 0.24472847, 0.66524096];
+        for i in 0..3 {
+            assert!((probs[i] - expected[i]).abs() < 1e-6);
+            assert!((log_probs[i] - expected[i].ln()).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_log_softmax_backward() {
+        let logits: Vec<RcScalar> = vec![
+            RcScalar::new(Scalar::new(0.5f32)),
+            RcScalar::new(Scalar::new(-1f32)),
+            RcScalar::new(Scalar::new(2f32)),
+        ];
+
+        let probs: Vec<f32> = softmax(&logits).iter().map(|p| p.0.borrow().data).collect();
+        log_softmax(&logits)[1].backwards();
+
+        // d log_softmax(x)_k / d x_i = [i == k] - softmax(x)_i
+        for (i, logit) in logits.iter().enumerate() {
+            let expected = if i == 1 { 1f32 } else { 0f32 } - probs[i];
+            assert!((logit.0.borrow().grad - expected).abs() < 1e-6);
+        }
+    }
+}
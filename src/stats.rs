@@ -0,0 +1,172 @@
+use crate::module::Module;
+use crate::regularization::weights;
+use crate::scalar::RcScalar;
+use std::collections::BTreeMap;
+
+/// Summary statistics of a set of values, e.g. a layer's weights or gradients.
+///
+/// NaN & infinite values are counted but left out of `mean`, `std`, `min` & `max`, which
+/// are NaN when no value is finite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TensorStats {
+    pub count: usize,
+    pub mean: f32,
+    /// Population standard deviation.
+    pub std: f32,
+    pub min: f32,
+    pub max: f32,
+    /// Fraction of the values that are exactly zero.
+    pub zeros: f32,
+    pub nan: usize,
+    pub inf: usize,
+}
+
+impl TensorStats {
+    pub fn new(values: &[f32]) -> Self {
+        let finite: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        let n = finite.len() as f32;
+        let mean = finite.iter().sum::<f32>() / n;
+        let variance = finite.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / n;
+        let (min, max) = if finite.is_empty() {
+            (f32::NAN, f32::NAN)
+        } else {
+            finite
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| {
+                    (lo.min(v), hi.max(v))
+                })
+        };
+        TensorStats {
+            count: values.len(),
+            mean,
+            std: variance.sqrt(),
+            min,
+            max,
+            zeros: values.iter().filter(|&&v| v == 0f32).count() as f32 / values.len() as f32,
+            nan: values.iter().filter(|v| v.is_nan()).count(),
+            inf: values.iter().filter(|v| v.is_infinite()).count(),
+        }
+    }
+
+    /// The statistics by name: `mean`, `std`, `min`, `max`, `zeros`, `nan` & `inf`.
+    pub fn metrics(&self) -> [(&'static str, f32); 7] {
+        [
+            ("mean", self.mean),
+            ("std", self.std),
+            ("min", self.min),
+            ("max", self.max),
+            ("zeros", self.zeros),
+            ("nan", self.nan as f32),
+            ("inf", self.inf as f32),
+        ]
+    }
+}
+
+/// Statistics of one module's parameters & their current gradients, see `Model::layer_stats`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerStats {
+    pub name: String,
+    /// Parameters that are not biases.
+    pub weights: TensorStats,
+    /// `None` for modules without biases.
+    pub biases: Option<TensorStats>,
+    /// Gradients of all the parameters.
+    pub grads: TensorStats,
+}
+
+impl LayerStats {
+    /// `None` for modules without parameters.
+    pub fn new(name: &str, module: &dyn Module) -> Option<Self> {
+        let parameters = module.parameters();
+        if parameters.is_empty() {
+            return None;
+        }
+        let data = |parameters: &[RcScalar]| -> Vec<f32> {
+            parameters.iter().map(|p| p.0.borrow().data).collect()
+        };
+        let biases = module.biases();
+        let grads: Vec<f32> = parameters.iter().map(|p| p.0.borrow().grad).collect();
+        Some(LayerStats {
+            name: name.to_string(),
+            weights: TensorStats::new(&data(&weights(module))),
+            biases: if biases.is_empty() {
+                None
+            } else {
+                Some(TensorStats::new(&data(&biases)))
+            },
+            grads: TensorStats::new(&grads),
+        })
+    }
+
+    /// Every statistic as a metric named `<module>/<weights|biases|grads>/<statistic>`, e.g.
+    /// `0/grads/std`, as written to the metrics log.
+    pub fn metrics(&self) -> BTreeMap<String, f32> {
+        let mut tensors = vec![("weights", &self.weights)];
+        if let Some(biases) = self.biases.as_ref() {
+            tensors.push(("biases", biases));
+        }
+        tensors.push(("grads", &self.grads));
+
+        let mut metrics = BTreeMap::new();
+        for (tensor, stats) in tensors {
+            for (stat, value) in stats.metrics() {
+                metrics.insert(format!("{}/{}/{}", self.name, tensor, stat), value);
+            }
+        }
+        metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::scalar::{RcScalar, Scalar};
+    use crate::sequential::Sequential;
+
+    #[test]
+    fn test_tensor_stats() {
+        let stats = TensorStats::new(&[0.0, 2.0, f32::NAN, -2.0, f32::INFINITY, 0.0]);
+        assert_eq!(stats.count, 6);
+        assert_eq!(stats.mean, 0.0);
+        assert_eq!(stats.std, 2f32.sqrt());
+        assert_eq!((stats.min, stats.max), (-2.0, 2.0));
+        assert_eq!(stats.zeros, 2.0 / 6.0);
+        assert_eq!((stats.nan, stats.inf), (1, 1));
+
+        let stats = TensorStats::new(&[f32::NAN]);
+        assert!(stats.mean.is_nan() && stats.min.is_nan());
+        assert_eq!(stats.nan, 1);
+    }
+
+    #[test]
+    fn test_layer_stats() {
+        let model_a = Model::from(Sequential::new().dense(2, 3).tanh().dense(3, 1));
+        let y = model_a.feed_foward(vec![
+            RcScalar::new(Scalar::new(1f32)),
+            RcScalar::new(Scalar::new(0f32)),
+        ]);
+        y[0].backwards();
+
+        let stats = model_a.layer_stats();
+        assert_eq!(
+            stats.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            vec!["0", "2"]
+        );
+        assert_eq!(stats[0].weights.count, 6);
+        assert_eq!(stats[0].biases.unwrap().count, 3);
+        assert_eq!(stats[0].grads.count, 9);
+        // The second input is zero, so are the gradients of its weights
+        assert!(stats[0].grads.zeros >= 3.0 / 9.0);
+        // The output layer's bias gets a gradient of exactly 1
+        assert!(stats[1].grads.max >= 1.0);
+
+        let metrics = stats[1].metrics();
+        assert_eq!(metrics.len(), 21);
+        assert_eq!(
+            metrics["2/biases/mean"],
+            model_a.biases()[3].0.borrow().data
+        );
+        assert_eq!(metrics["2/grads/nan"], 0.0);
+    }
+}
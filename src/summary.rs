@@ -0,0 +1,273 @@
+use crate::activation::Activation;
+use crate::module::Module;
+use crate::scalar::{RcScalar, Scalar};
+use std::collections::HashSet;
+use std::fmt;
+use std::mem::size_of;
+
+/// One row of a `Summary`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerSummary {
+    pub name: String,
+    /// `Module::name`, e.g. `Dense(3, 4, Tanh)`.
+    pub kind: String,
+    pub input_size: usize,
+    pub output_size: usize,
+    pub activation: Option<Activation>,
+    pub trainable: usize,
+    pub frozen: usize,
+    /// Scalars the module adds to the graph for one sample.
+    pub nodes: usize,
+    /// `prev` links of those scalars.
+    pub edges: usize,
+}
+
+impl LayerSummary {
+    pub fn parameters(&self) -> usize {
+        self.trainable + self.frozen
+    }
+
+    /// Approximate heap held by the module's graph nodes until the graph is dropped.
+    pub fn graph_bytes(&self) -> usize {
+        self.nodes * NODE_BYTES + self.edges * size_of::<RcScalar>()
+    }
+}
+
+/// Per-module shapes, parameter counts & graph size of a model, see `Model::summary`.
+///
+/// Every operation allocates an `RcScalar`, so the graph of a forward pass, not the
+/// parameters, is usually what limits the batch size: `Display` reports both.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub layers: Vec<LayerSummary>,
+}
+
+// Each node is an `Rc` allocation: strong & weak counts followed by the `RefCell<Scalar>`
+const NODE_BYTES: usize = 2 * size_of::<usize>() + size_of::<std::cell::RefCell<Scalar>>();
+
+impl Summary {
+    /// Feeds one sample of zeros through `modules` in order, recording what each one does to it.
+    /// The modules run in the mode they are in, see `Model::summary`.
+    pub fn new<'a, I>(modules: I, input_size: usize) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a dyn Module)>,
+    {
+        let mut x: Vec<RcScalar> = (0..input_size)
+            .map(|_| RcScalar::new(Scalar::new(0f32)))
+            .collect();
+        let mut known: HashSet<usize> = x.iter().map(|v| v.0.borrow().uid).collect();
+        let mut layers = Vec::new();
+        for (name, module) in modules {
+            let parameters = module.parameters();
+            known.extend(parameters.iter().map(|p| p.0.borrow().uid));
+            let trainable = parameters.iter().filter(|p| p.requires_grad()).count();
+            let input_size = x.len();
+            x = module.feed_foward(x);
+            let (nodes, edges) = new_nodes(&x, &mut known);
+            layers.push(LayerSummary {
+                name: name.to_string(),
+                kind: module.name(),
+                input_size,
+                output_size: x.len(),
+                activation: module.activation(),
+                trainable,
+                frozen: parameters.len() - trainable,
+                nodes,
+                edges,
+            });
+        }
+        Summary { layers }
+    }
+
+    pub fn parameters(&self) -> usize {
+        self.layers.iter().map(|l| l.parameters()).sum()
+    }
+
+    pub fn trainable(&self) -> usize {
+        self.layers.iter().map(|l| l.trainable).sum()
+    }
+
+    pub fn frozen(&self) -> usize {
+        self.layers.iter().map(|l| l.frozen).sum()
+    }
+
+    /// Graph nodes created by a forward pass of one sample, parameters excluded.
+    pub fn nodes(&self) -> usize {
+        self.layers.iter().map(|l| l.nodes).sum()
+    }
+
+    /// Approximate bytes of a forward pass's graph, per sample.
+    pub fn graph_bytes(&self) -> usize {
+        self.layers.iter().map(|l| l.graph_bytes()).sum()
+    }
+
+    /// Approximate bytes held by the parameters themselves.
+    pub fn parameter_bytes(&self) -> usize {
+        self.parameters() * NODE_BYTES
+    }
+}
+
+// Counts the nodes reachable from `outputs` that are not in `known` & adds them to it
+fn new_nodes(outputs: &[RcScalar], known: &mut HashSet<usize>) -> (usize, usize) {
+    let mut nodes = 0;
+    let mut edges = 0;
+    let mut to_visit: Vec<RcScalar> = outputs.to_vec();
+    while let Some(node) = to_visit.pop() {
+        let node = node.0.borrow();
+        if !known.insert(node.uid) {
+            continue;
+        }
+        nodes += 1;
+        edges += node.prev.len();
+        to_visit.extend(node.prev.iter().cloned());
+    }
+    (nodes, edges)
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let header = [
+            "Layer",
+            "Type",
+            "Input",
+            "Output",
+            "Activation",
+            "Params",
+            "Frozen",
+            "Nodes",
+            "Memory",
+        ];
+        let rows: Vec<[String; 9]> = self
+            .layers
+            .iter()
+            .map(|l| {
+                [
+                    l.name.clone(),
+                    l.kind.clone(),
+                    format!("[{}]", l.input_size),
+                    format!("[{}]", l.output_size),
+                    l.activation.map_or("-".to_string(), |a| format!("{:?}", a)),
+                    l.parameters().to_string(),
+                    l.frozen.to_string(),
+                    l.nodes.to_string(),
+                    format_bytes(l.graph_bytes()),
+                ]
+            })
+            .collect();
+        let mut widths = header.map(str::len);
+        for row in rows.iter() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let total_width = widths.iter().sum::<usize>() + 2 * (widths.len() - 1);
+
+        let line = |f: &mut fmt::Formatter, cells: &[&str]| -> fmt::Result {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(widths.iter())
+                .enumerate()
+                // Names left-aligned, numbers right-aligned
+                .map(|(i, (cell, &width))| match i {
+                    0 | 1 | 4 => format!("{:<width$}", cell, width = width),
+                    _ => format!("{:>width$}", cell, width = width),
+                })
+                .collect();
+            writeln!(f, "{}", padded.join("  ").trim_end())
+        };
+        line(f, &header)?;
+        writeln!(f, "{}", "-".repeat(total_width))?;
+        for row in rows.iter() {
+            line(f, &row.iter().map(String::as_str).collect::<Vec<_>>())?;
+        }
+        writeln!(f, "{}", "-".repeat(total_width))?;
+        writeln!(
+            f,
+            "Parameters: {} ({} trainable, {} frozen), ~{}",
+            self.parameters(),
+            self.trainable(),
+            self.frozen(),
+            format_bytes(self.parameter_bytes())
+        )?;
+        write!(
+            f,
+            "Graph per sample: {} nodes, ~{}",
+            self.nodes(),
+            format_bytes(self.graph_bytes())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Model;
+    use crate::sequential::Sequential;
+
+    #[test]
+    fn test_summary() {
+        let mut model_a = Model::from(Sequential::new().dense(2, 3).tanh().dense(3, 1));
+        model_a.param_group("0").unwrap().freeze();
+        let summary = model_a.summary(2);
+
+        let rows: Vec<_> = summary
+            .layers
+            .iter()
+            .map(|l| {
+                (
+                    l.name.as_str(),
+                    l.input_size,
+                    l.output_size,
+                    l.trainable,
+                    l.frozen,
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![("0", 2, 3, 0, 9), ("1", 3, 3, 0, 0), ("2", 3, 1, 4, 0)]
+        );
+        assert_eq!(summary.layers[1].activation, Some(Activation::Tanh));
+        assert_eq!(summary.parameters(), 13);
+
+        // A linear neuron of n inputs: n products, n sums onto the bias
+        assert_eq!(summary.layers[0].nodes, 3 * 4);
+        assert_eq!(summary.layers[0].edges, 3 * 4 * 2);
+        assert_eq!(summary.layers[1].nodes, 3);
+        assert_eq!(summary.layers[2].nodes, 6);
+        assert_eq!(summary.nodes(), 21);
+
+        let table = summary.to_string();
+        assert!(table.starts_with("Layer  Type"));
+        assert!(table.contains("Parameters: 13 (4 trainable, 9 frozen)"));
+        assert!(table.contains("Graph per sample: 21 nodes"));
+    }
+
+    #[test]
+    fn test_summary_in_training_mode() {
+        let mut model_a = Model::from(
+            Sequential::new()
+                .dense(2, 4)
+                .batch_norm(4)
+                .dropout(0.5)
+                .dense(4, 1),
+        );
+
+        // Dropout is the identity & batch norm uses its running statistics
+        let summary = model_a.summary(2);
+        assert_eq!(summary.layers[2].nodes, 0);
+        assert_eq!(model_a.summary(2), summary);
+        assert!(model_a.is_training());
+        model_a.eval();
+        assert_eq!(model_a.summary(2), summary);
+        assert!(!model_a.is_training());
+    }
+}
@@ -0,0 +1,434 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// CRC-32C (Castagnoli), the checksum of TFRecord framing.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            // 0x82F63B78 is the reversed Castagnoli polynomial
+            crc = (crc >> 1) ^ (0x82F6_3B78 & (!(crc & 1)).wrapping_add(1));
+        }
+    }
+    !crc
+}
+
+/// TFRecord stores CRCs rotated & offset, so that CRCs of data containing CRCs stay well-mixed.
+fn masked_crc32c(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    crc.rotate_right(15).wrapping_add(0xA282_EAD8)
+}
+
+/// Frames `data` as a TFRecord: length, CRC of the length, data, CRC of the data.
+pub fn write_record<W: Write>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    let length = (data.len() as u64).to_le_bytes();
+    let mut record = Vec::with_capacity(data.len() + 16);
+    record.extend_from_slice(&length);
+    record.extend_from_slice(&masked_crc32c(&length).to_le_bytes());
+    record.extend_from_slice(data);
+    record.extend_from_slice(&masked_crc32c(data).to_le_bytes());
+    writer.write_all(&record)
+}
+
+/// Reads back one TFRecord, checking both CRCs. `None` at the end of the input.
+pub fn read_record<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let corrupt =
+        |what: &str| io::Error::new(io::ErrorKind::InvalidData, format!("{} CRC mismatch", what));
+    let mut length = [0u8; 8];
+    match reader.read_exact(&mut length) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let mut crc = [0u8; 4];
+    reader.read_exact(&mut crc)?;
+    if u32::from_le_bytes(crc) != masked_crc32c(&length) {
+        return Err(corrupt("length"));
+    }
+    let mut data = vec![0u8; u64::from_le_bytes(length) as usize];
+    reader.read_exact(&mut data)?;
+    reader.read_exact(&mut crc)?;
+    if u32::from_le_bytes(crc) != masked_crc32c(&data) {
+        return Err(corrupt("data"));
+    }
+    Ok(Some(data))
+}
+
+/// Just enough of the protobuf wire format for `Event` & `Summary` messages.
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type) as u64);
+    }
+
+    fn int64(mut self, field: u32, value: i64) -> Self {
+        self.key(field, 0);
+        self.varint(value as u64);
+        self
+    }
+
+    fn double(mut self, field: u32, value: f64) -> Self {
+        self.key(field, 1);
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn float(mut self, field: u32, value: f32) -> Self {
+        self.key(field, 5);
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+        self.key(field, 2);
+        self.varint(value.len() as u64);
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    fn packed_doubles(self, field: u32, values: &[f64]) -> Self {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.bytes(field, &bytes)
+    }
+}
+
+/// Distribution of values in the layout of TensorBoard's `HistogramProto`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    pub num: f64,
+    pub sum: f64,
+    pub sum_squares: f64,
+    /// Right edge of each bucket, the first one starting at `min`.
+    pub bucket_limit: Vec<f64>,
+    pub bucket: Vec<f64>,
+}
+
+impl Histogram {
+    /// `buckets` equal-width buckets between the smallest & largest finite values.
+    /// Non-finite values are left out.
+    pub fn new(values: &[f32], buckets: usize) -> Self {
+        assert!(buckets > 0);
+        let values: Vec<f64> = values
+            .iter()
+            .filter(|v| v.is_finite())
+            .map(|&v| v as f64)
+            .collect();
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mut histogram = Histogram {
+            min: if values.is_empty() { 0f64 } else { min },
+            max: if values.is_empty() { 0f64 } else { max },
+            num: values.len() as f64,
+            sum: values.iter().sum(),
+            sum_squares: values.iter().map(|v| v * v).sum(),
+            bucket_limit: Vec::new(),
+            bucket: Vec::new(),
+        };
+        if values.is_empty() {
+            return histogram;
+        }
+        if min == max {
+            histogram.bucket_limit = vec![max];
+            histogram.bucket = vec![values.len() as f64];
+            return histogram;
+        }
+
+        let width = (max - min) / buckets as f64;
+        histogram.bucket_limit = (1..=buckets).map(|i| min + width * i as f64).collect();
+        histogram.bucket_limit[buckets - 1] = max;
+        histogram.bucket = vec![0f64; buckets];
+        for v in values {
+            let i = (((v - min) / width) as usize).min(buckets - 1);
+            histogram.bucket[i] += 1f64;
+        }
+        histogram
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        Message::default()
+            .double(1, self.min)
+            .double(2, self.max)
+            .double(3, self.num)
+            .double(4, self.sum)
+            .double(5, self.sum_squares)
+            .packed_doubles(6, &self.bucket_limit)
+            .packed_doubles(7, &self.bucket)
+            .0
+    }
+}
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0f64, |d| d.as_secs_f64())
+}
+
+/// Writes scalars & histograms to a TensorBoard event file, see `Trainer::tensorboard`.
+///
+/// ```no_run
+/// # use neural_network_from_scratch::tensorboard::SummaryWriter;
+/// let mut writer = SummaryWriter::new("runs/baseline")?;
+/// writer.add_scalar("loss", 0.5, 1)?;
+/// writer.add_histogram("weights", &[0.1, -0.2, 0.3], 1)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Then `tensorboard --logdir runs`. Every event is written with a single write & nothing is
+/// buffered, so the file is readable while training runs.
+pub struct SummaryWriter {
+    path: PathBuf,
+    file: File,
+    buckets: usize,
+}
+
+impl SummaryWriter {
+    /// Creates `log_dir` if needed & a new event file in it, never one that already exists:
+    /// writers created together, e.g. for training & validation, each get their own.
+    pub fn new<P: AsRef<Path>>(log_dir: P) -> io::Result<Self> {
+        static WRITERS: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(log_dir.as_ref())?;
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+        let (path, file) = loop {
+            let name = format!(
+                "events.out.tfevents.{}.{}.{}.{}",
+                now() as u64,
+                host,
+                std::process::id(),
+                WRITERS.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = log_dir.as_ref().join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => break (path, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        let mut writer = SummaryWriter {
+            file,
+            path,
+            buckets: 30,
+        };
+        writer.write_event(0, |event| event.bytes(3, b"brain.Event:2"))?;
+        Ok(writer)
+    }
+
+    /// Number of histogram buckets, 30 by default.
+    pub fn buckets(mut self, buckets: usize) -> Self {
+        assert!(buckets > 0);
+        self.buckets = buckets;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write_event<F: FnOnce(Message) -> Message>(&mut self, step: i64, what: F) -> io::Result<()> {
+        let event = what(Message::default().double(1, now()).int64(2, step));
+        write_record(&mut self.file, &event.0)
+    }
+
+    fn write_summary(&mut self, step: usize, value: Message) -> io::Result<()> {
+        let summary = Message::default().bytes(1, &value.0);
+        self.write_event(step as i64, |event| event.bytes(5, &summary.0))
+    }
+
+    pub fn add_scalar(&mut self, tag: &str, value: f32, step: usize) -> io::Result<()> {
+        let value = Message::default().bytes(1, tag.as_bytes()).float(2, value);
+        self.write_summary(step, value)
+    }
+
+    pub fn add_histogram(&mut self, tag: &str, values: &[f32], step: usize) -> io::Result<()> {
+        let histogram = Histogram::new(values, self.buckets);
+        let value = Message::default()
+            .bytes(1, tag.as_bytes())
+            .bytes(5, &histogram.encode());
+        self.write_summary(step, value)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A decoded protobuf field: varints & fixed-width values as raw integers.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) enum Field {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(Vec<u8>),
+        Fixed32(u32),
+    }
+
+    fn varint(data: &[u8], i: &mut usize) -> u64 {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = data[*i];
+            *i += 1;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte < 0x80 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    pub(crate) fn decode(data: &[u8]) -> Vec<(u32, Field)> {
+        let mut fields = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let key = varint(data, &mut i);
+            let field = match key & 7 {
+                0 => Field::Varint(varint(data, &mut i)),
+                1 => {
+                    i += 8;
+                    Field::Fixed64(u64::from_le_bytes(data[i - 8..i].try_into().unwrap()))
+                }
+                2 => {
+                    let len = varint(data, &mut i) as usize;
+                    i += len;
+                    Field::Bytes(data[i - len..i].to_vec())
+                }
+                5 => {
+                    i += 4;
+                    Field::Fixed32(u32::from_le_bytes(data[i - 4..i].try_into().unwrap()))
+                }
+                wire_type => panic!("unexpected wire type {}", wire_type),
+            };
+            fields.push(((key >> 3) as u32, field));
+        }
+        fields
+    }
+
+    pub(crate) fn get(fields: &[(u32, Field)], number: u32) -> Field {
+        fields
+            .iter()
+            .find(|(n, _)| *n == number)
+            .map(|(_, field)| field.clone())
+            .unwrap_or_else(|| panic!("no field {} in {:?}", number, fields))
+    }
+
+    pub(crate) fn bytes(field: Field) -> Vec<u8> {
+        match field {
+            Field::Bytes(bytes) => bytes,
+            other => panic!("expected bytes, found {:?}", other),
+        }
+    }
+
+    /// A summary event: its step, tag & the fields of its summary value.
+    pub(crate) type Event = (u64, String, Vec<(u32, Field)>);
+
+    /// The summary events of a file, skipping the version event.
+    pub(crate) fn read_events(path: &Path) -> Vec<Event> {
+        let mut file = File::open(path).unwrap();
+        let mut events = Vec::new();
+        while let Some(record) = read_record(&mut file).unwrap() {
+            let event = decode(&record);
+            if event.iter().any(|(n, _)| *n == 3) {
+                continue;
+            }
+            let step = match get(&event, 2) {
+                Field::Varint(step) => step,
+                other => panic!("expected a step, found {:?}", other),
+            };
+            let summary = decode(&bytes(get(&event, 5)));
+            let value = decode(&bytes(get(&summary, 1)));
+            let tag = String::from_utf8(bytes(get(&value, 1))).unwrap();
+            events.push((step, tag, value));
+        }
+        events
+    }
+
+    #[test]
+    fn test_crc32c() {
+        assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+        assert_eq!(crc32c(b""), 0);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut buffer = Vec::new();
+        write_record(&mut buffer, b"hello").unwrap();
+        write_record(&mut buffer, b"").unwrap();
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(read_record(&mut reader).unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(read_record(&mut reader).unwrap(), Some(Vec::new()));
+        assert_eq!(read_record(&mut reader).unwrap(), None);
+
+        buffer[14] ^= 1;
+        assert!(read_record(&mut buffer.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_summary_writer() {
+        let dir = std::env::temp_dir().join("nn_from_scratch_test_summary_writer");
+        let mut writer = SummaryWriter::new(&dir).unwrap().buckets(2);
+        writer.add_scalar("loss", 0.25, 3).unwrap();
+        writer
+            .add_histogram("weights", &[0.0, 1.0, 3.0, 4.0], 3)
+            .unwrap();
+
+        let mut file = File::open(writer.path()).unwrap();
+        let version = decode(&read_record(&mut file).unwrap().unwrap());
+        let events = read_events(writer.path());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(bytes(get(&version, 3)), b"brain.Event:2");
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[0].0, events[0].1.as_str()), (3, "loss"));
+        assert_eq!(get(&events[0].2, 2), Field::Fixed32(0.25f32.to_bits()));
+
+        let histogram = decode(&bytes(get(&events[1].2, 5)));
+        let doubles = |n: u32| -> Vec<f64> {
+            bytes(get(&histogram, n))
+                .chunks(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()))
+                .collect()
+        };
+        assert_eq!(get(&histogram, 2), Field::Fixed64(4f64.to_bits()));
+        assert_eq!(get(&histogram, 5), Field::Fixed64(26f64.to_bits()));
+        assert_eq!(doubles(6), vec![2.0, 4.0]);
+        assert_eq!(doubles(7), vec![2.0, 2.0]);
+    }
+
+    #[test]
+    fn test_writers_in_one_dir() {
+        let dir = std::env::temp_dir().join("nn_from_scratch_test_writers_in_one_dir");
+        let mut train = SummaryWriter::new(&dir).unwrap();
+        let mut val = SummaryWriter::new(&dir).unwrap();
+        train.add_scalar("loss", 0.5, 1).unwrap();
+        val.add_scalar("loss", 0.75, 1).unwrap();
+
+        let (train, val) = (read_events(train.path()), read_events(val.path()));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(get(&train[0].2, 2), Field::Fixed32(0.5f32.to_bits()));
+        assert_eq!(get(&val[0].2, 2), Field::Fixed32(0.75f32.to_bits()));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::new(&[1.0, f32::NAN, 1.0], 10);
+        assert_eq!(histogram.num, 2.0);
+        assert_eq!(histogram.bucket_limit, vec![1.0]);
+        assert_eq!(histogram.bucket, vec![2.0]);
+        assert!(Histogram::new(&[], 10).bucket.is_empty());
+    }
+}
@@ -0,0 +1,995 @@
+use crate::anomaly::detect_anomaly;
+use crate::callback::{Callback, Control, EpochLogs};
+use crate::checkpoint::Checkpoint;
+use crate::clip::{grad_norm, GradClip};
+use crate::logger::{LogInterval, MetricsLogger, Record};
+use crate::loss::Loss;
+use crate::lr_scheduler::LrScheduler;
+use crate::model::Model;
+use crate::optim::Optimizer;
+use crate::regularization::{weights, Regularizer};
+use crate::scalar::{RcScalar, Scalar};
+use crate::tensorboard::SummaryWriter;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// One training example.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub input: Vec<f32>,
+    pub target: Vec<f32>,
+}
+
+/// When the learning-rate scheduler is stepped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleInterval {
+    /// After every batch, observing the batch loss.
+    Step,
+    /// After every epoch, observing the epoch's mean loss.
+    Epoch,
+}
+
+/// Missing fields take their `Default` value when deserialized.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrainerConfig {
+    pub epochs: usize,
+    pub batch_size: usize,
+    /// Seeds the shuffling of every epoch & the dropout masks of every step, so runs are
+    /// reproducible.
+    pub seed: u64,
+    pub grad_clip: Option<GradClip>,
+    pub schedule_interval: ScheduleInterval,
+    /// Where `fit` saves a resumable checkpoint after every epoch.
+    pub checkpoint_path: Option<PathBuf>,
+    /// Adds the `Model::layer_stats` metrics, e.g. `0/grads/std`, to every logged record.
+    pub log_layer_stats: bool,
+    /// Trains in anomaly mode, `fit` failing on the first NaN or infinite value, see
+    /// `anomaly::detect_anomaly`.
+    pub detect_anomaly: bool,
+}
+
+impl Default for TrainerConfig {
+    fn default() -> Self {
+        TrainerConfig {
+            epochs: 10,
+            batch_size: 32,
+            seed: 0,
+            grad_clip: None,
+            schedule_interval: ScheduleInterval::Epoch,
+            checkpoint_path: None,
+            log_layer_stats: false,
+            detect_anomaly: false,
+        }
+    }
+}
+
+/// What one optimizer step saw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepStats {
+    pub loss: f32,
+    /// Global L2 norm of the gradients, before any clipping.
+    pub grad_norm: f32,
+    /// Learning rate the step was taken with.
+    pub lr: f32,
+}
+
+// What a generator drawn from the configured seed is for
+#[derive(Clone, Copy)]
+enum Stream {
+    Shuffle = 1,
+    Dropout = 2,
+}
+
+// Generator of the `index`-th epoch or step for `stream`: every seed, stream & index is a
+// different ChaCha key, so no two of them share their draws
+fn stream_rng(seed: u64, stream: Stream, index: usize) -> StdRng {
+    let mut key = [0u8; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&(stream as u64).to_le_bytes());
+    key[16..24].copy_from_slice(&(index as u64).to_le_bytes());
+    StdRng::from_seed(key)
+}
+
+/// Runs mini-batch training of a model: forward, loss, backward, clipping, optimizer & scheduler steps.
+pub struct Trainer {
+    model: Model,
+    optimizer: Box<dyn Optimizer>,
+    scheduler: Option<Box<dyn LrScheduler>>,
+    penalties: Vec<Regularizer>,
+    callbacks: Vec<Box<dyn Callback>>,
+    loggers: Vec<MetricsLogger>,
+    /// First error of a logger or `on_backward` hook, returned by `fit` at the end of the epoch.
+    error: Option<io::Error>,
+    tensorboard: Option<SummaryWriter>,
+    interrupt: Arc<AtomicBool>,
+    start: Instant,
+    loss: Loss,
+    config: TrainerConfig,
+    epoch: usize,
+    step: usize,
+}
+
+impl Trainer {
+    pub fn new<O: Optimizer + 'static>(
+        model: Model,
+        optimizer: O,
+        loss: Loss,
+        config: TrainerConfig,
+    ) -> Self {
+        assert!(config.batch_size > 0);
+        Trainer {
+            model,
+            optimizer: Box::new(optimizer),
+            scheduler: None,
+            penalties: Vec::new(),
+            callbacks: Vec::new(),
+            loggers: Vec::new(),
+            error: None,
+            tensorboard: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            start: Instant::now(),
+            loss,
+            config,
+            epoch: 0,
+            step: 0,
+        }
+    }
+
+    /// Drives the optimizer's learning rate, starting from the scheduler's initial value.
+    pub fn scheduler<S: LrScheduler + 'static>(mut self, scheduler: S) -> Self {
+        self.optimizer.set_learning_rate(scheduler.lr());
+        self.scheduler = Some(Box::new(scheduler));
+        self
+    }
+
+    /// Adds the regularizer's penalty to the training loss. Reported & evaluation losses leave it out.
+    pub fn penalty(mut self, regularizer: Regularizer) -> Self {
+        self.penalties.push(regularizer);
+        self
+    }
+
+    pub fn callback<C: Callback + 'static>(mut self, callback: C) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Writes step or epoch records, see `MetricsLogger`. Write errors end `fit`.
+    pub fn logger(mut self, logger: MetricsLogger) -> Self {
+        self.loggers.push(logger);
+        self
+    }
+
+    /// Writes TensorBoard summaries after every epoch, the epoch being the step: the epoch's
+    /// metrics, `lr` & `grad_norm`, and histograms of `<module>/weights`, `<module>/biases` &
+    /// `<module>/grads` for every module with parameters. Grads are those of the last batch.
+    pub fn tensorboard(mut self, writer: SummaryWriter) -> Self {
+        self.tensorboard = Some(writer);
+        self
+    }
+
+    fn write_summaries(&mut self, logs: &EpochLogs, stats: &StepStats) -> io::Result<()> {
+        let writer = match self.tensorboard.as_mut() {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let epoch = logs.epoch;
+        for (name, &value) in logs.metrics.iter() {
+            writer.add_scalar(name, value, epoch)?;
+        }
+        writer.add_scalar("lr", stats.lr, epoch)?;
+        writer.add_scalar("grad_norm", stats.grad_norm, epoch)?;
+
+        let data = |parameters: &[RcScalar]| -> Vec<f32> {
+            parameters.iter().map(|p| p.0.borrow().data).collect()
+        };
+        for (name, module) in self.model.layers().named_modules() {
+            let parameters = module.parameters();
+            if parameters.is_empty() {
+                continue;
+            }
+            let biases = module.biases();
+            writer.add_histogram(&format!("{}/weights", name), &data(&weights(module)), epoch)?;
+            if !biases.is_empty() {
+                writer.add_histogram(&format!("{}/biases", name), &data(&biases), epoch)?;
+            }
+            let grads: Vec<f32> = parameters.iter().map(|p| p.0.borrow().grad).collect();
+            writer.add_histogram(&format!("{}/grads", name), &grads, epoch)?;
+        }
+        Ok(())
+    }
+
+    /// A flag that stops training when set, e.g. from a Ctrl-C handler.
+    ///
+    /// Training stops after the current step; the unfinished epoch is left out of the history,
+    /// the logs & the checkpoint, so the last checkpoint still resumes exactly. Callbacks get
+    /// `on_train_end` as usual. `fit` clears the flag when it starts.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupt.load(Ordering::SeqCst)
+    }
+
+    fn log(&mut self, interval: LogInterval, record: Record) {
+        for logger in self.loggers.iter_mut() {
+            if logger.log_interval() != interval {
+                continue;
+            }
+            if let Err(e) = logger.log(&record) {
+                self.error.get_or_insert(e);
+            }
+        }
+    }
+
+    fn layer_metrics(&self) -> BTreeMap<String, f32> {
+        let mut metrics = BTreeMap::new();
+        if self.config.log_layer_stats {
+            for stats in self.model.layer_stats() {
+                metrics.extend(stats.metrics());
+            }
+        }
+        metrics
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    pub fn into_model(self) -> Model {
+        self.model
+    }
+
+    pub fn config(&self) -> &TrainerConfig {
+        &self.config
+    }
+
+    pub fn learning_rate(&self) -> f32 {
+        self.optimizer.learning_rate()
+    }
+
+    /// Number of epochs completed.
+    pub fn epoch(&self) -> usize {
+        self.epoch
+    }
+
+    /// Number of optimizer steps taken.
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Everything needed to pick training up from here, see `resume`.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new(
+            &self.model,
+            Some(self.optimizer.as_ref()),
+            self.scheduler.as_deref(),
+        );
+        checkpoint.epoch = self.epoch;
+        checkpoint.step = self.step;
+        checkpoint.seed = self.config.seed;
+        checkpoint.callbacks = self
+            .callbacks
+            .iter()
+            .map(|callback| callback.state())
+            .collect();
+        checkpoint
+    }
+
+    /// Restores a checkpoint taken from a trainer set up the same way, callbacks included, after
+    /// which training continues exactly as the original run would have.
+    pub fn resume(&mut self, checkpoint: &Checkpoint) -> io::Result<()> {
+        if checkpoint.callbacks.len() != self.callbacks.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "checkpoint has the state of {} callbacks, the trainer has {}",
+                    checkpoint.callbacks.len(),
+                    self.callbacks.len()
+                ),
+            ));
+        }
+        let scheduler: Option<&mut dyn LrScheduler> = match self.scheduler.as_mut() {
+            Some(scheduler) => Some(scheduler.as_mut()),
+            None => None,
+        };
+        checkpoint.restore(&self.model, Some(self.optimizer.as_mut()), scheduler)?;
+        self.epoch = checkpoint.epoch;
+        self.step = checkpoint.step;
+        self.config.seed = checkpoint.seed;
+        for (callback, state) in self.callbacks.iter_mut().zip(checkpoint.callbacks.iter()) {
+            callback.load_state(state)?;
+        }
+        Ok(())
+    }
+
+    fn batch_loss(&self, batch: &[&Sample]) -> RcScalar {
+        let inputs = batch
+            .iter()
+            .map(|sample| RcScalar::vec_from(&sample.input))
+            .collect();
+        let outputs = self.model.feed_foward_batch(inputs);
+        let loss = outputs
+            .iter()
+            .zip(batch.iter())
+            .fold(RcScalar::new(Scalar::new(0f32)), |acc, (output, sample)| {
+                acc + self.loss.compute(output, &sample.target)
+            });
+        loss * (1f32 / batch.len() as f32)
+    }
+
+    /// One optimizer step on the mean loss of `batch`.
+    pub fn train_step(&mut self, batch: &[&Sample]) -> StepStats {
+        let seed = stream_rng(self.config.seed, Stream::Dropout, self.step).gen();
+        self.model.seed(seed);
+        let loss = self.batch_loss(batch);
+        let objective = self
+            .penalties
+            .iter()
+            .fold(RcScalar::clone(&loss), |acc, regularizer| {
+                acc + regularizer.loss()
+            });
+        self.optimizer.zero_grad();
+        objective.backwards();
+        for callback in self.callbacks.iter_mut() {
+            if let Err(e) = callback.on_backward(&self.model) {
+                self.error.get_or_insert(e);
+            }
+        }
+        // Taken before clipping & the update, like `grad_norm`
+        let metrics = self.layer_metrics();
+        let grad_norm = match self.config.grad_clip {
+            Some(clip) => clip.apply(self.optimizer.parameters()),
+            None => grad_norm(self.optimizer.parameters()),
+        };
+        let lr = self.optimizer.learning_rate();
+        self.optimizer.step();
+        self.step += 1;
+
+        let loss = loss.0.borrow().data;
+        if self.config.schedule_interval == ScheduleInterval::Step {
+            if let Some(scheduler) = self.scheduler.as_mut() {
+                scheduler.observe(loss);
+                scheduler.step(self.optimizer.as_mut());
+            }
+        }
+        self.log(
+            LogInterval::Step,
+            Record {
+                step: self.step,
+                epoch: self.epoch + 1,
+                loss,
+                lr,
+                grad_norm,
+                wall_time: self.start.elapsed().as_secs_f64(),
+                metrics,
+            },
+        );
+        StepStats {
+            loss,
+            grad_norm,
+            lr,
+        }
+    }
+
+    /// One pass over `data` in a shuffled order, returning the mean training loss, or NaN if
+    /// the epoch was interrupted. Fails on the same data as `fit`.
+    pub fn train_epoch(&mut self, data: &[Sample]) -> io::Result<f32> {
+        self.check_data(data)?;
+        Ok(self.run_epoch(data).map_or(f32::NAN, |stats| stats.loss))
+    }
+
+    /// Fails on empty `data`, an input of the wrong width or a target the loss cannot take, e.g.
+    /// a class index beyond the model's outputs, before training on any of it.
+    fn check_data(&self, data: &[Sample]) -> io::Result<()> {
+        let first = data
+            .first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "there are no samples"))?;
+        // Only a model that takes any width sees a sample before it is checked
+        let probe = match self.model.input_size() {
+            Some(nin) => {
+                if let Some(i) = data.iter().position(|sample| sample.input.len() != nin) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "sample {} has {} inputs, the model takes {}",
+                            i + 1,
+                            data[i].input.len(),
+                            nin
+                        ),
+                    ));
+                }
+                vec![0f32; nin]
+            }
+            None => first.input.clone(),
+        };
+        let nout = self.model.predict(&probe).len();
+        match data
+            .iter()
+            .position(|sample| !self.loss.fits(&sample.target, nout))
+        {
+            Some(i) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "sample {} has target {:?}, which does not fit {} outputs with {:?} loss",
+                    i + 1,
+                    data[i].target,
+                    nout,
+                    self.loss
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The epoch's mean loss & grad norm and its last learning rate, or `None` if it was
+    /// interrupted, in which case it does not count.
+    fn run_epoch(&mut self, data: &[Sample]) -> Option<StepStats> {
+        self.model.train();
+        let mut order: Vec<&Sample> = data.iter().collect();
+        let mut rng = stream_rng(self.config.seed, Stream::Shuffle, self.epoch);
+        order.shuffle(&mut rng);
+
+        let mut total = 0f32;
+        let mut total_norm = 0f32;
+        let mut lr = self.learning_rate();
+        let mut batches: Vec<&[&Sample]> = order.chunks(self.config.batch_size).collect();
+        // A lone last sample joins the batch before it, batch statistics need two
+        if batches.len() > 1 && batches[batches.len() - 1].len() == 1 {
+            batches.pop();
+            let start = order.len() - batches.pop().unwrap().len() - 1;
+            batches.push(&order[start..]);
+        }
+        let num_batches = batches.len();
+        for batch in batches {
+            if self.interrupted() {
+                return None;
+            }
+            let stats = self.train_step(batch);
+            total += stats.loss * batch.len() as f32;
+            total_norm += stats.grad_norm;
+            lr = stats.lr;
+        }
+        let mean = total / data.len() as f32;
+        self.epoch += 1;
+
+        if self.config.schedule_interval == ScheduleInterval::Epoch {
+            if let Some(scheduler) = self.scheduler.as_mut() {
+                scheduler.observe(mean);
+                scheduler.step(self.optimizer.as_mut());
+            }
+        }
+        Some(StepStats {
+            loss: mean,
+            grad_norm: total_norm / num_batches as f32,
+            lr,
+        })
+    }
+
+    /// Trains until `config.epochs` epochs have been completed or a callback stops training,
+    /// returning each epoch's metrics: `loss`, plus `val_loss` when `validation` is given.
+    ///
+    /// Fails before training if either set is empty or has a target the loss cannot take.
+    pub fn fit(
+        &mut self,
+        data: &[Sample],
+        validation: Option<&[Sample]>,
+    ) -> io::Result<Vec<EpochLogs>> {
+        self.check_data(data)?;
+        if let Some(validation) = validation {
+            self.check_data(validation)?;
+        }
+        self.interrupt.store(false, Ordering::SeqCst);
+        self.start = Instant::now();
+        let mut history = Vec::new();
+        while self.epoch < self.config.epochs {
+            let epoch = if self.config.detect_anomaly {
+                detect_anomaly(|| self.run_epoch(data))?
+            } else {
+                self.run_epoch(data)
+            };
+            let stats = match epoch {
+                Some(stats) => stats,
+                None => break,
+            };
+            let mut metrics = BTreeMap::new();
+            metrics.insert("loss".to_string(), stats.loss);
+            if let Some(validation) = validation {
+                metrics.insert("val_loss".to_string(), self.mean_loss(validation));
+            }
+            let logs = EpochLogs {
+                epoch: self.epoch,
+                metrics,
+            };
+            let mut record_metrics = logs.metrics.clone();
+            record_metrics.remove("loss");
+            record_metrics.extend(self.layer_metrics());
+            self.log(
+                LogInterval::Epoch,
+                Record {
+                    step: self.step,
+                    epoch: self.epoch,
+                    loss: stats.loss,
+                    lr: stats.lr,
+                    grad_norm: stats.grad_norm,
+                    wall_time: self.start.elapsed().as_secs_f64(),
+                    metrics: record_metrics,
+                },
+            );
+            if let Some(e) = self.error.take() {
+                return Err(e);
+            }
+            self.write_summaries(&logs, &stats)?;
+
+            let mut control = Control::Continue;
+            for callback in self.callbacks.iter_mut() {
+                if callback.on_epoch_end(&self.model, &logs)? == Control::Stop {
+                    control = Control::Stop;
+                }
+            }
+            history.push(logs);
+            if let Some(path) = &self.config.checkpoint_path {
+                self.checkpoint().save(path)?;
+            }
+            if control == Control::Stop || self.interrupted() {
+                break;
+            }
+        }
+        for callback in self.callbacks.iter_mut() {
+            callback.on_train_end(&self.model)?;
+        }
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => Ok(history),
+        }
+    }
+
+    /// Mean loss over `data` in evaluation mode, without touching any gradients. The model is
+    /// left in the mode it was in. Fails on the same data as `fit`.
+    pub fn evaluate(&mut self, data: &[Sample]) -> io::Result<f32> {
+        self.check_data(data)?;
+        Ok(self.mean_loss(data))
+    }
+
+    fn mean_loss(&mut self, data: &[Sample]) -> f32 {
+        let training = self.model.is_training();
+        self.model.eval();
+        let mut total = 0f32;
+        for batch in data.chunks(self.config.batch_size) {
+            let batch: Vec<&Sample> = batch.iter().collect();
+            total += self.batch_loss(&batch).0.borrow().data * batch.len() as f32;
+        }
+        if training {
+            self.model.train();
+        }
+        total / data.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::callback::{EarlyStopping, ModelCheckpoint};
+    use crate::logger::LogFormat;
+    use crate::lr_scheduler::StepLr;
+    use crate::optim::{Adam, Sgd};
+    use crate::regularization::Penalty;
+    use crate::sequential::Sequential;
+    use crate::tensorboard::tests::{get, read_events, Field};
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    // y = 2x - 1 on [-1, 1]
+    fn line() -> Vec<Sample> {
+        (0..20)
+            .map(|i| {
+                let x = i as f32 / 10f32 - 1f32;
+                Sample {
+                    input: vec![x],
+                    target: vec![2f32 * x - 1f32],
+                }
+            })
+            .collect()
+    }
+
+    // Sgd at 0.1 on the mean squared error
+    fn trainer(model: Model, config: TrainerConfig) -> Trainer {
+        let optimizer = Sgd::new(model.parameters(), 0.1);
+        Trainer::new(model, optimizer, Loss::Mse, config)
+    }
+
+    fn epochs(epochs: usize) -> TrainerConfig {
+        TrainerConfig {
+            epochs,
+            batch_size: 5,
+            ..TrainerConfig::default()
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("nn_from_scratch_test_trainer_{}", name))
+    }
+
+    fn read_records(path: &Path) -> Vec<Record> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_fit() {
+        let model = Model::from(Sequential::new().dense(1, 1));
+        let mut trainer = trainer(model, epochs(40)).scheduler(StepLr::new(0.3, 20, 0.5));
+
+        let history = trainer.fit(&line(), None).unwrap();
+
+        assert_eq!(history.len(), 40);
+        assert_eq!(history[39].epoch, 40);
+        assert_eq!(trainer.step(), 160);
+        assert!((trainer.learning_rate() - 0.075).abs() < 1e-6);
+        assert!(trainer.evaluate(&line()).unwrap() < 1e-4);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let model = Model::from(Sequential::new().dense(1, 2));
+        let optimizer = Sgd::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(
+            model,
+            optimizer,
+            Loss::CrossEntropy,
+            TrainerConfig::default(),
+        );
+        let sample = |class: f32| Sample {
+            input: vec![0.5],
+            target: vec![class],
+        };
+
+        assert!(trainer.train_epoch(&[]).is_err());
+        assert!(trainer.evaluate(&[]).is_err());
+        for class in [2.0, -1.0, 0.5] {
+            let error = trainer
+                .fit(&[sample(0.0), sample(class)], None)
+                .unwrap_err();
+            assert!(error.to_string().starts_with("sample 2 has target"));
+        }
+        assert!(trainer.fit(&[sample(1.0)], Some(&[])).is_err());
+
+        // A sample of the wrong width is not fed to the model, even as the first one
+        let wide = || Sample {
+            input: vec![0.5, 0.5],
+            target: vec![0.0],
+        };
+        let error = trainer.fit(&[sample(0.0), wide()], None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "sample 2 has 2 inputs, the model takes 1"
+        );
+        assert!(trainer.fit(&[wide(), sample(0.0)], None).is_err());
+        assert!(trainer.evaluate(&[wide()]).is_err());
+        assert_eq!(trainer.step(), 0);
+
+        // Evaluating leaves the model in the mode it was in
+        trainer.evaluate(&[sample(1.0)]).unwrap();
+        assert!(trainer.model().is_training());
+        trainer.model.eval();
+        trainer.evaluate(&[sample(1.0)]).unwrap();
+        assert!(!trainer.model().is_training());
+    }
+
+    #[test]
+    fn test_lone_last_sample() {
+        let model = Model::from(Sequential::new().dense(1, 2).batch_norm(2).dense(2, 1));
+        let config = TrainerConfig {
+            batch_size: 19,
+            ..epochs(2)
+        };
+        let mut trainer = trainer(model, config);
+
+        // 20 samples make one batch of 20, not one of 19 & one of 1
+        trainer.fit(&line(), None).unwrap();
+        assert_eq!(trainer.step(), 2);
+    }
+
+    #[test]
+    fn test_penalty() {
+        let model = Model::from(Sequential::new().dense(1, 1));
+        let parameters = model.parameters();
+        let regularizer = Regularizer::new(Penalty::L2(1.0), model.layers());
+        let mut trainer = trainer(model, epochs(20)).penalty(regularizer);
+
+        trainer.fit(&line(), None).unwrap();
+
+        // Without the penalty the weight would reach 2, the bias is not penalized at all
+        let w = parameters[0].0.borrow().data;
+        assert!(w > 0.5 && w < 1.5, "weight {}", w);
+        assert!((parameters[1].0.borrow().data - -1.0).abs() < 0.2);
+    }
+
+    #[test]
+    fn test_early_stopping() {
+        let path = temp_path("early_stopping.json");
+        // Validation targets of a different line: the better the fit, the worse the validation loss
+        let validation: Vec<Sample> = line()
+            .into_iter()
+            .map(|sample| Sample {
+                target: vec![-sample.target[0]],
+                ..sample
+            })
+            .collect();
+        let mut trainer = trainer(Model::from(Sequential::new().dense(1, 1)), epochs(100))
+            .callback(EarlyStopping::new("val_loss", 3))
+            .callback(ModelCheckpoint::new(&path, "val_loss"));
+
+        let history = trainer.fit(&line(), Some(&validation)).unwrap();
+        let best = history
+            .iter()
+            .map(|logs| logs.metrics["val_loss"])
+            .fold(f32::INFINITY, f32::min);
+        let restored = trainer.evaluate(&validation).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(history.len() < 100);
+        assert_eq!(restored, best);
+    }
+
+    #[test]
+    fn test_streams() {
+        let draws = |stream, index| -> Vec<u64> {
+            let mut rng = stream_rng(3, stream, index);
+            (0..4).map(|_| rng.gen()).collect()
+        };
+        assert_eq!(draws(Stream::Dropout, 1), draws(Stream::Dropout, 1));
+        // The shuffle of epoch k & the dropout masks of step k draw different numbers
+        for index in 0..3 {
+            assert_ne!(draws(Stream::Shuffle, index), draws(Stream::Dropout, index));
+            assert_ne!(
+                draws(Stream::Shuffle, index),
+                draws(Stream::Shuffle, index + 1)
+            );
+        }
+        assert_ne!(
+            stream_rng(4, Stream::Shuffle, 0).gen::<u64>(),
+            draws(Stream::Shuffle, 0)[0]
+        );
+    }
+
+    #[test]
+    fn test_resume() {
+        let path = temp_path("resume.json");
+        let data: Vec<Sample> = line()
+            .into_iter()
+            .map(|sample| Sample {
+                target: vec![sample.target[0].tanh()],
+                ..sample
+            })
+            .collect();
+        let trainer_for = |model: Model, epochs: usize, checkpoint_path: Option<PathBuf>| {
+            let optimizer = Adam::new(model.parameters(), 0.05);
+            let config = TrainerConfig {
+                epochs,
+                batch_size: 3,
+                seed: 11,
+                schedule_interval: ScheduleInterval::Step,
+                checkpoint_path,
+                ..TrainerConfig::default()
+            };
+            // Never improving by 10, it stops after the sixth epoch if its count survives resuming
+            Trainer::new(model, optimizer, Loss::Mse, config)
+                .scheduler(StepLr::new(0.05, 10, 0.8))
+                .callback(EarlyStopping::new("loss", 5).min_delta(10.0))
+        };
+        let new_model = || {
+            Model::from(
+                Sequential::new()
+                    .dense(1, 4)
+                    .tanh()
+                    .dropout(0.2)
+                    .dense(4, 1),
+            )
+        };
+        let losses = |history: Vec<EpochLogs>| -> Vec<f32> {
+            history.iter().map(|logs| logs.metrics["loss"]).collect()
+        };
+        let model = new_model();
+        let initial = model.state();
+
+        let mut interrupted = trainer_for(model, 3, Some(path.clone()));
+        let mut curve = losses(interrupted.fit(&data, None).unwrap());
+
+        // A fresh model, optimizer & callback, as after a restart
+        let mut resumed = trainer_for(new_model(), 8, None);
+        resumed.resume(&Checkpoint::load(&path).unwrap()).unwrap();
+        curve.extend(losses(resumed.fit(&data, None).unwrap()));
+        std::fs::remove_file(&path).unwrap();
+
+        let model = new_model();
+        model.load_state(&initial).unwrap();
+        let mut uninterrupted = trainer_for(model, 8, None);
+        let expected = losses(uninterrupted.fit(&data, None).unwrap());
+
+        assert_eq!(expected.len(), 6);
+        assert_eq!(resumed.step(), uninterrupted.step());
+        assert_eq!(curve, expected);
+
+        let mut other = trainer_for(new_model(), 8, None).callback(EarlyStopping::new("loss", 1));
+        assert!(other.resume(&resumed.checkpoint()).is_err());
+    }
+
+    #[test]
+    fn test_logger() {
+        struct Interrupt(Arc<AtomicBool>);
+
+        impl Callback for Interrupt {
+            fn on_epoch_end(&mut self, _model: &Model, logs: &EpochLogs) -> io::Result<Control> {
+                if logs.epoch == 2 {
+                    self.0.store(true, Ordering::SeqCst);
+                }
+                Ok(Control::Continue)
+            }
+        }
+
+        let steps_path = temp_path("steps.jsonl");
+        let epochs_path = temp_path("epochs.csv");
+        let trainer = trainer(Model::from(Sequential::new().dense(1, 1)), epochs(5))
+            .logger(MetricsLogger::new(&steps_path, LogFormat::Jsonl).interval(LogInterval::Step))
+            .logger(MetricsLogger::new(&epochs_path, LogFormat::Csv));
+        let interrupt = trainer.interrupt_flag();
+        let mut trainer = trainer.callback(Interrupt(interrupt));
+
+        let history = trainer.fit(&line(), Some(&line())).unwrap();
+        let steps = read_records(&steps_path);
+        let epochs = std::fs::read_to_string(&epochs_path).unwrap();
+        std::fs::remove_file(&steps_path).unwrap();
+        std::fs::remove_file(&epochs_path).unwrap();
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(steps.len(), 8);
+        assert_eq!((steps[7].step, steps[7].epoch, steps[7].lr), (8, 2, 0.1));
+        let rows: Vec<&str> = epochs.lines().collect();
+        assert_eq!(rows[0], "step,epoch,loss,lr,grad_norm,wall_time,val_loss");
+        assert_eq!(rows.len(), 3);
+        assert!(rows[2].starts_with("8,2,"), "{}", rows[2]);
+
+        // Interrupted before its first step, the epoch does not count
+        trainer.interrupt_flag().store(true, Ordering::SeqCst);
+        assert!(trainer.train_epoch(&line()).unwrap().is_nan());
+        assert_eq!((trainer.epoch(), trainer.step()), (2, 8));
+    }
+
+    #[test]
+    fn test_layer_stats() {
+        // Records the output layer's bias gradient after every backward pass
+        struct BiasGrads(Rc<RefCell<Vec<f32>>>);
+        impl Callback for BiasGrads {
+            fn on_backward(&mut self, model: &Model) -> io::Result<()> {
+                let stats = model.layer_stats();
+                self.0.borrow_mut().push(stats[1].grads.max);
+                Ok(())
+            }
+        }
+
+        let path = temp_path("layer_stats.jsonl");
+        let model = Model::from(Sequential::new().dense(1, 2).tanh().dense(2, 1));
+        let config = TrainerConfig {
+            log_layer_stats: true,
+            ..epochs(2)
+        };
+        let grads = Rc::new(RefCell::new(Vec::new()));
+        let mut trainer = trainer(model, config)
+            .logger(MetricsLogger::new(&path, LogFormat::Jsonl).interval(LogInterval::Step))
+            .callback(BiasGrads(Rc::clone(&grads)));
+
+        trainer.fit(&line(), None).unwrap();
+        let steps = read_records(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(steps.len(), 8);
+        assert_eq!(grads.borrow().len(), 8);
+        let metrics = &steps[0].metrics;
+        assert_eq!(metrics.len(), 2 * 21);
+        assert_eq!(metrics["0/weights/nan"], 0.0);
+        assert_eq!(metrics["2/grads/max"], grads.borrow()[0]);
+    }
+
+    #[test]
+    fn test_detect_anomaly() {
+        let model = Model::from(Sequential::new().dense(1, 1));
+        model.parameters()[0].0.borrow_mut().data = f32::NAN;
+        let config = TrainerConfig {
+            detect_anomaly: true,
+            ..epochs(2)
+        };
+        let mut trainer = trainer(model, config);
+
+        let error = trainer.fit(&line(), None).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            error.to_string().contains("0 (Dense(1, 1, Linear))"),
+            "{}",
+            error
+        );
+        assert_eq!(trainer.step(), 0);
+    }
+
+    #[test]
+    fn test_tensorboard() {
+        let dir = temp_path("tensorboard");
+        let writer = SummaryWriter::new(&dir).unwrap();
+        let path = writer.path().to_path_buf();
+        let model = Model::from(Sequential::new().dense(1, 2).tanh().dense(2, 1));
+        let mut trainer = trainer(model, epochs(2)).tensorboard(writer);
+
+        let history = trainer.fit(&line(), Some(&line())).unwrap();
+        let events = read_events(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let tags: Vec<&str> = events
+            .iter()
+            .filter(|(step, _, _)| *step == 2)
+            .map(|(_, tag, _)| tag.as_str())
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                "loss",
+                "val_loss",
+                "lr",
+                "grad_norm",
+                "0/weights",
+                "0/biases",
+                "0/grads",
+                "2/weights",
+                "2/biases",
+                "2/grads"
+            ]
+        );
+        assert_eq!(
+            get(&events[0].2, 2),
+            Field::Fixed32(history[0].metrics["loss"].to_bits())
+        );
+    }
+
+    #[test]
+    fn test_grad_clip() {
+        let model = Model::from(Sequential::new().dense(1, 1));
+        let parameters = model.parameters();
+        let optimizer = Sgd::new(model.parameters(), 1.0);
+        let config = TrainerConfig {
+            grad_clip: Some(GradClip::Norm(0.1)),
+            ..TrainerConfig::default()
+        };
+        let mut trainer = Trainer::new(model, optimizer, Loss::Mse, config);
+        let before: Vec<f32> = parameters.iter().map(|p| p.0.borrow().data).collect();
+
+        let sample = Sample {
+            input: vec![100f32],
+            target: vec![-100f32],
+        };
+        let stats = trainer.train_step(&[&sample]);
+
+        // The step is bounded by lr * max_norm, however large the raw gradient was
+        let moved = parameters
+            .iter()
+            .zip(before.iter())
+            .map(|(p, b)| (p.0.borrow().data - b).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        assert!(stats.grad_norm > 100f32);
+        assert!((moved - 0.1).abs() < 1e-4);
+    }
+}